@@ -1,16 +1,37 @@
 use std::cmp::PartialEq;
-use crate::event::event::Event;
+use crate::event::event::{EditorEvent, Event, InputEvent};
+use crate::floating_panel::toast_panel::ToastPanel;
+use crate::panel::panel::{Panel, PanelFault};
+use crate::plugin::plugin::PluginContext;
+use crate::plugin::subscription::EventKind;
 use crate::plugin_builtin::mos_editor::mos_editor::MosEditorPlugin;
+use crate::plugin_builtin::mos_log::mos_log::MosLogPlugin;
+use crate::plugin_builtin::mos_messages::mos_messages::MosMessagesPlugin;
+use crate::system::command_registry::CommandRegistry;
+use crate::system::keymap_registry::KeymapRegistry;
 use crate::system::panel_registry::PanelRegistry;
 use crate::system::plugin_registry::PluginRegistry;
 use crate::workspace::workspace::Workspace;
+use ratatui::widgets::Clear;
 use ratatui::Frame;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 use crate::system::lua_manager::LuaManager;
+use crate::system::event_bus;
+use crate::system::logger::{self, LogLevel};
+use crate::system::notifications;
+use crate::system::theme;
+use crate::workspace::layout::{Anchor, Offset};
+use crate::system::panic_guard;
+use crate::system::cli::{self, FileSource, Options};
+use std::path::PathBuf;
 
 #[derive(Eq, Hash, PartialEq, Copy, Clone, Debug)]
 pub struct MosId(Uuid);
 
+// Owner id of the commands and keybindings registered by mos itself
+pub const CORE_ID: &str = "mos";
+
 
 #[derive(PartialEq)]
 pub enum MosState {
@@ -24,6 +45,12 @@ impl MosId {
     }
 }
 
+impl std::fmt::Display for MosId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 pub struct Mos {
     pub state: MosState,
     pub should_quit: bool,
@@ -31,37 +58,50 @@ pub struct Mos {
     pub workspaces: Vec<Workspace>,
     pub panel_registry: PanelRegistry,
     pub plugin_registry: PluginRegistry,
+    pub command_registry: CommandRegistry,
+    pub keymap_registry: KeymapRegistry,
     pub lua_manager: LuaManager,
+    pub tick_rate: Duration, // how often Event::Tick is emitted while a plugin subscribes to it
+    next_tick: Option<Instant>,
+    redraw: bool,
+    theme_generation: u64, // theme::generation() at the last render
+    toasts: ToastPanel,
+    toast_anchor: Anchor,
 }
 
 impl Mos {
-    pub fn new() -> Self {
+    pub fn new(options: &Options) -> Self {
         let mut plugin_registry = PluginRegistry::new();
         let mut panel_registry = PanelRegistry::new();
-        let mut lua_manager = LuaManager::new();
+        let mut command_registry = CommandRegistry::new();
+        let mut keymap_registry = KeymapRegistry::new();
+        let lua_manager = LuaManager::new();
 
         lua_manager.init().unwrap();
-        lua_manager.load_plugin("test.lua").unwrap();
 
-        let mut workspace = Workspace::new();
+        let workspace = Workspace::new();
 
         // Register built-in plugins here, **temporary code**
         plugin_registry.register_plugin(Box::new(MosEditorPlugin::new()));
+        plugin_registry.register_plugin(Box::new(MosLogPlugin::new()));
+        plugin_registry.register_plugin(Box::new(MosMessagesPlugin::new()));
 
-        plugin_registry.enable_plugins(&mut panel_registry);
-
-        let text_editor_kind_id = panel_registry.get_panels_by_plugin(&plugin_registry.get_plugins()[0].id()).first().cloned();
-        if let Some(kind_id) = text_editor_kind_id {
-            let panel_instance = panel_registry.new_panel_instance(kind_id);
-            if let Some(panel) = panel_instance {
-                workspace.add_panel(panel);
-            } else {
-                eprintln!("Failed to create an instance of the Text Editor panel");
-            }
-        } else {
-            eprintln!("Failed to register Text Editor Plugin");
+        let config = options.config.clone().unwrap_or_else(|| PathBuf::from("test.lua"));
+        match lua_manager.load_plugin(&config.to_string_lossy()) {
+            Ok(lua_plugin) => plugin_registry.register_plugin(Box::new(lua_plugin)),
+            Err(e) => notifications::error(CORE_ID, format!("Failed to load lua plugin {}: {}", config.display(), e)),
         }
 
+        Self::register_core_commands(&mut command_registry, &mut keymap_registry);
+
+        plugin_registry.enable_plugins(&mut PluginContext {
+            panel_registry: &mut panel_registry,
+            command_registry: &mut command_registry,
+            keymap_registry: &mut keymap_registry,
+        });
+
+        Self::open_files(options);
+
         Mos {
             state: MosState::Panel,
             should_quit: false,
@@ -69,12 +109,350 @@ impl Mos {
             workspaces: vec![workspace],
             panel_registry,
             plugin_registry,
-            lua_manager
+            command_registry,
+            keymap_registry,
+            lua_manager,
+            tick_rate: options.tick_rate,
+            next_tick: None,
+            redraw: true,
+            theme_generation: theme::generation(),
+            toasts: ToastPanel::new(),
+            toast_anchor: Anchor::BottomRight(Offset::Absolute(0, 0, 1, 1)),
         }
     }
 
+    // Every file gets its own editor tab, opened through commands on the first update so plugins see them too
+    fn open_files(options: &Options) {
+        let command = |name: &str, args: Vec<String>| event_bus::emit(Event::Command(name.to_string(), args));
+
+        if options.files.is_empty() {
+            command("panel.open", vec![String::from("editor_panel")]);
+        }
+
+        for file in &options.files {
+            command("panel.open", vec![String::from("editor_panel")]);
+
+            match &file.source {
+                FileSource::Path(path) => {
+                    let mut args = vec![path.to_string_lossy().to_string()];
+                    args.extend([file.line, file.column].into_iter().flatten().map(|n| n.to_string()));
+                    command("open", args);
+                }
+                FileSource::Stdin => command("set_text", vec![options.stdin.clone().unwrap_or_default()]),
+            }
+
+            if options.read_only {
+                command("read_only", vec![String::from("on")]);
+            }
+        }
+    }
+
+    fn register_core_commands(command_registry: &mut CommandRegistry, keymap_registry: &mut KeymapRegistry) {
+        let commands = [
+            ("quit", "Quit mos"),
+            ("plugin.enable", "Enable a plugin by id"),
+            ("plugin.disable", "Disable a plugin by id, closing its panels"),
+            ("plugin.stats", "Show how many events each plugin was sent and skipped"),
+            ("panel.open", "Open a new panel of the given kind"),
+            ("panel.close", "Close the active panel"),
+            ("panel.next", "Focus the next panel"),
+            ("panel.prev", "Focus the previous panel"),
+            ("workspace.switch", "Switch to a workspace by number, one past the last opens a new one"),
+            ("toast.dismiss", "Dismiss a toast by the id shown in the messages panel, or every toast without one"),
+            ("log.level", "Set which levels are written to mos.log from error to trace, or show it without one"),
+            ("tick_rate", "Set how many milliseconds pass between ticks, or show it without one"),
+            ("theme", "Switch to a theme by name, or list the themes without one"),
+            ("theme.load", "Load a .toml or .json theme file and switch to it"),
+            ("theme.colors", "Override the detected colour support with truecolor, 256 or 16"),
+        ];
+        for (name, description) in commands {
+            command_registry.register_command(CORE_ID.to_string(), name.to_string(), description.to_string());
+        }
+
+        keymap_registry.register_keybinding(CORE_ID.to_string(), vec![String::from("control"), String::from("q")], String::from("quit"), Vec::new());
+        keymap_registry.register_keybinding(CORE_ID.to_string(), vec![String::from("control"), String::from("w")], String::from("panel.close"), Vec::new());
+        keymap_registry.register_keybinding(CORE_ID.to_string(), vec![String::from("alt"), String::from("l")], String::from("panel.next"), Vec::new());
+        keymap_registry.register_keybinding(CORE_ID.to_string(), vec![String::from("alt"), String::from("h")], String::from("panel.prev"), Vec::new());
+        // alt-1 to alt-9 switch workspaces, keys are sorted so the digit comes first
+        for number in 1..=9 {
+            keymap_registry.register_keybinding(CORE_ID.to_string(), vec![number.to_string(), String::from("alt")], String::from("workspace.switch"), vec![number.to_string()]);
+        }
+    }
+
+    // Returns false if the command is not a core command
+    fn handle_core_command(&mut self, name: &str, args: &[String]) -> bool {
+        match name {
+            "quit" => self.should_quit = true,
+            "plugin.enable" => {
+                for plugin_id in args {
+                    match self.enable_plugin(plugin_id) {
+                        Ok(()) => notifications::success(CORE_ID, format!("Enabled plugin {}", plugin_id)),
+                        Err(e) => notifications::error(CORE_ID, e),
+                    }
+                }
+            }
+            "plugin.disable" => {
+                for plugin_id in args {
+                    match self.disable_plugin(plugin_id) {
+                        Ok(()) => notifications::success(CORE_ID, format!("Disabled plugin {}", plugin_id)),
+                        Err(e) => notifications::error(CORE_ID, e),
+                    }
+                }
+            }
+            "plugin.stats" => {
+                let report = self.plugin_registry.stats_report();
+                logger::info(CORE_ID, report.clone());
+                notifications::info(CORE_ID, report);
+            }
+            "panel.open" => {
+                for kind in args {
+                    match self.panel_registry.new_panel_instance(kind.clone()) {
+                        Some(panel) => self.workspaces[self.active_workspace].add_panel(panel),
+                        None => notifications::error(CORE_ID, format!("No panel kind {}", kind)),
+                    }
+                }
+            }
+            "panel.close" => {
+                let workspace = &mut self.workspaces[self.active_workspace];
+                if let Some(panel_id) = workspace.get_active_panel().map(|p| p.id()) {
+                    workspace.close_panel(panel_id);
+                }
+            }
+            "workspace.switch" => match args.first().and_then(|a| a.parse::<usize>().ok()).filter(|n| *n > 0) {
+                Some(number) if number <= self.workspaces.len() => self.switch_workspace(number - 1),
+                Some(number) if number == self.workspaces.len() + 1 => {
+                    self.workspaces.push(Workspace::new());
+                    self.switch_workspace(number - 1);
+                    notifications::info(CORE_ID, format!("Opened workspace {}", number));
+                }
+                Some(number) => notifications::error(CORE_ID, format!("No workspace {}, there are {}", number, self.workspaces.len())),
+                None => notifications::info(CORE_ID, format!("Workspace {} of {}", self.active_workspace + 1, self.workspaces.len())),
+            },
+            "toast.dismiss" => match args.first().map(|id| id.trim_start_matches('#').parse::<u64>()) {
+                Some(Ok(id)) => {
+                    if !self.toasts.dismiss(Some(id)) {
+                        notifications::error(CORE_ID, format!("No toast #{} is showing", id));
+                    }
+                }
+                Some(Err(_)) => notifications::error(CORE_ID, format!("Invalid toast id '{}'", args[0])),
+                None => {
+                    self.toasts.dismiss(None);
+                }
+            },
+            "log.level" => match args.first() {
+                Some(name) => match LogLevel::from_name(name) {
+                    Some(level) => {
+                        logger::set_level(level);
+                        notifications::info(CORE_ID, format!("Writing {} and above to mos.log", level));
+                    }
+                    None => notifications::error(CORE_ID, format!("Unknown log level '{}'", name)),
+                },
+                None => notifications::info(CORE_ID, format!("Writing {} and above to mos.log", logger::level())),
+            },
+            "tick_rate" => match args.first().map(|ms| cli::parse_tick_rate(ms)) {
+                Some(Ok(rate)) => {
+                    self.tick_rate = rate;
+                    self.next_tick = None;
+                    notifications::info(CORE_ID, format!("Tick rate {} ms", rate.as_millis()));
+                }
+                Some(Err(e)) => notifications::error(CORE_ID, e),
+                None => notifications::info(CORE_ID, format!("Tick rate {} ms", self.tick_rate.as_millis())),
+            },
+            "panel.next" => self.workspaces[self.active_workspace].cycle_panels(1),
+            "panel.prev" => self.workspaces[self.active_workspace].cycle_panels(-1),
+            "theme" => match args.first() {
+                Some(name) => match theme::set_current(name) {
+                    Ok(()) => notifications::info(CORE_ID, format!("Theme {}", name)),
+                    Err(e) => notifications::error(CORE_ID, e),
+                },
+                None => notifications::info(CORE_ID, format!("Theme {}, available: {}", theme::current_name(), theme::names().join(", "))),
+            },
+            "theme.colors" => match args.first() {
+                Some(name) => match theme::ColorDepth::from_name(name) {
+                    Some(depth) => theme::set_color_depth(depth),
+                    None => notifications::error(CORE_ID, format!("Unknown colour support {}, expected truecolor, 256 or 16", name)),
+                },
+                None => notifications::info(CORE_ID, format!("Using {} colours", theme::color_depth().name())),
+            },
+            "theme.load" => {
+                for path in args {
+                    let loaded = theme::Theme::load(std::path::Path::new(path)).and_then(|t| {
+                        let name = t.name.clone();
+                        theme::define(t);
+                        theme::set_current(&name).map(|()| name)
+                    });
+                    match loaded {
+                        Ok(name) => notifications::success(CORE_ID, format!("Loaded theme {}", name)),
+                        Err(e) => notifications::error(CORE_ID, e),
+                    }
+                }
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub fn enable_plugin(&mut self, plugin_id: &str) -> Result<(), String> {
+        self.plugin_registry.enable_plugin(plugin_id, &mut PluginContext {
+            panel_registry: &mut self.panel_registry,
+            command_registry: &mut self.command_registry,
+            keymap_registry: &mut self.keymap_registry,
+        })
+    }
+
+    // Unregisters everything the plugin and its dependents registered and closes the live panels of their kinds
+    pub fn disable_plugin(&mut self, plugin_id: &str) -> Result<(), String> {
+        let mut affected = self.plugin_registry.dependents(plugin_id);
+        affected.push(plugin_id.to_string());
+
+        let panel_kinds: Vec<String> = affected.iter()
+            .flat_map(|id| self.panel_registry.get_panels_by_plugin(id))
+            .collect();
+
+        let result = self.plugin_registry.disable_plugin(plugin_id, &mut PluginContext {
+            panel_registry: &mut self.panel_registry,
+            command_registry: &mut self.command_registry,
+            keymap_registry: &mut self.keymap_registry,
+        });
+
+        if !self.plugin_registry.is_enabled(plugin_id) {
+            for workspace in self.workspaces.iter_mut() {
+                workspace.close_panels_by_kind(&panel_kinds);
+            }
+        }
+
+        result.map(|_| ())
+    }
+
+    // Ticks are only emitted while a plugin is subscribed to them, so an idle mos doesn't wake up
+    fn update_tick(&mut self) {
+        if !self.plugin_registry.has_subscribers(EventKind::Tick) {
+            self.next_tick = None;
+            return;
+        }
+
+        let now = Instant::now();
+        match self.next_tick {
+            Some(at) if now >= at => {
+                event_bus::emit(Event::Tick);
+                self.next_tick = Some(now + self.tick_rate);
+            }
+            None => self.next_tick = Some(now + self.tick_rate),
+            _ => {}
+        }
+    }
+
+    // How long the main loop can sleep if no input arrives, None if it can wait for input forever
+    pub fn next_wakeup(&self) -> Option<Duration> {
+        let next = [self.next_tick, self.toasts.next_expiry()]
+            .into_iter()
+            .flatten()
+            .min();
+
+        next.map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn needs_redraw(&self) -> bool {
+        self.redraw || theme::generation() != self.theme_generation || self.toasts.is_dirty() || self.workspaces[self.active_workspace].is_dirty()
+    }
+
+    fn update_toasts(&mut self) {
+        for notification in notifications::take_pending() {
+            self.toasts.push(notification);
+        }
+        self.toasts.expire(Instant::now());
+    }
+
     pub fn update(&mut self) {
+        self.update_tick();
+
+        // Dispatch the events emitted since last update, commands also go to the active panel
+        for event in event_bus::drain() {
+            self.plugin_registry.handle_plugins_events(event.clone());
+
+            if let Event::Command(name, args) = &event
+                && self.handle_core_command(name, args) {
+                continue;
+            }
+
+            if let Event::Command(..) = event
+                && let Some(panel) = self.workspaces[self.active_workspace].get_active_panel_mut()
+                && let Some(fault) = Self::deliver_to_panel(panel, event) {
+                self.handle_panel_fault(fault);
+            }
+        }
+
+        self.handle_plugin_faults();
+        self.update_toasts();
+    }
+
+    // Delivers an event to the panels of every workspace, including floating ones
+    fn broadcast_to_panels(&mut self, event: Event) {
+        let faults: Vec<PanelFault> = self.workspaces.iter_mut()
+            .flat_map(|workspace| workspace.panels_mut())
+            .filter_map(|panel| Self::deliver_to_panel(panel, event.clone()))
+            .collect();
+
+        for fault in faults {
+            self.handle_panel_fault(fault);
+        }
+    }
+
+    fn deliver_to_panel(panel: &mut dyn Panel, event: Event) -> Option<PanelFault> {
+        panic_guard::guard(|| panel.handle_event(event)).err().map(|report| PanelFault {
+            panel_id: panel.id(),
+            kind: panel.kind().to_string(),
+            report,
+        })
+    }
+
+    // Plugins that panicked are disabled, mos keeps running without them
+    fn handle_plugin_faults(&mut self) {
+        for (plugin_id, report) in self.plugin_registry.take_faulted() {
+            logger::error(&plugin_id, format!("Plugin panicked: {} at {}, disabling it\n{}", report.message, report.location, report.backtrace));
+            notifications::error(CORE_ID, format!("Plugin {} crashed and was disabled: {}", plugin_id, report.message));
+
+            if let Err(e) = self.disable_plugin(&plugin_id) {
+                logger::error(CORE_ID, e);
+            }
+        }
+    }
+
+    // A panel that panicked is closed on its own, its plugin keeps running unless its Plugin callbacks panic
+    fn handle_panel_fault(&mut self, fault: PanelFault) {
+        self.redraw = true;
+        logger::error(CORE_ID, format!("Panel {} panicked: {} at {}\n{}", fault.kind, fault.report.message, fault.report.location, fault.report.backtrace));
+
+        // The panel may be in a broken state, so asking for its status is guarded too
+        let status = self.workspaces.iter_mut()
+            .flat_map(|workspace| workspace.panels_mut())
+            .find(|panel| panel.id() == fault.panel_id)
+            .and_then(|panel| panic_guard::guard(|| panel.status()).ok());
+
+        let lost = match status {
+            Some(status) if status.modified => format!(", unsaved changes to {} were discarded", status.file.as_deref().unwrap_or("an unnamed buffer")),
+            _ => String::new(),
+        };
+        if !lost.is_empty() {
+            logger::warn(CORE_ID, format!("Panel {} closed{}", fault.kind, lost));
+        }
+        notifications::error(CORE_ID, format!("Panel {} crashed and was closed: {}{}", fault.kind, fault.report.message, lost));
+
+        for workspace in self.workspaces.iter_mut() {
+            workspace.close_panel(fault.panel_id);
+        }
+    }
+
+    pub fn switch_workspace(&mut self, index: usize) {
+        if index >= self.workspaces.len() || index == self.active_workspace {
+            return;
+        }
 
+        let from = self.active_workspace;
+        self.active_workspace = index;
+        self.redraw = true;
+        event_bus::emit(Event::Editor(EditorEvent::WorkspaceSwitched { from, to: index }));
     }
 
     pub fn handle_terminal_event(&mut self, event: crossterm::event::Event) {
@@ -85,26 +463,71 @@ impl Mos {
         if let Some(ev) = mos_event {
             self.plugin_registry.handle_plugins_events(ev.clone());
 
-            
-            match self.state {
+            // Resize and focus changes concern every panel, not only the active one
+            if matches!(ev, Event::Resize(..) | Event::FocusGained | Event::FocusLost) {
+                self.redraw |= matches!(ev, Event::Resize(..));
+                self.broadcast_to_panels(ev);
+                self.handle_plugin_faults();
+                return;
+            }
+
+            // Global keybindings take precedence over the active panel
+            if let Event::Input(InputEvent::Keyboard(keys)) = &ev
+                && let Some((_, command, args)) = self.keymap_registry.get_binding(keys) {
+                event_bus::emit(Event::Command(command.clone(), args.clone()));
+                return;
+            }
+
+            // The mouse goes to the panel under it, in that panel's coordinates
+            if let Event::Input(InputEvent::Mouse(mouse)) = ev {
+                let floating = matches!(self.state, MosState::Floating);
+                let fault = self.workspaces[self.active_workspace].mouse_target(mouse, floating)
+                    .and_then(|(panel, local)| Self::deliver_to_panel(panel, Event::Input(InputEvent::Mouse(local))));
+                if let Some(fault) = fault {
+                    self.handle_panel_fault(fault);
+                }
+                self.handle_plugin_faults();
+                return;
+            }
+
+            let fault = match self.state {
                 MosState::Panel => {
                     let active_panel = self.workspaces[self.active_workspace].get_active_panel_mut();
-                    if let Some(panel) = active_panel {
-                        panel.handle_event(ev)
-                    }
+                    active_panel.and_then(|panel| Self::deliver_to_panel(panel, ev))
                 },
                 MosState::Floating => {
-                    if let Some(floating) = self.workspaces[self.active_workspace].get_floating() {
-                        floating.panel.handle_event(ev);
-                    }
+                    self.workspaces[self.active_workspace].get_floating()
+                        .as_mut()
+                        .and_then(|floating| Self::deliver_to_panel(floating.panel.as_mut(), ev))
                 }
+            };
+
+            if let Some(fault) = fault {
+                self.handle_panel_fault(fault);
             }
         }
+
+        self.handle_plugin_faults();
     }
 
     pub fn render(&mut self, _frame: &mut Frame) {
         // Render the current workspace and its panels.
         let workspace = &mut self.workspaces[self.active_workspace];
-        workspace.render(_frame);
+        let faults = workspace.render(_frame);
+        workspace.clear_dirty();
+        self.redraw = false;
+        self.theme_generation = theme::generation();
+
+        // Toasts go on top of everything
+        if !self.toasts.is_empty() {
+            let area = self.toast_anchor.area(_frame.size(), Some(self.toasts.size()));
+            _frame.render_widget(Clear, area);
+            self.toasts.render(_frame, area);
+        }
+        self.toasts.clear_dirty();
+
+        for fault in faults {
+            self.handle_panel_fault(fault);
+        }
     }
 }
\ No newline at end of file
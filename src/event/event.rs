@@ -1,20 +1,73 @@
+use crate::app::MosId;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent};
+use std::path::{Path, PathBuf};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum InputEvent {
     Keyboard(Vec<String>), // convert to Vec<String>
     Char(char),
     Mouse(MouseEvent),
+    Paste(String), // a bracketed paste, the whole text at once
+}
+
+// The chars start..end were replaced by text, positions refer to the buffer before the edit batch
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditRange {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+// Semantic events emitted by the editor, so plugins don't have to reimplement editor logic from raw input
+#[derive(Clone, Debug)]
+pub enum EditorEvent {
+    BufferOpened { panel: MosId, path: Option<PathBuf> },
+    BufferChanged { panel: MosId, path: Option<PathBuf>, edits: Vec<EditRange> },
+    BufferSaved { panel: MosId, path: PathBuf },
+    CursorMoved { panel: MosId, path: Option<PathBuf>, line: usize, column: usize },
+    ModeChanged { panel: MosId, mode: String },
+    PanelFocused { panel: MosId, title: String },
+    WorkspaceSwitched { from: usize, to: usize },
+}
+
+impl EditorEvent {
+    // The editor panel an event comes from, tells apart two buffers of the same file
+    pub fn panel(&self) -> Option<MosId> {
+        match self {
+            EditorEvent::BufferOpened { panel, .. }
+            | EditorEvent::BufferChanged { panel, .. }
+            | EditorEvent::BufferSaved { panel, .. }
+            | EditorEvent::CursorMoved { panel, .. }
+            | EditorEvent::ModeChanged { panel, .. }
+            | EditorEvent::PanelFocused { panel, .. } => Some(*panel),
+            EditorEvent::WorkspaceSwitched { .. } => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum Event {
     Input(InputEvent),
     Command(String, Vec<String>),
+    Editor(EditorEvent),
     Tick,
+    Resize(u16, u16), // the terminal's new width and height, every panel gets it
+    FocusGained, // the terminal window, also goes to every panel
+    FocusLost,
 }
 
 impl Event {
+    // The file an event is about, used by file type subscriptions
+    pub fn file_path(&self) -> Option<&Path> {
+        match self {
+            Event::Editor(EditorEvent::BufferOpened { path, .. })
+            | Event::Editor(EditorEvent::BufferChanged { path, .. })
+            | Event::Editor(EditorEvent::CursorMoved { path, .. }) => path.as_deref(),
+            Event::Editor(EditorEvent::BufferSaved { path, .. }) => Some(path.as_path()),
+            _ => None,
+        }
+    }
+
     pub fn keyboard_input_event_from_crossterm_key(event: KeyEvent) -> Event {
         fn modifier_name(modifier: KeyModifiers) -> String {
             let dbg = format!("{:?}", modifier);
@@ -46,6 +99,11 @@ impl Event {
         let key_str = match event.code {
             KeyCode::BackTab => String::from("tab"),
             KeyCode::F(n) => format!("f{}", n),
+            // Chars with control or alt are keybindings rather than text, e.g. ["control", "s"]
+            KeyCode::Char(c) if event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                keyboard_vec.retain(|m| m != "shift");
+                c.to_lowercase().to_string()
+            }
             KeyCode::Char(c) => return Event::Input(InputEvent::Char(c)),
             other => format!("{:?}", other).to_lowercase(),
         };
@@ -63,7 +121,10 @@ impl Event {
                 Some(ev)
             },
             crossterm::event::Event::Mouse(mouse_event) => Some(Event::Input(InputEvent::Mouse(mouse_event))),
-            _ => None,
+            crossterm::event::Event::Paste(text) => Some(Event::Input(InputEvent::Paste(text))),
+            crossterm::event::Event::Resize(width, height) => Some(Event::Resize(width, height)),
+            crossterm::event::Event::FocusGained => Some(Event::FocusGained),
+            crossterm::event::Event::FocusLost => Some(Event::FocusLost),
         }
     }
 }
\ No newline at end of file
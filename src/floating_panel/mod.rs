@@ -1 +1,2 @@
-mod floating_panel;
\ No newline at end of file
+mod floating_panel;
+pub mod toast_panel;
\ No newline at end of file
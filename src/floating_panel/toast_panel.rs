@@ -0,0 +1,140 @@
+use crate::app::MosId;
+use crate::event::event::Event;
+use crate::panel::panel::{Panel, PanelStatus};
+use crate::system::notifications::{Notification, Severity};
+use crate::system::theme;
+use ratatui::layout::Rect;
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::Frame;
+use std::time::Instant;
+use unicode_width::UnicodeWidthStr;
+
+const MAX_TOASTS: usize = 5;
+const MAX_WIDTH: u16 = 60;
+
+// Transient notifications stacked in a floating panel, owned by Mos so they show over any workspace
+pub struct ToastPanel {
+    id: MosId,
+    toasts: Vec<(Notification, Instant)>, // (notification, expires at)
+    dirty: bool,
+}
+
+impl ToastPanel {
+    pub fn new() -> Self {
+        Self {
+            id: MosId::new(),
+            toasts: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    // Themes style severities by their names, "error", "warning" and so on
+    pub fn severity_style(severity: Severity) -> Style {
+        theme::style(severity.name())
+    }
+
+    pub fn push(&mut self, notification: Notification) {
+        let expires = Instant::now() + notification.severity.duration();
+        self.toasts.push((notification, expires));
+
+        if self.toasts.len() > MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        self.dirty = true;
+    }
+
+    // A specific toast by its notification id, or every toast without one
+    pub fn dismiss(&mut self, id: Option<u64>) -> bool {
+        let before = self.toasts.len();
+        self.toasts.retain(|(notification, _)| id.is_some_and(|id| notification.id != id));
+        self.dirty |= self.toasts.len() != before;
+        self.toasts.len() != before
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        let before = self.toasts.len();
+        self.toasts.retain(|(_, expires)| *expires > now);
+        if self.toasts.len() != before {
+            self.dirty = true;
+        }
+    }
+
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.toasts.iter().map(|(_, expires)| *expires).min()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.toasts.is_empty()
+    }
+
+    // A toast shows the first line, the rest of a longer message is in the messages panel
+    fn more_lines(notification: &Notification) -> String {
+        match notification.message.lines().count() {
+            0 | 1 => String::new(),
+            lines => format!(" (+{} lines in messages)", lines - 1),
+        }
+    }
+
+    // Wide enough for the longest message, one line per toast plus the border
+    pub fn size(&self) -> (u16, u16) {
+        let longest = self.toasts.iter()
+            .map(|(n, _)| n.message.lines().next().unwrap_or("").width() + Self::more_lines(n).width() + n.source.width() + 5)
+            .max()
+            .unwrap_or(0) as u16;
+
+        ((longest + 2).min(MAX_WIDTH), self.toasts.len() as u16 + 2)
+    }
+}
+
+impl Panel for ToastPanel {
+    fn id(&self) -> MosId {
+        self.id
+    }
+
+    fn title(&self) -> &str {
+        "Notifications"
+    }
+
+    fn kind(&self) -> &str {
+        "toast"
+    }
+
+    fn handle_event(&mut self, _event: Event) {}
+
+    fn render(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self.toasts.iter().map(|(n, _)| {
+            Line::from(vec![
+                Span::styled("● ", Self::severity_style(n.severity)),
+                Span::styled(format!("[{}] ", n.source), theme::style("ui.text.dim")),
+                Span::raw(n.message.lines().next().unwrap_or("").to_string()),
+                Span::styled(Self::more_lines(n), theme::style("ui.text.dim")),
+            ])
+        }).collect();
+
+        let border_style = self.toasts.iter()
+            .map(|(n, _)| n.severity)
+            .max()
+            .map(Self::severity_style)
+            .unwrap_or_else(|| theme::style("ui.border"));
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style);
+
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn status(&self) -> PanelStatus {
+        PanelStatus::default()
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+}
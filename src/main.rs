@@ -8,71 +8,82 @@ mod plugin;
 mod system;
 
 use crate::app::Mos;
-use crossterm::{event::{DisableMouseCapture, EnableMouseCapture}, execute, terminal::{EnterAlternateScreen, LeaveAlternateScreen}};
-use ratatui::backend::CrosstermBackend;
-use ratatui::Terminal;
-use std::io::stdout;
-use std::time::Duration;
-use crossterm::event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crate::system::cli::{self, CliAction};
+use crate::system::event_bus;
+use crate::system::event_loop::{self, LoopMessage};
+use crate::system::terminal_session::TerminalSession;
+use std::sync::mpsc::{self, RecvTimeoutError};
 
 fn main() -> Result<(), String> {
-    if crossterm::terminal::enable_raw_mode().is_err() {
-        return Err("Failed to enable raw mode".to_string());
+    let mut options = match cli::parse(std::env::args().skip(1)) {
+        Ok(CliAction::Run(options)) => options,
+        Ok(CliAction::Help) => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(CliAction::Version) => {
+            println!("mos {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("mos: {}\nTry 'mos --help' for more information.", e);
+            std::process::exit(2);
+        }
+    };
+
+    // stdin has to be read before the terminal is taken over, input then comes from the tty
+    options.read_stdin()?;
+    cli::enter_workspace(&mut options)?;
+
+    // The terminal hook has to come first, panic_guard forwards the panics it doesn't catch to it
+    TerminalSession::install_panic_hook();
+    system::panic_guard::install_hook();
+
+    if let Err(e) = system::logger::init(options.log_level) {
+        eprintln!("Failed to open the log file, logging to memory only: {}", e);
     }
-    
-    // Enter the alternate screen and enable mouse capture so only our UI is visible.
-    execute!(stdout(), EnterAlternateScreen, EnableMouseCapture, PushKeyboardEnhancementFlags(
-        KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES,
-        //KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
-    )).map_err(|e| {
-        crossterm::terminal::disable_raw_mode().ok();
-        format!("Failed to enter alternate screen: {}", e)
-    })?;
 
-    // RAII guard to restore terminal state on exit (also runs on panic)
-    //struct TerminalRestore;
-    //impl Drop for TerminalRestore {
-    //    fn drop(&mut self) {
-    //        crossterm::terminal::disable_raw_mode().ok();
-    //        // Best-effort restore: disable mouse capture and leave alternate screen
-    //        execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen).ok();
-    //    }
-    //}
-    //let _terminal_restore = TerminalRestore;
+    // Restores the terminal when dropped, also on early returns with ?
+    let mut session = TerminalSession::enter()?;
 
-    let backend = CrosstermBackend::new(stdout());
-    let mut terminal = if let Ok(term) = Terminal::new(backend) {
-        term
-    } else {
-        // If terminal initialization failed, try to restore terminal state and return error.
-        crossterm::terminal::disable_raw_mode().ok();
-        execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen, PopKeyboardEnhancementFlags).ok();
-        return Err("Failed to initialize terminal".to_string());
-    };
+    let mut mos = Mos::new(&options);
 
-    let mut mos = Mos::new();
+    // Input, emitted events and timers all wake the loop through this channel, nothing spins while idle
+    let (sender, receiver) = mpsc::channel();
+    event_bus::set_waker(sender.clone());
+    event_loop::spawn_input_thread(sender);
 
     loop {
+        mos.update();
+
         if mos.should_quit {
             break;
         }
-        
-        while crossterm::event::poll(Duration::from_millis(0)).map_err(|e| format!("Failed to poll events: {}", e))? {
-            let ev = crossterm::event::read().map_err(|e| format!("Failed to read event: {}", e))?;
-            mos.handle_terminal_event(ev);
-        }
 
-        mos.update();
+        if mos.needs_redraw() {
+            session.terminal.draw(|frame| {
+                mos.render(frame);
+            }).map_err(|e| format!("Failed to draw terminal: {}", e))?;
+        }
 
-        terminal.draw(|frame| {
-            mos.render(frame);
-        }).map_err(|e| format!("Failed to draw terminal: {}", e))?;
+        let message = match mos.next_wakeup() {
+            Some(timeout) => match receiver.recv_timeout(timeout) {
+                Ok(message) => Some(message),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return Err("Event loop disconnected".to_string()),
+            },
+            None => Some(receiver.recv().map_err(|_| "Event loop disconnected".to_string())?),
+        };
 
-        //  std::thread::sleep(Duration::from_millis(16));
+        // Handle everything that arrived, then update and draw once
+        for message in message.into_iter().chain(receiver.try_iter()) {
+            match message {
+                LoopMessage::Terminal(ev) => mos.handle_terminal_event(ev),
+                LoopMessage::TerminalError(e) => return Err(e),
+                LoopMessage::Wake => {}
+            }
+        }
     }
 
-    // Normal cleanup will also happen in TerminalRestore::drop, but do an explicit best-effort here.
-    crossterm::terminal::disable_raw_mode().ok();
-    execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen, PopKeyboardEnhancementFlags).ok();
     Ok(())
 }
@@ -1,19 +1,45 @@
 use crate::app::MosId;
 use crate::event::event::Event;
+use crate::system::panic_guard::PanicReport;
 use ratatui::layout::Rect;
 use ratatui::Frame;
 
 pub type PanelCtor = fn() -> Box<dyn Panel>;
 
+// A panel that panicked while rendering or handling an event
+pub struct PanelFault {
+    pub panel_id: MosId,
+    pub kind: String,
+    pub report: PanicReport,
+}
+
+// What the status line shows about the active panel, panels leave out what doesn't apply to them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PanelStatus {
+    pub mode: Option<String>,
+    pub file: Option<String>,
+    pub modified: bool,
+    pub read_only: bool,
+    pub position: Option<(usize, usize)>, // 0-based line and column of the primary cursor
+    pub cursors: usize,
+    pub encoding: Option<String>,
+    pub line_ending: Option<String>,
+}
+
 pub trait Panel {
     fn id(&self) -> MosId;
     fn title(&self) -> &str;
+    fn kind(&self) -> &str; // the panel id it was registered with in the panel registry
     
     // fn plugin_id(&self) -> MosId; // maybe panels should also have a reference to their plugin
-    // fn kind(&self) -> MosId; // kind?
     
     // fn in_normal() -> bool; // if is in normal mode, panels only get input in normal mode, this should probably be an an event, reveresed not managed by the panel, defined in workspace maybe?
     
     fn handle_event(&mut self, event: Event);
     fn render(&self, frame: &mut Frame, area: Rect);
+    fn status(&self) -> PanelStatus;
+
+    // Mos only redraws when a visible panel or the layout is dirty
+    fn is_dirty(&self) -> bool;
+    fn clear_dirty(&mut self);
 }
\ No newline at end of file
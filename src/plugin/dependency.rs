@@ -0,0 +1,230 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    // Missing parts are zero, so "1.2" is 1.2.0, anything after a '-' or '+' is ignored
+    pub fn parse(version: &str) -> Result<Version, String> {
+        let core = version.trim().split(['-', '+']).next().unwrap_or("");
+        let parts: Vec<&str> = core.split('.').collect();
+        if core.is_empty() || parts.len() > 3 {
+            return Err(format!("Invalid version '{}'", version));
+        }
+
+        let mut numbers = [0u64; 3];
+        for (i, part) in parts.iter().enumerate() {
+            numbers[i] = part.parse().map_err(|_| format!("Invalid version '{}'", version))?;
+        }
+
+        Ok(Version { major: numbers[0], minor: numbers[1], patch: numbers[2] })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Exact, // =1.2.3
+    Greater, // >1.2.3
+    GreaterEq, // >=1.2.3
+    Less, // <1.2.3
+    LessEq, // <=1.2.3
+    Tilde, // ~1.2.3, same minor version
+    Caret, // ^1.2.3 or 1.2.3, no breaking changes like cargo
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Comparator {
+    op: Op,
+    version: Version,
+    parts: usize, // how many parts were written, ~1 allows any 1.x
+}
+
+impl Comparator {
+    fn matches(&self, v: &Version) -> bool {
+        let req = &self.version;
+        match self.op {
+            Op::Exact => v == req,
+            Op::Greater => v > req,
+            Op::GreaterEq => v >= req,
+            Op::Less => v < req,
+            Op::LessEq => v <= req,
+            Op::Tilde => {
+                v >= req && v.major == req.major && (self.parts < 2 || v.minor == req.minor)
+            }
+            Op::Caret => {
+                if v < req {
+                    false
+                } else if req.major > 0 || self.parts < 2 {
+                    v.major == req.major
+                } else if req.minor > 0 || self.parts < 3 {
+                    v.major == 0 && v.minor == req.minor
+                } else {
+                    v == req
+                }
+            }
+        }
+    }
+}
+
+// A version requirement like cargo's, e.g. "*", "1.2", "^0.3", "~1.2.0" or ">=1.0, <2.0"
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionReq {
+    source: String,
+    comparators: Vec<Comparator>,
+}
+
+impl VersionReq {
+    pub fn any() -> Self {
+        Self {
+            source: String::from("*"),
+            comparators: Vec::new(),
+        }
+    }
+
+    pub fn parse(req: &str) -> Result<VersionReq, String> {
+        let mut comparators = Vec::new();
+
+        for part in req.split(',').map(|p| p.trim()) {
+            if part == "*" || part.is_empty() {
+                continue;
+            }
+
+            let ops = [(">=", Op::GreaterEq), ("<=", Op::LessEq), (">", Op::Greater), ("<", Op::Less), ("=", Op::Exact), ("~", Op::Tilde), ("^", Op::Caret)];
+            let (op, rest) = ops.iter()
+                .find(|(prefix, _)| part.starts_with(prefix))
+                .map(|(prefix, op)| (*op, &part[prefix.len()..]))
+                .unwrap_or((Op::Caret, part));
+
+            let rest = rest.trim();
+            comparators.push(Comparator {
+                op,
+                version: Version::parse(rest).map_err(|_| format!("Invalid version requirement '{}'", req))?,
+                parts: rest.split('.').count(),
+            });
+        }
+
+        Ok(VersionReq {
+            source: req.trim().to_string(),
+            comparators,
+        })
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|c| c.matches(version))
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dependency {
+    pub plugin_id: String,
+    pub version: VersionReq,
+}
+
+impl Dependency {
+    pub fn new(plugin_id: &str, version: &str) -> Result<Self, String> {
+        Ok(Self {
+            plugin_id: plugin_id.to_string(),
+            version: VersionReq::parse(version)?,
+        })
+    }
+
+    // Listed without a version, any version will do
+    pub fn any_version(plugin_id: &str) -> Self {
+        Self {
+            plugin_id: plugin_id.to_string(),
+            version: VersionReq::any(),
+        }
+    }
+
+    // Checks the version a plugin reports against the requirement
+    pub fn check(&self, version: &str) -> Result<(), String> {
+        let parsed = Version::parse(version)?;
+        if self.version.matches(&parsed) {
+            Ok(())
+        } else {
+            Err(format!("requires {} {}, but version {} is installed", self.plugin_id, self.version, parsed))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(v("1.2"), Version { major: 1, minor: 2, patch: 0 });
+        assert_eq!(v("1.2.3-beta+build"), Version { major: 1, minor: 2, patch: 3 });
+        assert!(Version::parse("").is_err());
+        assert!(Version::parse("1.2.3.4").is_err());
+        assert!(Version::parse("1.x").is_err());
+    }
+
+    #[test]
+    fn caret_is_the_default() {
+        let req = VersionReq::parse("1.2").unwrap();
+        assert!(req.matches(&v("1.2.0")));
+        assert!(req.matches(&v("1.9.9")));
+        assert!(!req.matches(&v("2.0.0")));
+        assert!(!req.matches(&v("1.1.9")));
+
+        let req = VersionReq::parse("^0.3").unwrap();
+        assert!(req.matches(&v("0.3.5")));
+        assert!(!req.matches(&v("0.4.0")));
+
+        let req = VersionReq::parse("^0.0.3").unwrap();
+        assert!(req.matches(&v("0.0.3")));
+        assert!(!req.matches(&v("0.0.4")));
+    }
+
+    #[test]
+    fn tilde_and_ranges() {
+        let req = VersionReq::parse("~1.2.0").unwrap();
+        assert!(req.matches(&v("1.2.7")));
+        assert!(!req.matches(&v("1.3.0")));
+        assert!(VersionReq::parse("~1").unwrap().matches(&v("1.8.0")));
+
+        let req = VersionReq::parse(">=1.0, <2.0").unwrap();
+        assert!(req.matches(&v("1.5.0")));
+        assert!(!req.matches(&v("2.0.0")));
+        assert!(VersionReq::parse("=1.2.3").unwrap().matches(&v("1.2.3")));
+        assert!(!VersionReq::parse("=1.2.3").unwrap().matches(&v("1.2.4")));
+    }
+
+    #[test]
+    fn any_and_errors() {
+        assert!(VersionReq::parse("*").unwrap().matches(&v("0.0.1")));
+        assert!(VersionReq::any().matches(&v("9.9.9")));
+        assert_eq!(VersionReq::any().to_string(), "*");
+        assert!(VersionReq::parse(">=abc").is_err());
+    }
+
+    #[test]
+    fn checks_installed_versions() {
+        let dependency = Dependency::new("mos_editor", "^1.0").unwrap();
+        assert!(dependency.check("1.4.0").is_ok());
+        let error = dependency.check("2.0.0").unwrap_err();
+        assert!(error.contains("requires mos_editor ^1.0"), "{}", error);
+        assert!(Dependency::any_version("mos_editor").check("0.1.0").is_ok());
+    }
+}
\ No newline at end of file
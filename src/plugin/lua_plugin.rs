@@ -0,0 +1,172 @@
+use crate::event::event::{EditorEvent, Event, InputEvent};
+use crate::plugin::dependency::Dependency;
+use crate::plugin::plugin::{Plugin, PluginContext};
+use crate::plugin::subscription::{EventKind, Subscription};
+use crate::system::theme::{self, Theme};
+use mlua::{Function, Lua, Table};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// What a lua plugin registered through the mos api while loading, shared with the api functions
+#[derive(Default)]
+pub struct LuaRegistrations {
+    pub subscriptions: Vec<(Subscription, Function)>,
+    pub commands: Vec<(String, String)>, // (name, description)
+    pub keymaps: Vec<(Vec<String>, String, Vec<String>)>, // (keys, command, args)
+    pub themes: Vec<Theme>,
+}
+
+pub type SharedLuaRegistrations = Rc<RefCell<LuaRegistrations>>;
+
+// A plugin loaded from a lua file, the callbacks are registered with mos.subscribe(kind, filter, callback)
+pub struct LuaPlugin {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub dependencies: Vec<Dependency>,
+    lua: Lua,
+    registrations: SharedLuaRegistrations,
+}
+
+impl LuaPlugin {
+    pub fn new(id: String, lua: Lua, registrations: SharedLuaRegistrations) -> Self {
+        Self {
+            name: id.clone(),
+            id,
+            version: String::from("0.0.0"),
+            description: String::new(),
+            dependencies: Vec::new(),
+            lua,
+            registrations,
+        }
+    }
+
+    fn event_to_table(&self, event: &Event) -> mlua::Result<Table> {
+        let table = self.lua.create_table()?;
+        table.set("kind", EventKind::of(event).name())?;
+
+        if let Some(path) = event.file_path() {
+            table.set("path", path.to_string_lossy().to_string())?;
+        }
+
+        match event {
+            Event::Input(input) => match input {
+                InputEvent::Keyboard(keys) => table.set("keys", keys.clone())?,
+                InputEvent::Char(c) => table.set("char", c.to_string())?,
+                InputEvent::Paste(text) => table.set("text", text.as_str())?,
+                InputEvent::Mouse(mouse) => {
+                    table.set("column", mouse.column)?;
+                    table.set("row", mouse.row)?;
+                }
+            },
+            Event::Command(name, args) => {
+                table.set("name", name.as_str())?;
+                table.set("args", args.clone())?;
+            }
+            Event::Editor(editor_event) => {
+                if let Some(panel) = editor_event.panel() {
+                    table.set("panel", panel.to_string())?;
+                }
+                match editor_event {
+                    EditorEvent::BufferChanged { edits, .. } => {
+                        let edits_table = self.lua.create_table()?;
+                        for edit in edits {
+                            let edit_table = self.lua.create_table()?;
+                            edit_table.set("start", edit.start)?;
+                            edit_table.set("end", edit.end)?;
+                            edit_table.set("text", edit.text.as_str())?;
+                            edits_table.push(edit_table)?;
+                        }
+                        table.set("edits", edits_table)?;
+                    }
+                    EditorEvent::CursorMoved { line, column, .. } => {
+                        table.set("line", *line)?;
+                        table.set("column", *column)?;
+                    }
+                    EditorEvent::ModeChanged { mode, .. } => table.set("mode", mode.as_str())?,
+                    EditorEvent::PanelFocused { title, .. } => table.set("title", title.as_str())?,
+                    EditorEvent::WorkspaceSwitched { from, to } => {
+                        table.set("from", *from)?;
+                        table.set("to", *to)?;
+                    }
+                    EditorEvent::BufferOpened { .. } | EditorEvent::BufferSaved { .. } => {}
+                }
+            }
+            Event::Resize(width, height) => {
+                table.set("width", *width)?;
+                table.set("height", *height)?;
+            }
+            Event::Tick | Event::FocusGained | Event::FocusLost => {}
+        }
+
+        Ok(table)
+    }
+}
+
+impl Plugin for LuaPlugin {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn dependencies(&self) -> Vec<Dependency> {
+        self.dependencies.clone()
+    }
+
+    fn subscriptions(&self) -> Vec<Subscription> {
+        self.registrations.borrow().subscriptions.iter().map(|(s, _)| s.clone()).collect()
+    }
+
+    fn enable(&mut self, context: &mut PluginContext) -> Result<(), String> {
+        let registrations = self.registrations.borrow();
+
+        for (name, description) in &registrations.commands {
+            context.command_registry.register_command(self.id(), name.clone(), description.clone());
+        }
+        for (keys, command, args) in &registrations.keymaps {
+            context.keymap_registry.register_keybinding(self.id(), keys.clone(), command.clone(), args.clone());
+        }
+        for definition in &registrations.themes {
+            theme::define(definition.clone());
+        }
+
+        Ok(())
+    }
+
+    fn disable(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: Event) -> Result<(), String> {
+        // Clone the callbacks out so a callback can subscribe to more events without a double borrow
+        let callbacks: Vec<Function> = self.registrations.borrow()
+            .subscriptions
+            .iter()
+            .filter(|(s, _)| s.matches(&event))
+            .map(|(_, f)| f.clone())
+            .collect();
+
+        if callbacks.is_empty() {
+            return Ok(());
+        }
+
+        let table = self.event_to_table(&event).map_err(|e| e.to_string())?;
+        for callback in callbacks {
+            callback.call::<()>(table.clone()).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -1 +1,4 @@
-pub mod plugin;
\ No newline at end of file
+pub mod plugin;
+pub mod subscription;
+pub mod lua_plugin;
+pub mod dependency;
\ No newline at end of file
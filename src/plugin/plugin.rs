@@ -1,12 +1,15 @@
-use crate::app::MosId;
 use crate::event::event::Event;
-use crate::panel::panel::PanelCtor;
+use crate::plugin::dependency::Dependency;
+use crate::plugin::subscription::Subscription;
+use crate::system::command_registry::CommandRegistry;
+use crate::system::keymap_registry::KeymapRegistry;
 use crate::system::panel_registry::PanelRegistry;
-use std::collections::HashMap;
 
-pub struct PluginRegistration {
-    pub panel_kinds: HashMap<MosId, PanelCtor>,
-    // may contain more things
+// Everything a plugin can register things in when it is enabled, all of it is unregistered by plugin id when disabled
+pub struct PluginContext<'a> {
+    pub panel_registry: &'a mut PanelRegistry,
+    pub command_registry: &'a mut CommandRegistry,
+    pub keymap_registry: &'a mut KeymapRegistry,
 }
 
 pub trait Plugin {
@@ -14,12 +17,14 @@ pub trait Plugin {
     fn name(&self) -> String;
     fn version(&self) -> String;
     fn description(&self) -> String;
-    // fn dependencies(&self) -> Vec<PrettyMosKind>;
+    fn dependencies(&self) -> Vec<Dependency>; // plugins that have to be enabled before this one
 
     // fn is_backend()
-    // also/or have some kind of function subscription, so that they only get what the care about
 
-    fn enable(&mut self, panel_registry: &mut PanelRegistry) -> Result<(), String>;
-    fn disable(&mut self) -> PluginRegistration; // return the panels to be removed and unregistered, could probably just remove all panels with this plugin's id from hashmap
+    // The events this plugin cares about, the registry only sends it events matching one of these
+    fn subscriptions(&self) -> Vec<Subscription>;
+
+    fn enable(&mut self, context: &mut PluginContext) -> Result<(), String>;
+    fn disable(&mut self) -> Result<(), String>; // only for the plugin's own cleanup, the registry unregisters everything registered with its id
     fn handle_event(&mut self, event: Event) -> Result<(), String>;
 }
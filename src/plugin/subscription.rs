@@ -0,0 +1,138 @@
+use crate::event::event::{EditorEvent, Event, InputEvent};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Input,
+    Command,
+    Tick,
+    Resize,
+    FocusGained,
+    FocusLost,
+    BufferOpened,
+    BufferChanged,
+    BufferSaved,
+    CursorMoved,
+    ModeChanged,
+    PanelFocused,
+    WorkspaceSwitched,
+}
+
+impl EventKind {
+    pub fn of(event: &Event) -> EventKind {
+        match event {
+            Event::Input(_) => EventKind::Input,
+            Event::Command(..) => EventKind::Command,
+            Event::Tick => EventKind::Tick,
+            Event::Resize(..) => EventKind::Resize,
+            Event::FocusGained => EventKind::FocusGained,
+            Event::FocusLost => EventKind::FocusLost,
+            Event::Editor(editor_event) => match editor_event {
+                EditorEvent::BufferOpened { .. } => EventKind::BufferOpened,
+                EditorEvent::BufferChanged { .. } => EventKind::BufferChanged,
+                EditorEvent::BufferSaved { .. } => EventKind::BufferSaved,
+                EditorEvent::CursorMoved { .. } => EventKind::CursorMoved,
+                EditorEvent::ModeChanged { .. } => EventKind::ModeChanged,
+                EditorEvent::PanelFocused { .. } => EventKind::PanelFocused,
+                EditorEvent::WorkspaceSwitched { .. } => EventKind::WorkspaceSwitched,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Input => "input",
+            EventKind::Command => "command",
+            EventKind::Tick => "tick",
+            EventKind::Resize => "resize",
+            EventKind::FocusGained => "focus_gained",
+            EventKind::FocusLost => "focus_lost",
+            EventKind::BufferOpened => "buffer_opened",
+            EventKind::BufferChanged => "buffer_changed",
+            EventKind::BufferSaved => "buffer_saved",
+            EventKind::CursorMoved => "cursor_moved",
+            EventKind::ModeChanged => "mode_changed",
+            EventKind::PanelFocused => "panel_focused",
+            EventKind::WorkspaceSwitched => "workspace_switched",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EventKind> {
+        match name {
+            "input" => Some(EventKind::Input),
+            "command" => Some(EventKind::Command),
+            "tick" => Some(EventKind::Tick),
+            "resize" => Some(EventKind::Resize),
+            "focus_gained" => Some(EventKind::FocusGained),
+            "focus_lost" => Some(EventKind::FocusLost),
+            "buffer_opened" => Some(EventKind::BufferOpened),
+            "buffer_changed" => Some(EventKind::BufferChanged),
+            "buffer_saved" => Some(EventKind::BufferSaved),
+            "cursor_moved" => Some(EventKind::CursorMoved),
+            "mode_changed" => Some(EventKind::ModeChanged),
+            "panel_focused" => Some(EventKind::PanelFocused),
+            "workspace_switched" => Some(EventKind::WorkspaceSwitched),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventFilter {
+    Any,
+    Command(String), // only commands with this name, e.g. Command("save")
+    Key(Vec<String>), // only this keyboard combination, same format as InputEvent::Keyboard
+    FileType(Vec<String>), // only events about a file with one of these extensions
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        match self {
+            EventFilter::Any => true,
+            EventFilter::Command(name) => matches!(event, Event::Command(n, _) if n == name),
+            EventFilter::Key(keys) => matches!(event, Event::Input(InputEvent::Keyboard(k)) if k == keys),
+            EventFilter::FileType(extensions) => {
+                event.file_path()
+                    .and_then(|path| path.extension())
+                    .map(|ext| extensions.iter().any(|e| ext.to_string_lossy() == e.trim_start_matches('.')))
+                    .unwrap_or(false)
+            }
+        }
+    }
+}
+
+// A plugin only gets the events that match at least one of its subscriptions
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subscription {
+    pub kind: EventKind,
+    pub filter: EventFilter,
+}
+
+impl Subscription {
+    pub fn new(kind: EventKind) -> Self {
+        Self {
+            kind,
+            filter: EventFilter::Any,
+        }
+    }
+
+    pub fn command(name: &str) -> Self {
+        Self::new(EventKind::Command).with_filter(EventFilter::Command(name.to_string()))
+    }
+
+    pub fn with_filter(mut self, filter: EventFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        self.kind == EventKind::of(event) && self.filter.matches(event)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DispatchStats {
+    pub events: u64, // events that went through the registry
+    pub delivered: u64, // (event, plugin) pairs that were delivered
+    pub skipped: u64, // (event, plugin) pairs filtered out by subscriptions
+    pub errors: u64, // deliveries where the plugin returned an error
+}
\ No newline at end of file
@@ -1 +1,3 @@
-pub mod mos_editor;
\ No newline at end of file
+pub mod mos_editor;
+pub mod mos_log;
+pub mod mos_messages;
\ No newline at end of file
@@ -1,6 +1,15 @@
 use std::path::PathBuf;
+use std::time::SystemTime;
 use ropey::Rope;
+use crate::event::event::{EditRange, EditorEvent};
 use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, CursorDirection, EditorPanel};
+use crate::plugin_builtin::mos_editor::graphemes::LineLayout;
+use crate::plugin_builtin::mos_editor::syntax::grammars;
+use crate::plugin_builtin::mos_editor::syntax::syntax::Highlighter;
+use crate::plugin_builtin::mos_editor::viewport::Scroll;
+use crate::plugin_builtin::mos_editor::mos_editor::PLUGIN_ID;
+use crate::plugin_builtin::mos_editor::undo::Change;
+use crate::system::notifications;
 
 #[derive(Debug, Clone)]
 enum Edit {
@@ -8,14 +17,147 @@ enum Edit {
     Delete { range: std::ops::Range<usize> },
 }
 
+impl Edit {
+    fn to_range(&self) -> EditRange {
+        match self {
+            Edit::Insert { at, text } => EditRange { start: *at, end: *at, text: text.clone() },
+            Edit::Delete { range } => EditRange { start: range.start, end: range.end, text: String::new() },
+        }
+    }
+}
+
 impl EditorPanel {
-    pub fn open_file(&mut self, file_path: PathBuf) {
-        if let Ok(content) = std::fs::read_to_string(&file_path) {
-            self.rope = Rope::from_str(&content);
-            self.file_path = Some(file_path);
-            self.cursors = vec![Cursor::new(0, 0, 0)];
-            self.scroll_offset = 0;
-            //self.syntax = syntax_for_extension(&*self.get_file_extension().unwrap(), &self.syntax_index);
+    // A file that doesn't exist yet opens as an empty buffer, it is created on save
+    pub fn open_file(&mut self, file_path: PathBuf) -> Result<(), String> {
+        let content = match std::fs::read_to_string(&file_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Failed to open {}: {}", file_path.display(), e)),
+        };
+
+        self.title = file_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| file_path.display().to_string());
+        self.file_path = Some(file_path);
+        self.set_text(&content);
+        self.disk_modified = self.disk_mtime();
+        Ok(())
+    }
+
+    // When the file was last written, by anyone
+    fn disk_mtime(&self) -> Option<SystemTime> {
+        let path = self.file_path.as_ref()?;
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    // Reads the file again, the cursors and view stay where they were as far as the new text allows
+    pub fn reload(&mut self) -> Result<(), String> {
+        let path = self.file_path.clone().ok_or("Buffer has no file path")?;
+        let content = std::fs::read_to_string(&path).map_err(|e| format!("Failed to reload {}: {}", path.display(), e))?;
+
+        let (cursors, scroll) = (self.cursors.clone(), (self.scroll_offset, self.scroll_row, self.h_scroll));
+        self.set_text(&content);
+        self.disk_modified = self.disk_mtime();
+        self.cursors = cursors.into_iter().map(|c| Self::clamp_cursor(&self.rope, Cursor::new(c.line, c.column, c.goal_column))).collect();
+        self.dedup_cursors();
+        (self.scroll_offset, self.scroll_row, self.h_scroll) = scroll;
+        self.clamp_scroll();
+        Ok(())
+    }
+
+    // When the terminal gets focus again, a file changed elsewhere is reloaded unless the buffer has changes of its own
+    pub fn check_disk(&mut self) {
+        let mtime = self.disk_mtime();
+        if mtime.is_none() || mtime == self.disk_modified {
+            return;
+        }
+        let name = self.file_path.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+
+        if self.modified {
+            // Only warned about once, reload discards the changes and save overwrites the file
+            self.disk_modified = mtime;
+            self.disk_conflict = true;
+            let paused = if self.autosave { ", autosave is paused until then" } else { "" };
+            notifications::warning(PLUGIN_ID, format!("{} changed on disk, reload to discard your changes or save to keep them{}", name, paused));
+            return;
+        }
+        match self.reload() {
+            Ok(()) => notifications::info(PLUGIN_ID, format!("Reloaded {}", name)),
+            Err(e) => notifications::error(PLUGIN_ID, e),
+        }
+    }
+
+    // When the terminal loses focus, with autosave on. Not while the file changed on disk, that would overwrite it
+    pub fn autosave(&mut self) {
+        if !self.autosave || !self.modified || self.read_only || self.disk_conflict || self.file_path.is_none() {
+            return;
+        }
+        if let Err(e) = self.save_file() {
+            notifications::error(PLUGIN_ID, format!("Failed to autosave: {}", e));
+        }
+    }
+
+    // Replaces the whole buffer, e.g. with what was piped into mos
+    pub fn set_text(&mut self, text: &str) {
+        self.rope = Rope::from_str(text);
+        self.modified = false;
+        self.disk_conflict = false;
+        self.history.clear();
+        self.cursors = vec![Cursor::new(0, 0, 0)];
+        self.scroll_offset = 0;
+        self.folds.clear();
+        self.detect_syntax();
+
+        self.emit(EditorEvent::BufferOpened { panel: self.id, path: self.file_path.clone() });
+    }
+
+    // By file name or extension, and by the shebang for scripts without one
+    pub fn detect_syntax(&mut self) {
+        let first_line = Self::line_text_rope(&self.rope, 0);
+        let grammar = grammars::detect(self.file_path.as_deref(), &first_line);
+        *self.syntax.get_mut() = grammar.map(Highlighter::new);
+    }
+
+    // Moves to a 0-based position, clamped to the buffer, and centers it in the view
+    pub fn goto(&mut self, line: usize, column: usize) {
+        self.cursors = vec![Self::clamp_cursor(&self.rope, Cursor::new(line, column, column))];
+        self.reveal_line(self.cursors[0].line);
+        self.update_goal_columns();
+        self.scroll(Scroll::Center);
+    }
+
+    pub fn save_file(&mut self) -> Result<(), String> {
+        let path = self.file_path.clone().ok_or("Buffer has no file path")?;
+
+        let file = std::fs::File::create(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        self.rope.write_to(std::io::BufWriter::new(file)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        self.modified = false;
+        self.history.mark_saved();
+        self.disk_modified = self.disk_mtime();
+        self.disk_conflict = false;
+        self.emit(EditorEvent::BufferSaved { panel: self.id, path });
+        Ok(())
+    }
+
+    fn emit_changes(&self, edits: &[Edit]) {
+        if edits.is_empty() {
+            return;
+        }
+
+        self.emit(EditorEvent::BufferChanged {
+            panel: self.id,
+            path: self.file_path.clone(),
+            edits: edits.iter().map(|e| e.to_range()).collect(),
+        });
+    }
+
+    // Going by the first line, a buffer without any line break is shown as LF
+    pub fn line_ending(&self) -> &'static str {
+        let first = self.rope.line(0);
+        let len = first.len_chars();
+        if len >= 2 && first.char(len - 2) == '\r' && first.char(len - 1) == '\n' {
+            "CRLF"
+        } else {
+            "LF"
         }
     }
 
@@ -32,19 +174,49 @@ impl EditorPanel {
         self.rope.line_to_char(c.line) + c.column
     }
 
-    fn clamp_cursor(rope: &Rope, mut c: Cursor) -> Cursor {
+    // Keeps a cursor inside the buffer and on a grapheme boundary
+    pub fn clamp_cursor(rope: &Rope, mut c: Cursor) -> Cursor {
         let max_line = rope.len_lines().saturating_sub(1);
         c.line = c.line.min(max_line);
+        c.column = LineLayout::new(&Self::line_text_rope(rope, c.line), 1, None).snap(c.column);
+        c
+    }
 
-        let line_len = rope.line(c.line).len_chars();
-        let visible = if line_len > 0 && rope.char(rope.line_to_char(c.line) + line_len - 1) == '\n' {
-            line_len - 1
-        } else {
-            line_len
-        };
+    // The text of a line without its line break
+    pub fn line_text_rope(rope: &Rope, line: usize) -> String {
+        if line >= rope.len_lines() {
+            return String::new();
+        }
+        rope.line(line).chars().take(Self::line_visible_len_rope(rope, line)).collect()
+    }
 
-        c.column = c.column.min(visible);
-        c
+    // A line laid out without soft wrap, x is the display column from the start of the line
+    pub fn unwrapped_layout(&self, line: usize) -> LineLayout {
+        LineLayout::new(&Self::line_text_rope(&self.rope, line), self.tab_width, None)
+    }
+
+    // Vertical motion aims for the display column the cursor was last moved to horizontally
+    pub fn update_goal_columns(&mut self) {
+        let mut cursors = std::mem::take(&mut self.cursors);
+        for c in cursors.iter_mut() {
+            c.goal_column = self.unwrapped_layout(c.line).position(c.column).1;
+        }
+        self.cursors = cursors;
+    }
+
+    // Where the grapheme before pos starts, a line break counts as one even if it is \r\n
+    fn prev_grapheme_start(&self, pos: usize) -> usize {
+        let line = self.rope.char_to_line(pos);
+        let line_start = self.rope.line_to_char(line);
+        let column = pos - line_start;
+
+        if column > 0 {
+            line_start + self.unwrapped_layout(line).prev_column(column)
+        } else if line > 0 {
+            self.rope.line_to_char(line - 1) + Self::line_visible_len_rope(&self.rope, line - 1)
+        } else {
+            0
+        }
     }
 
     fn normalize_geometry(&mut self) {
@@ -56,27 +228,100 @@ impl EditorPanel {
         self.cursors.sort();
     }
 
-    fn dedup_cursors(&mut self) {
+    pub fn dedup_cursors(&mut self) {
         self.cursors.dedup();
     }
 
+    fn first_edited_line(&self, edits: &[Edit]) -> Option<usize> {
+        edits.iter()
+            .map(|e| match e { Edit::Insert { at, .. } => *at, Edit::Delete { range } => range.start })
+            .min()
+            .map(|at| self.rope.char_to_line(at))
+    }
+
+    // Lines above the first edit keep their cached highlighting, folds below it move with the text
+    fn after_edit(&mut self, first_line: usize, lines_before: usize) {
+        if let Some(syntax) = self.syntax.get_mut().as_mut() {
+            syntax.invalidate(first_line);
+        }
+        self.shift_folds(first_line, self.rope.len_lines() as isize - lines_before as isize);
+    }
+
     fn apply_edits(&mut self, mut edits: Vec<Edit>) {
         edits.sort_by(|a, b| {
             let pa = match a { Edit::Insert { at, .. } => *at, Edit::Delete { range } => range.start };
             let pb = match b { Edit::Insert { at, .. } => *at, Edit::Delete { range } => range.start };
             pb.cmp(&pa)
         });
+        self.apply_in_order(&edits);
+    }
 
+    // Each edit is applied to the buffer as the ones before it left it, and recorded for undo
+    fn apply_in_order(&mut self, edits: &[Edit]) {
+        self.modified |= !edits.is_empty();
+        let first_line = self.first_edited_line(edits);
+        let lines_before = self.rope.len_lines();
         for edit in edits {
-            match edit {
+            let change = match edit {
                 Edit::Insert { at, text } => {
-                    self.rope.insert(at, &text);
+                    self.rope.insert(*at, text);
+                    Change { at: *at, removed: String::new(), inserted: text.clone() }
                 }
                 Edit::Delete { range } => {
-                    self.rope.remove(range);
+                    let removed = self.rope.slice(range.clone()).to_string();
+                    self.rope.remove(range.clone());
+                    Change { at: range.start, removed, inserted: String::new() }
                 }
+            };
+            self.history.record(change, &self.cursors);
+        }
+        if let Some(line) = first_line {
+            self.after_edit(line, lines_before);
+        }
+    }
+
+    // Applies recorded changes one after the other, for undo and redo
+    pub fn apply_changes(&mut self, changes: Vec<Change>) {
+        let mut edits = Vec::new();
+        for change in changes.iter().cloned() {
+            let removed = change.removed.chars().count();
+            if removed > 0 {
+                edits.push(Edit::Delete { range: change.at..change.at + removed });
+            }
+            if !change.inserted.is_empty() {
+                edits.push(Edit::Insert { at: change.at, text: change.inserted });
+            }
+        }
+
+        self.apply_in_order(&edits);
+
+        // Each change is relative to the buffer the one before it left, so each goes out on its own
+        for change in changes {
+            self.emit(EditorEvent::BufferChanged {
+                panel: self.id,
+                path: self.file_path.clone(),
+                edits: vec![EditRange { start: change.at, end: change.at + change.removed.chars().count(), text: change.inserted }],
+            });
+        }
+    }
+
+    // Replaces char ranges of the buffer in one batch, so it goes out as a single change. The ranges must not overlap
+    pub fn replace_ranges(&mut self, mut replacements: Vec<(std::ops::Range<usize>, String)>) {
+        replacements.sort_by_key(|r| std::cmp::Reverse(r.0.start));
+
+        let mut edits = Vec::new();
+        for (range, text) in replacements {
+            let at = range.start;
+            if !range.is_empty() {
+                edits.push(Edit::Delete { range });
+            }
+            if !text.is_empty() {
+                edits.push(Edit::Insert { at, text });
             }
         }
+
+        self.apply_edits(edits.clone());
+        self.emit_changes(&edits);
     }
 
     pub fn input(&mut self, ch: char) {
@@ -104,17 +349,7 @@ impl EditorPanel {
             };
             pb.cmp(&pa)
         });
-
-        for edit in &edits {
-            match edit {
-                Edit::Insert { at, text } => {
-                    self.rope.insert(*at, text);
-                }
-                Edit::Delete { range } => {
-                    self.rope.remove(range.clone());
-                }
-            }
-        }
+        self.apply_in_order(&edits);
 
         for edit in &edits {
             for pos in &mut positions {
@@ -146,6 +381,8 @@ impl EditorPanel {
 
         self.normalize_geometry();
         self.dedup_cursors();
+        self.update_goal_columns();
+        self.emit_changes(&edits);
     }
 
     pub fn backspace(&mut self) {
@@ -159,7 +396,7 @@ impl EditorPanel {
             .filter_map(|&pos| {
                 if pos > 0 {
                     Some(Edit::Delete {
-                        range: pos - 1..pos,
+                        range: self.prev_grapheme_start(pos)..pos,
                     })
                 } else {
                     None
@@ -178,6 +415,7 @@ impl EditorPanel {
         });
 
         self.apply_edits(edits.clone());
+        self.emit_changes(&edits);
 
         for edit in &edits {
             for pos in &mut positions {
@@ -204,9 +442,10 @@ impl EditorPanel {
             .collect();
 
         self.normalize_geometry();
+        self.update_goal_columns();
     }
 
-    fn line_visible_len_rope(rope: &Rope, line: usize) -> usize {
+    pub fn line_visible_len_rope(rope: &Rope, line: usize) -> usize {
         if line >= rope.len_lines() {
             return 0;
         }
@@ -214,12 +453,12 @@ impl EditorPanel {
         let line = rope.line(line);
         let len = line.len_chars();
 
-        if len == 0 {
-            0
-        } else if line.char(len - 1) == '\n' {
-            len - 1
-        } else {
+        if len == 0 || line.char(len - 1) != '\n' {
             len
+        } else if len >= 2 && line.char(len - 2) == '\r' {
+            len - 2
+        } else {
+            len - 1
         }
     }
 
@@ -250,48 +489,47 @@ impl EditorPanel {
         });
     }
 
+    // Left and right move by grapheme, up and down keep the display column
     pub fn move_cursor(&mut self, dir: CursorDirection) {
-        let rope = &self.rope; // immutable borrow ends here, not inside loop
-        let line_count = rope.len_lines();
+        let mut cursors = std::mem::take(&mut self.cursors);
 
-        for c in &mut self.cursors {
+        for c in &mut cursors {
             match dir {
                 CursorDirection::Left => {
                     if c.column > 0 {
-                        c.column -= 1;
-                    } else if c.line > 0 {
-                        c.line -= 1;
-                        c.column = Self::line_visible_len_rope(rope, c.line);
+                        c.column = self.unwrapped_layout(c.line).prev_column(c.column);
+                    } else if let Some(line) = self.prev_visible_line(c.line) {
+                        c.line = line;
+                        c.column = Self::line_visible_len_rope(&self.rope, c.line);
                     }
-                    c.goal_column = c.column;
+                    c.goal_column = self.unwrapped_layout(c.line).position(c.column).1;
                 }
                 CursorDirection::Right => {
-                    let len = Self::line_visible_len_rope(rope, c.line);
+                    let len = Self::line_visible_len_rope(&self.rope, c.line);
                     if c.column < len {
-                        c.column += 1;
-                    } else if c.line + 1 < line_count {
-                        c.line += 1;
+                        c.column = self.unwrapped_layout(c.line).next_column(c.column);
+                    } else if let Some(line) = self.next_visible_line(c.line) {
+                        c.line = line;
                         c.column = 0;
                     }
-                    c.goal_column = c.column;
+                    c.goal_column = self.unwrapped_layout(c.line).position(c.column).1;
                 }
                 CursorDirection::Up => {
-                    if c.line > 0 {
-                        c.line -= 1;
-                        let max_column = Self::line_visible_len_rope(rope, c.line);
-                        c.column = c.goal_column.min(max_column);
+                    if let Some(line) = self.prev_visible_line(c.line) {
+                        c.line = line;
+                        c.column = self.unwrapped_layout(c.line).column_at(0, c.goal_column);
                     }
                 }
                 CursorDirection::Down => {
-                    if c.line + 1 < line_count {
-                        c.line += 1;
-                        let max_column = Self::line_visible_len_rope(rope, c.line);
-                        c.column = c.goal_column.min(max_column);
+                    if let Some(line) = self.next_visible_line(c.line) {
+                        c.line = line;
+                        c.column = self.unwrapped_layout(c.line).column_at(0, c.goal_column);
                     }
                 }
                 _ => {}
             }
         }
+        self.cursors = cursors;
 
         self.normalize_geometry();
         self.dedup_cursors();
@@ -356,4 +594,31 @@ impl EditorPanel {
         self.cursors.clear();
         self.cursors.push(primary);
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::panel::panel::Panel;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    #[test]
+    fn reload_keeps_the_view_on_a_shorter_file() {
+        let path = std::env::temp_dir().join(format!("mos-reload-test-{}.rs", std::process::id()));
+        let long: String = (0..100).map(|i| format!("let x{} = {};\n", i, i)).collect();
+        std::fs::write(&path, long).unwrap();
+
+        let mut editor = EditorPanel::new();
+        editor.open_file(path.clone()).unwrap();
+        editor.scroll_view(90);
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+        let reloaded = editor.reload();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(reloaded, Ok(()));
+        assert_eq!((editor.scroll_offset, editor.scroll_row), (1, 0));
+        let mut terminal = Terminal::new(TestBackend::new(40, 10)).unwrap();
+        terminal.draw(|frame| editor.render(frame, frame.size())).unwrap();
+    }
 }
\ No newline at end of file
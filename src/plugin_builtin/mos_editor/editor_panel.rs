@@ -1,19 +1,38 @@
 use crate::app::MosId;
-use crate::event::event::{Event, InputEvent};
-use crate::panel::panel::Panel;
+use crate::event::event::{EditorEvent, Event, InputEvent};
+use crate::panel::panel::{Panel, PanelStatus};
+use crate::plugin_builtin::mos_editor::mos_editor::PLUGIN_ID;
+use crate::plugin_builtin::mos_editor::graphemes::{LineLayout, DEFAULT_TAB_WIDTH};
+use crate::plugin_builtin::mos_editor::viewport::Scroll;
+use crate::plugin_builtin::mos_editor::folds::{Fold, FoldAction};
+use crate::plugin_builtin::mos_editor::search::{Search, SearchDirection, SearchPrompt};
+use crate::plugin_builtin::mos_editor::substitute::SubstitutePrompt;
+use crate::plugin_builtin::mos_editor::registers::{self, UNNAMED};
+use crate::plugin_builtin::mos_editor::mouse::Click;
+use crate::plugin_builtin::mos_editor::macros;
+use crate::plugin_builtin::mos_editor::text_objects::{Operator, TextPending};
+use crate::plugin_builtin::mos_editor::undo::History;
+use crate::plugin_builtin::mos_editor::gutter::{GutterColumn, LineNumbers, DEFAULT_GUTTER};
+use crate::plugin_builtin::mos_editor::syntax::grammars;
+use crate::plugin_builtin::mos_editor::syntax::syntax::Highlighter;
+use crate::system::signs::{self, Sign};
+use crate::system::{event_bus, notifications, theme};
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
-use ratatui::style::{Color, Modifier, Style};
+use ratatui::style::Style;
 use ratatui::text::{Line, Span};
 use ratatui::widgets::Paragraph;
 use ratatui::Frame;
 use ropey::Rope;
+use std::cell::{Cell, RefCell};
 use std::path::PathBuf;
+use std::time::SystemTime;
 
 #[derive(PartialEq, Clone, Ord, Eq, PartialOrd)]
 pub struct Cursor {
     pub line: usize,
     pub column: usize,
     pub goal_column: usize,
+    pub anchor: Option<(usize, usize)>, // where a selection started, it runs from there to the cursor
 }
 
 impl Cursor {
@@ -22,8 +41,24 @@ impl Cursor {
             line,
             column,
             goal_column,
+            anchor: None,
         }
     }
+
+    // A cursor at the end of a selection
+    pub fn selecting(anchor: (usize, usize), line: usize, column: usize) -> Self {
+        Self {
+            anchor: Some(anchor),
+            ..Self::new(line, column, column)
+        }
+    }
+
+    // The start and end of the selection in order, None without one or when it is empty
+    pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.anchor?;
+        let head = (self.line, self.column);
+        (anchor != head).then(|| (anchor.min(head), anchor.max(head)))
+    }
 }
 
 pub enum CursorDirection {
@@ -36,104 +71,744 @@ pub enum CursorDirection {
     WordLeft,
 }
 
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Mode {
     Normal,
     Insert,
 }
 
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Insert => "insert",
+        }
+    }
+}
+
 pub struct EditorPanel {
+    pub id: MosId,
     pub mode: Mode,
     pub rope: Rope,
     pub cursors: Vec<Cursor>,
     pub file_path: Option<PathBuf>,
-    pub scroll_offset: usize,
+    pub scroll_offset: usize, // first line in view
+    pub scroll_row: usize, // and its first wrapped row in view, with soft wrap
+    pub h_scroll: usize, // first column in view, without soft wrap
+    pub soft_wrap: bool,
+    pub scrolloff: usize, // lines kept visible above and below the cursor
+    pub tab_width: usize,
+    pub view_height: Cell<usize>, // rows of text at the last render, 0 before the first one
+    pub view_width: Cell<usize>, // columns of text next to the gutter at the last render
+    pub syntax: RefCell<Option<Highlighter>>, // None for plain text, caches line states while rendering
+    pub folds: Vec<Fold>, // closed ones, by start line
+    pub gutter: Vec<GutterColumn>, // left to right
+    pub line_numbers: LineNumbers,
+    sign_generation: Cell<u64>, // signs::generation() at the last render
+    pub search_prompt: Option<SearchPrompt>, // open while typing after / or ?
+    pub search_highlight: bool, // matches of the last search stay highlighted until esc
+    pub substitute_prompt: Option<SubstitutePrompt>, // open while typing after : or confirming matches
+    pub occurrence: Option<usize>, // start of the occurrence ctrl-n added last, the next one is looked for after it
+    pub register: Option<char>, // picked with "x for the next yank, delete or paste
+    pub last_click: Option<Click>, // for double and triple clicks and where a drag selects from
+    pub count: Option<usize>, // typed before a normal mode command, e.g. 3 in 3@a
+    pub recording: Option<(char, Vec<InputEvent>)>, // the register a macro goes into and its input so far
+    pub last_macro: Option<char>, // replayed again by @@
+    pub macro_depth: usize, // macros replaying inside each other, stops a macro that calls itself
+    pub replay_aborted: bool, // a replayed command failed or went too deep, the rest of the replay is skipped
+    pub command_failed: bool, // set by fail, a replay checks it after each event
+    pub change: Vec<InputEvent>, // input since the editor was last at rest, kept with the transaction once it edited
+    pub change_edited: bool,
+    pub last_change: Vec<InputEvent>, // the input of the last transaction that had some, what . repeats
+    pub history: History, // undo and redo
+    pub text_pending: Option<TextPending>, // an operator or surround waiting for its object or pair
+    pub pending_key: Option<char>, // first key of a two key normal mode command, e.g. z in zz
+    pub title: String,
+    pub read_only: bool,
+    pub modified: bool, // changed since it was opened or saved
+    pub disk_modified: Option<SystemTime>, // the file's mtime when it was last read or written
+    pub disk_conflict: bool, // changed on disk while the buffer had changes, until it is saved or reloaded
+    pub autosave: bool, // saves when the terminal loses focus
+    pub dirty: bool, // needs to be redrawn
+    pub resized: bool, // the view size is only known after the next render, the scroll follows it then
 }
 
 impl EditorPanel {
     pub fn new() -> Self {
         Self {
+            id: MosId::new(),
             mode: Mode::Normal,
             rope: Rope::new(),
             cursors: vec![Cursor::new(0, 0, 0)],
             file_path: None,
             scroll_offset: 0,
+            scroll_row: 0,
+            h_scroll: 0,
+            soft_wrap: false,
+            scrolloff: 5,
+            tab_width: DEFAULT_TAB_WIDTH,
+            view_height: Cell::new(0),
+            view_width: Cell::new(0),
+            syntax: RefCell::new(None),
+            folds: Vec::new(),
+            gutter: DEFAULT_GUTTER.to_vec(),
+            line_numbers: LineNumbers::Absolute,
+            sign_generation: Cell::new(signs::generation()),
+            search_prompt: None,
+            search_highlight: false,
+            substitute_prompt: None,
+            occurrence: None,
+            register: None,
+            last_click: None,
+            count: None,
+            recording: None,
+            last_macro: None,
+            macro_depth: 0,
+            replay_aborted: false,
+            command_failed: false,
+            change: Vec::new(),
+            change_edited: false,
+            last_change: Vec::new(),
+            history: History::default(),
+            text_pending: None,
+            pending_key: None,
+            title: String::from("Editor"),
+            read_only: false,
+            modified: false,
+            disk_modified: None,
+            disk_conflict: false,
+            autosave: false,
+            dirty: true,
+            resized: false,
+        }
+    }
+
+    fn highlight_line(&self, line: usize) -> Vec<Span<'static>> {
+        match self.syntax.borrow_mut().as_mut() {
+            Some(syntax) => syntax.highlight(&self.rope, line),
+            None => self.no_highlight_line(line),
+        }
+    }
+
+    fn no_highlight_line(&self, line: usize) -> Vec<Span<'static>> {
+        vec![Span::raw(Self::line_text_rope(&self.rope, line))]
+    }
+
+    // The cells of one row between display columns start and end, with the style of the span each grapheme came from
+    fn row_spans(layout: &LineLayout, styles: &[Style], row: usize, start: usize, end: usize) -> Vec<Span<'static>> {
+        let mut spans: Vec<Span> = Vec::new();
+
+        for cell in layout.cells.iter().filter(|cell| cell.row == row) {
+            let (left, right) = (cell.x, cell.x + cell.width);
+            if right <= start || left >= end {
+                continue;
+            }
+
+            // A wide grapheme cut by the edge of the view is drawn as the spaces that fit
+            let text = if left < start || right > end {
+                " ".repeat(right.min(end) - left.max(start))
+            } else {
+                cell.text.clone()
+            };
+
+            let style = styles.get(cell.char_idx).copied().unwrap_or_default();
+            match spans.last_mut() {
+                Some(last) if last.style == style => last.content.to_mut().push_str(&text),
+                _ => spans.push(Span::styled(text, style)),
+            }
         }
+
+        spans
+    }
+
+    // The style of every char in the line, from the highlighted spans
+    fn char_styles(spans: &[Span<'static>]) -> Vec<Style> {
+        spans.iter()
+            .flat_map(|span| std::iter::repeat_n(span.style, span.content.chars().count()))
+            .collect()
     }
 
-    fn highlight_line(&self, max_line: usize) -> Vec<Line<'static>> {
-        //if let Some(syntax) = &self.editor.syntax {
-        //    syntax.highlight(
-        //        scroll_offset,
-        //        max_line,
-        //        &rope,
-        //    )
-        //} else {
-        //    Self::no_highlight_line(scroll_offset, max_line, rope)
-        //}
-        self.no_highlight_line(max_line)
+    // The rows in view, each buffer line split into wrapped rows with soft wrap or cut to the view without
+    fn visible_rows(&self, height: usize, width: usize, signs: &[Sign]) -> Vec<Line<'static>> {
+        let mut rows = Vec::new();
+        let mut line = self.scroll_offset;
+        let mut row = self.scroll_row.min(self.line_rows(line).saturating_sub(1));
+        let matcher = self.active_search().and_then(|s| s.matcher().ok());
+        let (match_style, current_style) = (theme::style("ui.search"), theme::style("ui.search.current"));
+        let (replace_style, replace_current_style) = (theme::style("ui.substitute"), theme::style("ui.substitute.current"));
+
+        loop {
+            let mut styles = Self::char_styles(&self.highlight_line(line));
+            if let Some(matcher) = &matcher {
+                for (range, current) in self.search_matches(matcher, line) {
+                    let style = if current { current_style } else { match_style };
+                    for s in styles.iter_mut().take(range.end).skip(range.start) {
+                        *s = s.patch(style);
+                    }
+                }
+            }
+            let selection_style = theme::style("ui.selection");
+            for range in self.cursors.iter().filter_map(|c| self.selection_columns(c, line)) {
+                for s in styles.iter_mut().take(range.end).skip(range.start) {
+                    *s = s.patch(selection_style);
+                }
+            }
+            for (range, current) in self.substitute_matches(line) {
+                let style = if current { replace_current_style } else { replace_style };
+                for s in styles.iter_mut().take(range.end).skip(range.start) {
+                    *s = s.patch(style);
+                }
+            }
+            let layout = self.line_layout(line);
+
+            while row < layout.rows() && rows.len() < height {
+                let (start, end) = if self.soft_wrap {
+                    (0, width)
+                } else {
+                    (self.h_scroll, self.h_scroll + width)
+                };
+
+                let mut row_spans = self.gutter_spans(signs, line, row);
+                row_spans.extend(Self::row_spans(&layout, &styles, row, start, end));
+                if row + 1 == layout.rows()
+                    && let Some(fold) = self.closed_fold_at(line) {
+                    row_spans.push(Span::styled(format!("  ⋯ {} lines", fold.end - fold.start), theme::style("ui.fold")));
+                }
+                rows.push(Line::from(row_spans));
+                row += 1;
+            }
+
+            match self.next_visible_line(line) {
+                Some(next) if rows.len() < height => line = next,
+                _ => break,
+            }
+            row = 0;
+        }
+
+        rows
+    }
+
+    // Where a cursor is drawn relative to the text area, None when it is out of view
+    fn cursor_screen_pos(&self, cursor: &Cursor, height: usize, width: usize) -> Option<(usize, usize)> {
+        let (row, x) = self.line_layout(cursor.line).position(cursor.column);
+        let top = (self.scroll_offset, self.scroll_row);
+        if (cursor.line, row) < top {
+            return None;
+        }
+
+        let y = self.rows_between(top, (cursor.line, row), height);
+        let x = if self.soft_wrap {
+            x
+        } else {
+            x.checked_sub(self.h_scroll)?
+        };
+
+        (y < height && x < width).then_some((x, y))
+    }
+
+    fn move_vertical(&mut self, delta: isize) {
+        if self.soft_wrap {
+            self.move_visual(delta);
+        } else if delta < 0 {
+            self.move_cursor(CursorDirection::Up);
+        } else {
+            self.move_cursor(CursorDirection::Down);
+        }
+    }
+
+    pub fn emit(&self, event: EditorEvent) {
+        event_bus::emit(Event::Editor(event));
+    }
+
+    // A command or motion that couldn't do what it was asked, a macro or . replaying it stops there
+    pub fn fail(&mut self, message: impl Into<String>) {
+        self.command_failed = true;
+        notifications::warning(PLUGIN_ID, message);
+    }
+
+    pub fn fail_error(&mut self, message: impl Into<String>) {
+        self.command_failed = true;
+        notifications::error(PLUGIN_ID, message);
+    }
+
+    fn handle_keys(&mut self, keys: Vec<String>) {
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+        if self.search_prompt.is_some() {
+            self.handle_search_keys(&keys);
+            return;
+        }
+        if self.substitute_prompt.is_some() {
+            self.handle_substitute_keys(&keys);
+            return;
+        }
+
+        // Keys like esc abort an operator still waiting for its object
+        if self.text_pending.take().is_some() {
+            return;
+        }
+
+        match (self.mode, keys.as_slice()) {
+            (_, ["left"]) => self.move_cursor(CursorDirection::Left),
+            (_, ["right"]) => self.move_cursor(CursorDirection::Right),
+            (_, ["up"]) => self.move_vertical(-1),
+            (_, ["down"]) => self.move_vertical(1),
+            (_, ["pagedown"]) => self.scroll(Scroll::PageDown),
+            (_, ["pageup"]) => self.scroll(Scroll::PageUp),
+            (Mode::Normal, ["control", "f"]) => self.scroll(Scroll::PageDown),
+            (Mode::Normal, ["control", "b"]) => self.scroll(Scroll::PageUp),
+            (Mode::Normal, ["control", "d"]) => self.scroll(Scroll::HalfPageDown),
+            (Mode::Normal, ["control", "u"]) => self.scroll(Scroll::HalfPageUp),
+            (Mode::Normal, ["control", "e"]) => self.scroll(Scroll::LineDown),
+            (Mode::Normal, ["control", "y"]) => self.scroll(Scroll::LineUp),
+            (Mode::Insert, ["esc"]) => self.mode = Mode::Normal,
+            (Mode::Insert, ["backspace"]) => self.backspace(),
+            (Mode::Insert, ["enter"]) => self.input('\n'),
+            (Mode::Insert, ["tab"]) => self.input('\t'),
+            // ctrl-d is vim's half page scroll here, mos.keymap({ "control", "d" }, "select_next_occurrence") takes it over
+            (Mode::Normal, ["control", "n"]) => self.select_next_occurrence(),
+            (Mode::Normal, ["control", "x"]) => self.skip_occurrence(),
+            (Mode::Normal, ["alt", "n"]) => self.select_all_occurrences(),
+            (Mode::Normal, ["control", "r"]) if self.read_only => self.fail("Buffer is read-only"),
+            (Mode::Normal, ["control", "r"]) => {
+                let count = self.count.take().unwrap_or(1);
+                self.redo(count);
+            }
+            // Selections go first, then the extra cursors
+            (Mode::Normal, ["esc"]) if self.cursors.iter().any(|c| c.anchor.is_some()) => self.clear_selections(),
+            (Mode::Normal, ["esc"]) => {
+                self.search_highlight = false;
+                self.clear_cursors();
+            }
+            _ => {}
+        }
     }
 
-    fn no_highlight_line(&self, max_line: usize) -> Vec<Line<'static>> {
-        let mut lines_spans: Vec<Line> = Vec::new();
+    fn handle_char(&mut self, ch: char) {
+        if self.search_prompt.is_some() {
+            self.handle_search_char(ch);
+            return;
+        }
+        if self.substitute_prompt.is_some() {
+            self.handle_substitute_char(ch);
+            return;
+        }
 
-        for i in self.scroll_offset..max_line {
-            let rope_line = self.rope.line(i);
-            let text_line = rope_line.to_string();
-            let spans = vec![Span::raw(text_line)];
-            let mut line_spans = vec![Span::styled(format!("{:4} ", i), Style::default().fg(Color::Gray))]; // small gutter
+        if self.mode == Mode::Normal
+            && let Some(pending) = self.text_pending.take() {
+            self.continue_text_pending(pending, ch);
+            return;
+        }
 
-            line_spans.extend(spans);
-            lines_spans.push(Line::from(line_spans));
+        match (self.mode, self.pending_key.take()) {
+            (Mode::Insert, _) => self.input(ch),
+            (Mode::Normal, Some('z')) => match ch {
+                'z' => self.scroll(Scroll::Center),
+                't' => self.scroll(Scroll::Top),
+                'b' => self.scroll(Scroll::Bottom),
+                'h' => self.scroll(Scroll::Left),
+                'l' => self.scroll(Scroll::Right),
+                'a' => self.fold(FoldAction::Toggle),
+                'o' => self.fold(FoldAction::Open),
+                'c' => self.fold(FoldAction::Close),
+                'R' => self.fold(FoldAction::OpenAll),
+                'M' => self.fold(FoldAction::CloseAll),
+                _ => {}
+            },
+            (Mode::Normal, Some('"')) if registers::is_register(ch) => self.register = Some(ch),
+            (Mode::Normal, Some('"')) => self.fail(format!("Unknown register {}", ch)),
+            (Mode::Normal, Some('y')) if ch == 'y' => {
+                let register = self.take_register();
+                self.yank_lines(register);
+            }
+            (Mode::Normal, Some('d')) if ch == 'd' => {
+                let register = self.take_register();
+                self.delete_lines(register);
+            }
+            (Mode::Normal, Some(key @ ('d' | 'c' | 'y'))) if ch == 'i' || ch == 'a' => {
+                let operator = match key {
+                    'd' => Operator::Delete,
+                    'c' => Operator::Change,
+                    _ => Operator::Yank,
+                };
+                self.text_pending = Some(TextPending::Object(operator, ch == 'a'));
+            }
+            (Mode::Normal, Some('y')) if ch == 's' => self.text_pending = Some(TextPending::Operator(Operator::Surround)),
+            (Mode::Normal, Some('d')) if ch == 's' => self.text_pending = Some(TextPending::SurroundDelete),
+            (Mode::Normal, Some('c')) if ch == 's' => self.text_pending = Some(TextPending::SurroundChange),
+            (Mode::Normal, Some('q')) => self.start_recording(ch),
+            (Mode::Normal, Some('@')) => {
+                let count = self.count.take().unwrap_or(1);
+                self.replay_macro(ch, count);
+            }
+            (Mode::Normal, None) if ch.is_ascii_digit() && (ch != '0' || self.count.is_some()) => {
+                let digit = ch.to_digit(10).unwrap_or(0) as usize;
+                self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            }
+            // Only @, . and u take a count so far, the other commands drop it
+            (Mode::Normal, _) => match (self.count.take(), ch) {
+                (count, '@') => {
+                    self.count = count;
+                    self.pending_key = Some('@');
+                }
+                (count, '.') => self.repeat_change(count.unwrap_or(1)),
+                (_, 'u') if self.read_only => self.fail("Buffer is read-only"),
+                (count, 'u') => self.undo(count.unwrap_or(1)),
+                (_, 'q') if self.recording.is_some() => self.stop_recording(),
+                (_, 'q') => self.pending_key = Some('q'),
+                (_, ch) => self.handle_normal_char(ch),
+            },
         }
+    }
 
-        lines_spans
+    fn handle_normal_char(&mut self, ch: char) {
+        match ch {
+            'z' => self.pending_key = Some('z'),
+            // Right after v, i and a pick a text object to select
+            'i' | 'a' if self.cursors.iter().all(|c| c.anchor == Some((c.line, c.column))) => {
+                self.text_pending = Some(TextPending::Object(Operator::Select, ch == 'a'));
+            }
+            'i' if self.read_only => self.fail("Buffer is read-only"),
+            'i' => {
+                self.clear_selections();
+                self.mode = Mode::Insert;
+            }
+            'v' => self.toggle_selection(),
+            '"' => self.pending_key = Some('"'),
+            'y' if self.has_selection() => {
+                let register = self.take_register();
+                self.yank(register);
+            }
+            'y' => self.pending_key = Some('y'),
+            'd' | 'c' | 'p' | 'P' | 'S' if self.read_only => self.fail("Buffer is read-only"),
+            'd' if self.has_selection() => {
+                let register = self.take_register();
+                self.cut_selections(register);
+            }
+            'd' => self.pending_key = Some('d'),
+            'p' | 'P' => {
+                let register = self.take_register();
+                self.paste(register, ch == 'P');
+            }
+            'c' if self.has_selection() => {
+                let register = self.take_register();
+                self.cut_selections(register);
+                self.mode = Mode::Insert;
+            }
+            'c' => self.pending_key = Some('c'),
+            'S' if self.has_selection() => self.text_pending = Some(TextPending::SurroundSelections),
+            'h' => self.move_cursor(CursorDirection::Left),
+            'j' => self.move_vertical(1),
+            'k' => self.move_vertical(-1),
+            'l' => self.move_cursor(CursorDirection::Right),
+            '/' => self.start_search(SearchDirection::Forward),
+            '?' => self.start_search(SearchDirection::Backward),
+            'n' => self.search_next(false),
+            'N' => self.search_next(true),
+            ':' => self.start_substitute(),
+            _ => {}
+        }
+    }
+
+    fn handle_command(&mut self, name: &str, args: &[String]) {
+        match name {
+            // scroll <line_down|line_up|half_page_down|half_page_up|page_down|page_up|center|top|bottom>
+            "scroll" => match args.first().and_then(|a| Scroll::from_name(a)) {
+                Some(scroll) => self.scroll(scroll),
+                None => self.fail_error(format!("Unknown scroll '{}'", args.first().cloned().unwrap_or_default())),
+            },
+            "wrap" => match args.first().map(|a| a.as_str()) {
+                Some("on") => self.set_soft_wrap(true),
+                Some("off") => self.set_soft_wrap(false),
+                _ => self.set_soft_wrap(!self.soft_wrap),
+            },
+            // gutter [signs] [numbers] [folds], the columns left to right, none hides the gutter
+            "gutter" => {
+                match args.iter().map(|a| GutterColumn::from_name(a).ok_or(a)).collect::<Result<Vec<_>, _>>() {
+                    Ok(columns) => self.gutter = columns,
+                    Err(name) => self.fail_error(format!("Unknown gutter column '{}'", name)),
+                }
+            }
+            "line_numbers" => match args.first().and_then(|a| LineNumbers::from_name(a)) {
+                Some(mode) => self.line_numbers = mode,
+                None => self.fail_error("line_numbers expects absolute, relative, hybrid or off"),
+            },
+            "fold" => match args.first().and_then(|a| FoldAction::from_name(a)) {
+                Some(action) => self.fold(action),
+                None => self.fail_error("fold expects toggle, open, close, open_all or close_all"),
+            },
+            // search <pattern> [literal|regex] [forward|backward]
+            "search" => {
+                let Some(query) = args.first().filter(|q| !q.is_empty()) else {
+                    self.fail_error("search expects a pattern");
+                    return;
+                };
+                let option = |name: &str| args.iter().skip(1).any(|a| a == name);
+                self.search_for(Search {
+                    query: query.clone(),
+                    regex: option("regex"),
+                    direction: if option("backward") { SearchDirection::Backward } else { SearchDirection::Forward },
+                });
+            }
+            "search_next" => self.search_next(false),
+            "search_prev" => self.search_next(true),
+            "search_clear" => self.search_highlight = false,
+            // yank [register], the selections or else the lines of the cursors
+            "yank" => self.yank(args.first().and_then(|a| a.chars().next()).unwrap_or(UNNAMED)),
+            // paste [register] [before]
+            "paste" if self.read_only => self.fail("Buffer is read-only"),
+            "paste" => {
+                let register = args.first().and_then(|a| a.chars().next()).unwrap_or(UNNAMED);
+                self.paste(register, args.get(1).is_some_and(|a| a == "before"));
+            }
+            // One register per line, the toast only has room for the count, the messages panel shows them all
+            "registers" => {
+                let lines: Vec<String> = registers::list().into_iter()
+                    .map(|(name, register)| format!("\"{}  {}", name, register.text().escape_debug()))
+                    .collect();
+                if lines.is_empty() {
+                    notifications::info(PLUGIN_ID, "All registers are empty");
+                } else {
+                    notifications::info(PLUGIN_ID, format!("{} registers\n{}", lines.len(), lines.join("\n")));
+                }
+            }
+            // record_macro <register>, or stops the recording going on
+            "record_macro" => match (&self.recording, args.first().and_then(|a| a.chars().next())) {
+                (Some(_), _) => self.stop_recording(),
+                (None, Some(name)) => self.start_recording(name),
+                (None, None) => self.fail_error("record_macro needs a register"),
+            },
+            // replay_macro <register> [count]
+            "replay_macro" => match args.first().and_then(|a| a.chars().next()) {
+                Some(name) => self.replay_macro(name, args.get(1).and_then(|a| a.parse().ok()).unwrap_or(1)),
+                None => self.fail_error("replay_macro needs a register"),
+            },
+            // macro <register> <keys>, writes a macro as text, e.g. macro a A;<esc>j
+            "macro" => match args.split_first() {
+                Some((name, keys)) if name.chars().count() == 1 && name.chars().all(|c| c.is_ascii_alphabetic()) => {
+                    let name = name.chars().next().unwrap_or('a');
+                    let text = macros::encode(&macros::decode(&keys.join(" ")));
+                    registers::store(name, registers::Register::new(vec![text], false));
+                }
+                _ => self.fail_error("macro needs a register a to z and its keys"),
+            },
+            "repeat_change" => self.repeat_change(1),
+            // text_object <delete|change|yank|select> <inner|around> <object>, e.g. text_object change inner "
+            "text_object" => {
+                let operator = match args.first().map(|a| a.as_str()) {
+                    Some("delete") => Some(Operator::Delete),
+                    Some("change") => Some(Operator::Change),
+                    Some("yank") => Some(Operator::Yank),
+                    Some("select") => Some(Operator::Select),
+                    _ => None,
+                };
+                let around = args.get(1).map(|a| a.as_str());
+                let object = args.get(2).and_then(|a| a.chars().next());
+                match (operator, around, object) {
+                    (Some(operator), Some(scope @ ("inner" | "around")), Some(object)) => self.apply_text_object(operator, scope == "around", object),
+                    _ => self.fail_error("text_object needs delete, change, yank or select, inner or around and an object"),
+                }
+            }
+            "select_next_occurrence" => self.select_next_occurrence(),
+            "skip_occurrence" => self.skip_occurrence(),
+            "select_all_occurrences" => self.select_all_occurrences(),
+            "cursors_from_search" => self.cursors_from_search(),
+            "cursors_at_line_ends" => self.cursors_at_line_ends(),
+            "align_cursors" if self.read_only => self.fail("Buffer is read-only"),
+            "align_cursors" => self.align_cursors(),
+            // substitute [range]s/pattern/replacement/[flags], the args are joined back with spaces
+            "substitute" if args.is_empty() => self.start_substitute(),
+            "substitute" => self.substitute(&args.join(" ")),
+            "tab_width" => match args.first().and_then(|n| n.parse::<usize>().ok()).filter(|n| *n > 0) {
+                Some(width) => {
+                    self.tab_width = width;
+                    self.update_goal_columns();
+                    self.scroll_to_cursor();
+                }
+                None => self.fail_error("tab_width expects a number of columns"),
+            },
+            "scrolloff" => match args.first().and_then(|n| n.parse().ok()) {
+                Some(lines) => {
+                    self.scrolloff = lines;
+                    self.scroll_to_cursor();
+                }
+                None => self.fail_error("scrolloff expects a number of lines"),
+            },
+            // open <path> [line] [column], line and column start at 1
+            "open" => {
+                let Some(path) = args.first() else {
+                    return;
+                };
+                if let Err(e) = self.open_file(PathBuf::from(path)) {
+                    self.fail_error(e);
+                    return;
+                }
+
+                let position = |i: usize| args.get(i).and_then(|n| n.parse::<usize>().ok()).map(|n| n.saturating_sub(1));
+                if let Some(line) = position(1) {
+                    self.goto(line, position(2).unwrap_or(0));
+                }
+            }
+            // reload, drops unsaved changes
+            "reload" => {
+                if let Err(e) = self.reload() {
+                    self.fail_error(e);
+                }
+            }
+            "undo" | "redo" if self.read_only => self.fail("Buffer is read-only"),
+            "undo" => self.undo(args.first().and_then(|a| a.parse().ok()).unwrap_or(1)),
+            "redo" => self.redo(args.first().and_then(|a| a.parse().ok()).unwrap_or(1)),
+            // syntax [name|off|auto], without a name shows the current one
+            "syntax" => match args.first().map(|a| a.as_str()) {
+                Some("auto") => self.detect_syntax(),
+                Some("off") => *self.syntax.get_mut() = None,
+                Some(name) => match grammars::by_name(name) {
+                    Some(grammar) => *self.syntax.get_mut() = Some(Highlighter::new(grammar)),
+                    None => self.fail_error(format!("No syntax {}, available: {}", name, grammars::GRAMMARS.iter().map(|g| g.name).collect::<Vec<_>>().join(", "))),
+                },
+                None => notifications::info(PLUGIN_ID, format!("Syntax {}", self.syntax.borrow().as_ref().map(|s| s.grammar.name).unwrap_or("off"))),
+            },
+            // autosave [on|off], saves when the terminal loses focus
+            "autosave" => self.autosave = !matches!(args.first().map(|a| a.as_str()), Some("off")),
+            "set_text" => self.set_text(args.first().map(|t| t.as_str()).unwrap_or("")),
+            "read_only" => {
+                self.read_only = !matches!(args.first().map(|a| a.as_str()), Some("off"));
+                if self.read_only {
+                    self.mode = Mode::Normal;
+                }
+            }
+            "save" if self.read_only => self.fail_error("Buffer is read-only"),
+            "save" => {
+                if let Some(path) = args.first() {
+                    self.file_path = Some(PathBuf::from(path));
+                }
+                match self.save_file() {
+                    Ok(()) => notifications::success(PLUGIN_ID, format!("Saved {}", self.file_path.as_ref().map(|p| p.display().to_string()).unwrap_or_default())),
+                    Err(e) => self.fail_error(format!("Failed to save file: {}", e)),
+                }
+            }
+            _ => {}
+        }
     }
 }
 
 impl Panel for EditorPanel {
     fn id(&self) -> MosId {
-        MosId::new()
+        self.id
     }
 
     fn title(&self) -> &str {
-        "Editor"
+        &self.title
+    }
+
+    fn kind(&self) -> &str {
+        "editor_panel"
     }
 
     fn handle_event(&mut self, event: Event) {
+        let cursor_before = self.cursors.first().cloned();
+        let mode_before = self.mode;
+
         match event {
             Event::Input(input) => {
-                match input {
+                let context = self.before_input(&input);
+                match input.clone() {
                     InputEvent::Keyboard(keys) => {
-                        
+                        self.handle_keys(keys);
                     }
                     InputEvent::Char(char) => {
-                        self.input(char);
+                        self.handle_char(char);
                     }
-                    _ => {
-
+                    InputEvent::Mouse(mouse) => {
+                        self.handle_mouse(mouse);
+                    }
+                    InputEvent::Paste(text) if self.search_prompt.is_some() || self.substitute_prompt.is_some() => {
+                        for ch in text.chars().filter(|c| *c != '\n' && *c != '\r') {
+                            self.handle_char(ch);
+                        }
                     }
+                    InputEvent::Paste(text) => self.paste_text(text),
                 }
+                self.after_input(input, context);
+            }
+            Event::Command(name, args) => self.handle_command(&name, &args),
+            Event::Resize(..) => self.resized = true,
+            Event::FocusGained => self.check_disk(),
+            Event::FocusLost => self.autosave(),
+            _ => return,
+        }
+
+        self.dirty = true;
+        self.commit_transaction();
+
+        if self.mode != mode_before {
+            self.emit(EditorEvent::ModeChanged { panel: self.id, mode: self.mode.name().to_string() });
+        }
+
+        // Only cursor movement scrolls, so the mouse wheel can look around without the view jumping back
+        if let Some(cursor) = self.cursors.first().cloned()
+            && Some(&cursor) != cursor_before.as_ref() {
+            self.scroll_to_cursor();
+            self.emit(EditorEvent::CursorMoved {
+                panel: self.id,
+                path: self.file_path.clone(),
+                line: cursor.line,
+                column: cursor.column,
+            });
+        }
+    }
+
+    fn status(&self) -> PanelStatus {
+        PanelStatus {
+            mode: Some(self.mode.name().to_string()),
+            file: Some(self.file_path.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| String::from("[No Name]"))),
+            modified: self.modified,
+            read_only: self.read_only,
+            position: self.cursors.first().map(|c| (c.line, c.column)),
+            cursors: self.cursors.len(),
+            encoding: Some(String::from("utf-8")), // ropey buffers are always utf-8
+            line_ending: Some(self.line_ending().to_string()),
+        }
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty || signs::generation() != self.sign_generation.get()
+    }
+
+    fn clear_dirty(&mut self) {
+        self.dirty = false;
+
+        // Rendered at the new size, keep the cursor in view and draw again if that scrolled
+        if std::mem::take(&mut self.resized) {
+            let scroll = (self.scroll_offset, self.scroll_row, self.h_scroll);
+            self.scroll_to_cursor();
+            if scroll != (self.scroll_offset, self.scroll_row, self.h_scroll) {
+                self.dirty = true;
+                event_bus::wake();
             }
-            _ => {}
         }
     }
 
     fn render(&self, frame: &mut Frame, area: Rect) {
         //frame.render_widget(ratatui::widgets::Paragraph::new("self.rope.to_string()"), area);
 
+        // The search or substitute prompt takes the bottom row while it is open
+        let prompt = self.search_prompt_line().or_else(|| self.substitute_prompt_line());
+        let prompt_height = if prompt.is_some() { 1 } else { 0 };
         let chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(100)].as_ref())
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(prompt_height)].as_ref())
             .split(area);
 
-        let height = std::cmp::max(1, chunks[0].height as usize - 1);
+        self.sign_generation.set(signs::generation());
+        let signs = self.signs();
+        let gutter_width = self.gutter_width_with(&signs);
 
-        let max_line = std::cmp::min(
-            self.rope.len_lines(),
-            self.scroll_offset.saturating_add(height),
-        );
+        let height = std::cmp::max(1, chunks[0].height as usize);
+        let width = std::cmp::max(1, (chunks[0].width as usize).saturating_sub(gutter_width));
+        self.view_height.set(height);
+        self.view_width.set(width);
 
-        let lines_spans: Vec<Line> = self.highlight_line(max_line);
+        let lines_spans: Vec<Line> = self.visible_rows(height, width, &signs);
 
         // Have to think about how I can to the multiple editor panels later, block should be set from outside, not in editor panel
         let paragraph = Paragraph::new(lines_spans);
@@ -142,17 +817,20 @@ impl Panel for EditorPanel {
 
         frame.render_widget(paragraph, chunks[0]);
 
-        for cursor in &self.cursors {
-            let x = chunks[0].x + 5 + cursor.column as u16; // 5 for gutter
-            let y = chunks[0].y + (cursor.line.saturating_sub(self.scroll_offset)) as u16;
+        let cursor = theme::style("ui.cursor");
+        for (x, y) in self.cursors.iter().filter_map(|c| self.cursor_screen_pos(c, height, width)) {
+            let x = chunks[0].x + (gutter_width + x) as u16;
+            let y = chunks[0].y + y as u16;
             frame.render_widget(
-                Paragraph::new("")
-                    .style(Style::default()
-                        .add_modifier(Modifier::REVERSED)
-                        .fg(Color::White)),
-                //.fg(if panel.active { Color::White } else { Color::DarkGray })),
+                Paragraph::new("").style(cursor),
                 Rect::new(x, y, 1, 1),
             );
         }
+
+        if let Some((line, cursor_x)) = prompt {
+            frame.render_widget(Paragraph::new(line), chunks[1]);
+            let x = chunks[1].x + (cursor_x as u16).min(chunks[1].width.saturating_sub(1));
+            frame.render_widget(Paragraph::new("").style(cursor), Rect::new(x, chunks[1].y, 1, 1));
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,179 @@
+use crate::plugin_builtin::mos_editor::editor_panel::EditorPanel;
+
+// A closed fold, the first line stays in view and the rest up to end is hidden
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fold {
+    pub start: usize,
+    pub end: usize, // inclusive
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FoldAction {
+    Toggle, // za
+    Open, // zo
+    Close, // zc
+    OpenAll, // zR
+    CloseAll, // zM
+}
+
+impl FoldAction {
+    pub fn from_name(name: &str) -> Option<FoldAction> {
+        match name {
+            "toggle" => Some(FoldAction::Toggle),
+            "open" => Some(FoldAction::Open),
+            "close" => Some(FoldAction::Close),
+            "open_all" => Some(FoldAction::OpenAll),
+            "close_all" => Some(FoldAction::CloseAll),
+            _ => None,
+        }
+    }
+}
+
+impl EditorPanel {
+    // Leading whitespace in display columns, None for a blank line
+    fn indent(&self, line: usize) -> Option<usize> {
+        let text = Self::line_text_rope(&self.rope, line);
+        let trimmed = text.trim_start();
+        if trimmed.is_empty() {
+            return None;
+        }
+
+        let prefix = &text[..text.len() - trimmed.len()];
+        Some(prefix.chars().map(|c| if c == '\t' { self.tab_width } else { 1 }).sum())
+    }
+
+    // The lines a fold starting here would hide, the block indented deeper than this line without trailing blank lines
+    pub fn fold_range(&self, line: usize) -> Option<Fold> {
+        let indent = self.indent(line)?;
+        let mut end = None;
+
+        for next in line + 1..self.rope.len_lines() {
+            match self.indent(next) {
+                Some(next_indent) if next_indent > indent => end = Some(next),
+                Some(_) => break,
+                None => {}
+            }
+        }
+
+        end.map(|end| Fold { start: line, end })
+    }
+
+    // Cheaper than fold_range, only looks at the next line that isn't blank
+    pub fn is_foldable(&self, line: usize) -> bool {
+        let Some(indent) = self.indent(line) else {
+            return false;
+        };
+        (line + 1..self.rope.len_lines())
+            .find_map(|next| self.indent(next))
+            .is_some_and(|next_indent| next_indent > indent)
+    }
+
+    // The innermost foldable block around a line, a line that starts a block folds that block
+    fn enclosing_fold_range(&self, line: usize) -> Option<Fold> {
+        if self.is_foldable(line) {
+            return self.fold_range(line);
+        }
+
+        // A blank line belongs to the block of the next line that isn't
+        let indent = (line..self.rope.len_lines()).find_map(|l| self.indent(l))?;
+        (0..line).rev()
+            .find(|l| self.indent(*l).is_some_and(|i| i < indent))
+            .and_then(|start| self.fold_range(start))
+            .filter(|fold| fold.end >= line)
+    }
+
+    pub fn closed_fold_at(&self, line: usize) -> Option<Fold> {
+        self.folds.iter().find(|f| f.start == line).copied()
+    }
+
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.folds.iter().any(|f| line > f.start && line <= f.end)
+    }
+
+    // The line a hidden line is folded into, the start of the outermost closed fold around it
+    pub fn visible_line(&self, line: usize) -> usize {
+        self.folds.iter()
+            .filter(|f| line > f.start && line <= f.end)
+            .map(|f| f.start)
+            .min()
+            .unwrap_or(line)
+    }
+
+    pub fn next_visible_line(&self, line: usize) -> Option<usize> {
+        (line + 1..self.rope.len_lines()).find(|l| !self.is_hidden(*l))
+    }
+
+    pub fn prev_visible_line(&self, line: usize) -> Option<usize> {
+        (0..line).rev().find(|l| !self.is_hidden(*l))
+    }
+
+    // Visible lines from one line to a later one, for relative line numbers
+    pub fn visible_lines_between(&self, from: usize, to: usize) -> usize {
+        (from.min(to)..from.max(to)).filter(|l| !self.is_hidden(*l + 1)).count()
+    }
+
+    // Opens every closed fold that hides a line, e.g. after jumping into one
+    pub fn reveal_line(&mut self, line: usize) {
+        self.folds.retain(|f| line <= f.start || line > f.end);
+    }
+
+    fn close_fold(&mut self, line: usize) {
+        let Some(fold) = self.enclosing_fold_range(line) else {
+            return;
+        };
+
+        if !self.folds.contains(&fold) {
+            self.folds.push(fold);
+            self.folds.sort_by_key(|f| f.start);
+        }
+
+        for c in self.cursors.iter_mut().filter(|c| c.line > fold.start && c.line <= fold.end) {
+            c.line = fold.start;
+            c.column = 0;
+        }
+        self.dedup_cursors();
+    }
+
+    pub fn fold(&mut self, action: FoldAction) {
+        let line = self.cursors.first().map(|c| c.line).unwrap_or(0);
+
+        match action {
+            FoldAction::Open => self.folds.retain(|f| f.start != line),
+            FoldAction::Close => self.close_fold(line),
+            FoldAction::Toggle if self.closed_fold_at(line).is_some() => self.folds.retain(|f| f.start != line),
+            FoldAction::Toggle => self.close_fold(line),
+            FoldAction::OpenAll => self.folds.clear(),
+            FoldAction::CloseAll => {
+                self.folds.clear();
+                let mut line = 0;
+                while line < self.rope.len_lines() {
+                    match self.fold_range(line) {
+                        Some(fold) => {
+                            self.folds.push(fold);
+                            line = fold.end + 1;
+                        }
+                        None => line += 1,
+                    }
+                }
+                for c in self.cursors.iter_mut() {
+                    let visible = self.folds.iter().find(|f| c.line > f.start && c.line <= f.end).map(|f| f.start);
+                    if let Some(start) = visible {
+                        c.line = start;
+                        c.column = 0;
+                    }
+                }
+                self.dedup_cursors();
+            }
+        }
+    }
+
+    // Keeps folds on their lines after an edit at line changed the line count by delta, a fold that was edited inside opens
+    pub fn shift_folds(&mut self, line: usize, delta: isize) {
+        let edited = |f: &Fold| (line > f.start && line <= f.end) || (line == f.start && delta != 0);
+        self.folds.retain(|f| !edited(f));
+        for fold in self.folds.iter_mut().filter(|f| f.start > line) {
+            fold.start = fold.start.saturating_add_signed(delta);
+            fold.end = fold.end.saturating_add_signed(delta);
+        }
+    }
+}
\ No newline at end of file
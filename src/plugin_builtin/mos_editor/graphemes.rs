@@ -0,0 +1,215 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+// A grapheme as it is drawn, tabs become spaces and control characters a replacement character
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    pub char_idx: usize, // column of its first char in the line
+    pub text: String,
+    pub row: usize, // wrapped row, 0 without soft wrap
+    pub x: usize, // display column within the row
+    pub width: usize,
+}
+
+// Where the graphemes of one line go on screen, columns are chars and positions are display cells
+pub struct LineLayout {
+    pub cells: Vec<Cell>,
+    pub len: usize, // chars in the line, without the line break
+    pub end: (usize, usize), // row and x of a cursor after the last grapheme
+}
+
+fn is_control(grapheme: &str) -> bool {
+    grapheme.chars().all(|c| c.is_control())
+}
+
+impl LineLayout {
+    // Lays out a line without its line break, wrapping at wrap_width when soft wrap is on
+    pub fn new(line: &str, tab_width: usize, wrap_width: Option<usize>) -> Self {
+        let tab_width = tab_width.max(1);
+        let mut cells = Vec::new();
+        let (mut row, mut x, mut char_idx) = (0, 0, 0);
+
+        for grapheme in line.graphemes(true) {
+            let (text, width) = if grapheme == "\t" {
+                let width = tab_width - x % tab_width;
+                (" ".repeat(width), width)
+            } else if is_control(grapheme) {
+                (String::from("\u{fffd}"), 1)
+            } else {
+                (grapheme.to_string(), grapheme.width())
+            };
+
+            if let Some(wrap_width) = wrap_width
+                && x > 0
+                && x + width > wrap_width {
+                row += 1;
+                x = 0;
+            }
+
+            cells.push(Cell { char_idx, text, row, x, width });
+            x += width;
+            char_idx += grapheme.chars().count();
+        }
+
+        let end = match wrap_width {
+            Some(wrap_width) if x >= wrap_width => (row + 1, 0),
+            _ => (row, x),
+        };
+
+        Self { cells, len: char_idx, end }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.end.0 + 1
+    }
+
+    // Row and x of the grapheme a column falls in, or the end of the line past it
+    pub fn position(&self, column: usize) -> (usize, usize) {
+        self.cells.iter()
+            .take_while(|cell| cell.char_idx <= column)
+            .last()
+            .filter(|_| column < self.len)
+            .map(|cell| (cell.row, cell.x))
+            .unwrap_or(self.end)
+    }
+
+    // The column at or left of x in a row, the end of the line only on its last row
+    pub fn column_at(&self, row: usize, x: usize) -> usize {
+        if row >= self.end.0 && x >= self.end.1 {
+            return self.len;
+        }
+
+        self.cells.iter()
+            .rev()
+            .find(|cell| cell.row == row && cell.x <= x)
+            .or_else(|| self.cells.iter().find(|cell| cell.row == row))
+            .map(|cell| cell.char_idx)
+            .unwrap_or(self.len)
+    }
+
+    // The grapheme boundary after column
+    pub fn next_column(&self, column: usize) -> usize {
+        self.cells.iter()
+            .map(|cell| cell.char_idx)
+            .find(|&idx| idx > column)
+            .unwrap_or(self.len)
+    }
+
+    // The grapheme boundary before column
+    pub fn prev_column(&self, column: usize) -> usize {
+        self.cells.iter()
+            .map(|cell| cell.char_idx)
+            .take_while(|&idx| idx < column)
+            .last()
+            .unwrap_or(0)
+    }
+
+    // Moves a column that points into the middle of a grapheme back to its start
+    pub fn snap(&self, column: usize) -> usize {
+        if column >= self.len {
+            self.len
+        } else {
+            self.prev_column(column + 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(layout: &LineLayout) -> Vec<(usize, &str, usize, usize)> {
+        layout.cells.iter().map(|c| (c.char_idx, c.text.as_str(), c.row, c.x)).collect()
+    }
+
+    #[test]
+    fn expands_tabs_to_the_next_stop() {
+        let layout = LineLayout::new("a\tb\t", 4, None);
+        assert_eq!(cells(&layout), vec![(0, "a", 0, 0), (1, "   ", 0, 1), (2, "b", 0, 4), (3, "   ", 0, 5)]);
+        assert_eq!(layout.end, (0, 8));
+
+        let layout = LineLayout::new("\t\tx", 2, None);
+        assert_eq!(layout.position(2), (0, 4));
+        assert_eq!(LineLayout::new("\tx", 0, None).position(1), (0, 1));
+    }
+
+    #[test]
+    fn measures_wide_and_combined_graphemes() {
+        let layout = LineLayout::new("日本", 4, None);
+        assert_eq!(cells(&layout), vec![(0, "日", 0, 0), (1, "本", 0, 2)]);
+        assert_eq!(layout.end, (0, 4));
+
+        let layout = LineLayout::new("a😀b", 4, None);
+        assert_eq!(layout.position(2), (0, 3));
+
+        // e and a combining acute accent are one grapheme of two chars
+        let layout = LineLayout::new("e\u{301}x", 4, None);
+        assert_eq!(cells(&layout), vec![(0, "e\u{301}", 0, 0), (2, "x", 0, 1)]);
+        assert_eq!(layout.len, 3);
+        assert_eq!(layout.position(1), (0, 0));
+
+        let layout = LineLayout::new("a\u{1}", 4, None);
+        assert_eq!(cells(&layout)[1], (1, "\u{fffd}", 0, 1));
+    }
+
+    #[test]
+    fn moves_by_graphemes() {
+        let layout = LineLayout::new("e\u{301}x日", 4, None);
+        assert_eq!(layout.next_column(0), 2);
+        assert_eq!(layout.next_column(1), 2);
+        assert_eq!(layout.next_column(3), 4);
+        assert_eq!(layout.prev_column(4), 3);
+        assert_eq!(layout.prev_column(2), 0);
+        assert_eq!(layout.prev_column(0), 0);
+        assert_eq!(layout.snap(1), 0);
+        assert_eq!(layout.snap(2), 2);
+        assert_eq!(layout.snap(9), 4);
+    }
+
+    #[test]
+    fn finds_the_column_at_a_position() {
+        let layout = LineLayout::new("日本x", 4, None);
+        assert_eq!(layout.column_at(0, 0), 0);
+        assert_eq!(layout.column_at(0, 1), 0);
+        assert_eq!(layout.column_at(0, 3), 1);
+        assert_eq!(layout.column_at(0, 4), 2);
+        assert_eq!(layout.column_at(0, 5), 3);
+        assert_eq!(layout.column_at(0, 50), 3);
+        assert_eq!(layout.position(9), (0, 5));
+    }
+
+    #[test]
+    fn wraps_rows_at_the_width() {
+        let layout = LineLayout::new("abcdef", 4, Some(4));
+        assert_eq!(layout.rows(), 2);
+        assert_eq!(layout.position(4), (1, 0));
+        assert_eq!(layout.end, (1, 2));
+        assert_eq!(layout.column_at(0, 10), 3);
+        assert_eq!(layout.column_at(1, 1), 5);
+        assert_eq!(layout.column_at(1, 5), 6);
+
+        // A cursor after a full last row goes on a row of its own
+        let layout = LineLayout::new("abcd", 4, Some(4));
+        assert_eq!(layout.rows(), 2);
+        assert_eq!(layout.position(4), (1, 0));
+        assert_eq!(layout.column_at(0, 3), 3);
+
+        assert_eq!(LineLayout::new("", 4, Some(4)).rows(), 1);
+    }
+
+    #[test]
+    fn wraps_a_wide_grapheme_that_doesnt_fit() {
+        let layout = LineLayout::new("abc日d", 4, Some(4));
+        assert_eq!(cells(&layout)[3], (3, "日", 1, 0));
+        assert_eq!(layout.position(4), (1, 2));
+        assert_eq!(layout.column_at(0, 3), 2);
+        assert_eq!(layout.column_at(1, 1), 3);
+
+        // Wider than the view it still gets a row, nothing is lost
+        let layout = LineLayout::new("日日", 4, Some(1));
+        assert_eq!(cells(&layout), vec![(0, "日", 0, 0), (1, "日", 1, 0)]);
+        assert_eq!(layout.rows(), 3);
+    }
+}
\ No newline at end of file
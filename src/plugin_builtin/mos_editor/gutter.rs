@@ -0,0 +1,143 @@
+use crate::plugin_builtin::mos_editor::editor_panel::EditorPanel;
+use crate::system::signs::{self, Sign};
+use crate::system::theme;
+use ratatui::text::Span;
+use unicode_width::UnicodeWidthStr;
+
+const MIN_NUMBER_WIDTH: usize = 3;
+const SIGN_WIDTH: usize = 2;
+const FOLD_WIDTH: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative, // distance to the cursor line, which shows 0
+    Hybrid, // relative, with the cursor line absolute
+}
+
+impl LineNumbers {
+    pub fn from_name(name: &str) -> Option<LineNumbers> {
+        match name {
+            "off" => Some(LineNumbers::Off),
+            "absolute" => Some(LineNumbers::Absolute),
+            "relative" => Some(LineNumbers::Relative),
+            "hybrid" => Some(LineNumbers::Hybrid),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GutterColumn {
+    Signs, // only takes room while the file has signs
+    Numbers,
+    Folds,
+}
+
+impl GutterColumn {
+    pub fn from_name(name: &str) -> Option<GutterColumn> {
+        match name {
+            "signs" => Some(GutterColumn::Signs),
+            "numbers" => Some(GutterColumn::Numbers),
+            "folds" => Some(GutterColumn::Folds),
+            _ => None,
+        }
+    }
+}
+
+pub const DEFAULT_GUTTER: [GutterColumn; 3] = [GutterColumn::Signs, GutterColumn::Numbers, GutterColumn::Folds];
+
+// Pads or cuts text to exactly width cells
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    for c in text.chars() {
+        if fitted.width() + c.to_string().width() > width {
+            break;
+        }
+        fitted.push(c);
+    }
+    let padding = width - fitted.width();
+    fitted + &" ".repeat(padding)
+}
+
+impl EditorPanel {
+    // The signs plugins set on the buffer's file, one per line
+    pub fn signs(&self) -> Vec<Sign> {
+        self.file_path.as_deref().map(signs::signs_for).unwrap_or_default()
+    }
+
+    // Grows with the line count so numbers stay aligned
+    fn number_width(&self) -> usize {
+        self.rope.len_lines().to_string().len().max(MIN_NUMBER_WIDTH)
+    }
+
+    fn column_width(&self, column: GutterColumn, signs: &[Sign]) -> usize {
+        match column {
+            GutterColumn::Signs if signs.is_empty() => 0,
+            GutterColumn::Signs => SIGN_WIDTH,
+            GutterColumn::Numbers if self.line_numbers == LineNumbers::Off => 0,
+            GutterColumn::Numbers => self.number_width() + 1,
+            GutterColumn::Folds => FOLD_WIDTH,
+        }
+    }
+
+    pub fn gutter_width_with(&self, signs: &[Sign]) -> usize {
+        self.gutter.iter().map(|c| self.column_width(*c, signs)).sum()
+    }
+
+    // Where the text starts, right of the gutter
+    pub fn gutter_width(&self) -> usize {
+        self.gutter_width_with(&self.signs())
+    }
+
+    fn line_number(&self, line: usize) -> Span<'static> {
+        let width = self.number_width();
+        let cursor_line = self.cursors.first().map(|c| c.line).unwrap_or(0);
+        let relative = self.visible_lines_between(line, cursor_line);
+
+        let text = match self.line_numbers {
+            LineNumbers::Hybrid if line == cursor_line => format!("{:<width$} ", line + 1),
+            LineNumbers::Relative | LineNumbers::Hybrid => format!("{:>width$} ", relative),
+            _ => format!("{:>width$} ", line + 1),
+        };
+        let group = if line == cursor_line { "ui.gutter.current" } else { "ui.gutter" };
+        Span::styled(text, theme::style(group))
+    }
+
+    // The gutter next to one row, continuation rows of a wrapped line only get the wrap marker
+    pub fn gutter_spans(&self, signs: &[Sign], line: usize, row: usize) -> Vec<Span<'static>> {
+        let mut spans = Vec::new();
+
+        for column in self.gutter.iter().copied() {
+            let width = self.column_width(column, signs);
+            if width == 0 {
+                continue;
+            }
+
+            let span = match column {
+                GutterColumn::Signs => match signs.iter().find(|s| s.line == line).filter(|_| row == 0) {
+                    Some(sign) => Span::styled(fit(&sign.text, width), theme::style(&sign.group)),
+                    None => Span::raw(" ".repeat(width)),
+                },
+                GutterColumn::Numbers if row == 0 => self.line_number(line),
+                GutterColumn::Numbers => Span::styled(format!("{:>w$} ", "↪", w = width - 1), theme::style("ui.gutter.wrap")),
+                GutterColumn::Folds => {
+                    let marker = if row > 0 {
+                        " "
+                    } else if self.closed_fold_at(line).is_some() {
+                        "▸"
+                    } else if self.is_foldable(line) {
+                        "▾"
+                    } else {
+                        " "
+                    };
+                    Span::styled(fit(marker, width), theme::style("ui.gutter.fold"))
+                }
+            };
+            spans.push(span);
+        }
+
+        spans
+    }
+}
\ No newline at end of file
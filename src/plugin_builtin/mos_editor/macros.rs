@@ -0,0 +1,294 @@
+use crate::event::event::{Event, InputEvent};
+use crate::panel::panel::Panel;
+use crate::plugin_builtin::mos_editor::editor_panel::{EditorPanel, Mode};
+use crate::plugin_builtin::mos_editor::mos_editor::PLUGIN_ID;
+use crate::plugin_builtin::mos_editor::registers::{self, Register};
+use crate::system::notifications;
+
+const MAX_DEPTH: usize = 20; // a macro replaying itself stops there
+
+// A macro as text, chars as they are and keys like <esc> or <control-n>, < itself is <lt>.
+// A paste keeps its text between <paste> and </paste>, mouse input isn't recorded
+pub fn encode(events: &[InputEvent]) -> String {
+    let escape = |text: &str| text.replace('<', "<lt>");
+    events.iter()
+        .map(|event| match event {
+            InputEvent::Char(ch) => escape(&ch.to_string()),
+            InputEvent::Keyboard(keys) => format!("<{}>", keys.join("-")),
+            InputEvent::Paste(text) => format!("<paste>{}</paste>", escape(text)),
+            InputEvent::Mouse(_) => String::new(),
+        })
+        .collect()
+}
+
+// Macro text back to input. A < that doesn't start a key is just a char and a line break is enter
+pub fn decode(text: &str) -> Vec<InputEvent> {
+    let mut events = Vec::new();
+    let mut paste: Option<String> = None;
+    let mut rest = text;
+
+    while let Some(ch) = rest.chars().next() {
+        let key = rest.strip_prefix('<')
+            .and_then(|after| after.find('>').map(|end| &after[..end]))
+            .filter(|key| !key.is_empty() && !key.contains(char::is_whitespace));
+        rest = match key {
+            Some(key) => &rest[key.len() + 2..],
+            None => &rest[ch.len_utf8()..],
+        };
+
+        match (key, paste.as_mut()) {
+            (Some("lt"), Some(text)) => text.push('<'),
+            (Some("/paste"), Some(_)) => events.extend(paste.take().map(InputEvent::Paste)),
+            (Some(key), Some(text)) => text.push_str(&format!("<{}>", key)),
+            (None, Some(text)) => text.push(ch),
+            (Some("lt"), None) => events.push(InputEvent::Char('<')),
+            (Some("paste"), None) => paste = Some(String::new()),
+            (Some(key), None) => {
+                let mut keys: Vec<String> = key.split('-').map(String::from).collect();
+                keys.sort();
+                events.push(InputEvent::Keyboard(keys));
+            }
+            (None, None) if ch == '\r' => {}
+            (None, None) if ch == '\n' => events.push(InputEvent::Keyboard(vec![String::from("enter")])),
+            (None, None) => events.push(InputEvent::Char(ch)),
+        }
+    }
+    events.extend(paste.map(InputEvent::Paste));
+    events
+}
+
+// What the editor was doing before an input event, to tell what the event did afterwards
+pub struct InputContext {
+    recording: bool,
+    tracked: bool, // part of a change . can repeat
+    changes: usize, // in the pending transaction
+}
+
+impl EditorPanel {
+    // Normal mode with no command half typed, selection or prompt, where a change starts and ends
+    pub fn at_rest(&self) -> bool {
+        self.mode == Mode::Normal
+            && self.pending_key.is_none()
+            && self.text_pending.is_none()
+            && self.count.is_none()
+            && self.register.is_none()
+            && self.search_prompt.is_none()
+            && self.substitute_prompt.is_none()
+            && self.cursors.iter().all(|c| c.anchor.is_none())
+    }
+
+    // The . or the register name after @, their own input is replayed instead of being part of a change
+    fn replays(&self, input: &InputEvent) -> bool {
+        let InputEvent::Char(ch) = input else {
+            return false;
+        };
+        self.mode == Mode::Normal
+            && self.text_pending.is_none()
+            && self.search_prompt.is_none()
+            && self.substitute_prompt.is_none()
+            && match self.pending_key {
+                Some(key) => key == '@',
+                None => *ch == '.',
+            }
+    }
+
+    pub fn before_input(&mut self, input: &InputEvent) -> InputContext {
+        let tracked = !matches!(input, InputEvent::Mouse(_)) && !self.replays(input);
+        if tracked {
+            if self.at_rest() {
+                self.change.clear();
+                self.change_edited = false;
+            }
+            self.change.push(input.clone());
+        }
+
+        InputContext {
+            recording: self.recording.is_some() && self.macro_depth == 0,
+            tracked,
+            changes: self.history.pending_len(),
+        }
+    }
+
+    // Records the input into the macro. Once the editor is back at rest the input goes with the transaction, if it edited
+    pub fn after_input(&mut self, input: InputEvent, context: InputContext) {
+        if context.recording
+            && !matches!(input, InputEvent::Mouse(_))
+            && let Some((_, events)) = self.recording.as_mut() {
+            events.push(input);
+        }
+
+        if !context.tracked {
+            return;
+        }
+        self.change_edited |= self.history.pending_len() != context.changes;
+        if self.at_rest() {
+            if self.change_edited {
+                self.history.set_input(std::mem::take(&mut self.change));
+            }
+            self.change.clear();
+            self.change_edited = false;
+        }
+    }
+
+    // q followed by the register, an uppercase one appends to the macro in it
+    pub fn start_recording(&mut self, name: char) {
+        if !name.is_ascii_alphabetic() {
+            self.fail(format!("Macros go in registers a to z, not {}", name));
+            return;
+        }
+        self.recording = Some((name, Vec::new()));
+        notifications::info(PLUGIN_ID, format!("Recording @{}", name.to_ascii_lowercase()));
+    }
+
+    pub fn stop_recording(&mut self) {
+        let Some((name, events)) = self.recording.take() else {
+            return;
+        };
+
+        // Appended onto the macro text, a fragment of its own would be joined with a line break that replays as enter
+        let mut text = encode(&events);
+        if name.is_ascii_uppercase()
+            && let Some(register) = registers::get(name) {
+            text = register.fragments.concat() + &text;
+        }
+        registers::store(name.to_ascii_lowercase(), Register::new(vec![text], false));
+        notifications::info(PLUGIN_ID, format!("Recorded @{}", name.to_ascii_lowercase()));
+    }
+
+    // A command that fails stops the whole replay, the macros it was called from too, like in vim
+    fn replay(&mut self, events: &[InputEvent]) {
+        self.macro_depth += 1;
+        for event in events {
+            if self.replay_aborted {
+                break;
+            }
+            self.command_failed = false;
+            self.handle_event(Event::Input(event.clone()));
+            self.replay_aborted |= self.command_failed;
+        }
+        self.macro_depth -= 1;
+    }
+
+    // Replays the events count times, a replay started from typed input starts over with nothing aborted
+    fn replay_times(&mut self, events: &[InputEvent], count: usize) {
+        if self.macro_depth == 0 {
+            self.replay_aborted = false;
+        }
+        for _ in 0..count {
+            if self.replay_aborted {
+                break;
+            }
+            self.replay(events);
+        }
+    }
+
+    // @ followed by the register, @@ replays the macro replayed last
+    pub fn replay_macro(&mut self, name: char, count: usize) {
+        let name = if name == '@' { self.last_macro } else { Some(name.to_ascii_lowercase()) };
+        let Some(name) = name else {
+            self.fail("No macro replayed yet");
+            return;
+        };
+        let Some(register) = registers::get(name).filter(|r| !r.fragments.is_empty()) else {
+            self.fail(format!("Register {} is empty", name));
+            return;
+        };
+        if self.macro_depth >= MAX_DEPTH {
+            self.fail_error(format!("Macro @{} replays itself too deep", name));
+            return;
+        }
+
+        // Yanked as whole lines, the last line break isn't an enter
+        let text = register.text();
+        let text = if register.linewise { text.trim_end_matches(['\r', '\n']) } else { &text };
+        let events = decode(text);
+        self.last_macro = Some(name);
+        self.replay_times(&events, count);
+    }
+
+    // ., replays the input of the last transaction that had some at the cursors as they are now
+    pub fn repeat_change(&mut self, count: usize) {
+        if self.last_change.is_empty() {
+            self.fail("No change to repeat");
+            return;
+        }
+
+        let events = self.last_change.clone();
+        self.replay_times(&events, count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> InputEvent {
+        InputEvent::Keyboard(keys.iter().map(|k| k.to_string()).collect())
+    }
+
+    fn sample() -> Vec<InputEvent> {
+        vec![
+            InputEvent::Char('i'),
+            InputEvent::Char('<'),
+            InputEvent::Char('a'),
+            InputEvent::Char('>'),
+            keys(&["esc"]),
+            keys(&["control", "n"]),
+            InputEvent::Paste(String::from("x <esc> y\nz")),
+        ]
+    }
+
+    #[test]
+    fn encodes_keys_and_text() {
+        assert_eq!(encode(&sample()), "i<lt>a><esc><control-n><paste>x <lt>esc> y\nz</paste>");
+    }
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(decode(&encode(&sample())), sample());
+    }
+
+    #[test]
+    fn appended_macros_replay_both_parts() {
+        let first = vec![InputEvent::Char('d'), InputEvent::Char('d')];
+        let second = vec![InputEvent::Char('j'), keys(&["control", "n"])];
+        let appended = encode(&first) + &encode(&second);
+        assert_eq!(decode(&appended), [first, second].concat());
+    }
+
+    #[test]
+    fn decodes_loose_text() {
+        assert_eq!(decode("a < b"), "a < b".chars().map(InputEvent::Char).collect::<Vec<_>>());
+        assert_eq!(decode("x\r\ny"), vec![InputEvent::Char('x'), keys(&["enter"]), InputEvent::Char('y')]);
+        assert_eq!(decode("<n-control>"), vec![keys(&["control", "n"])]);
+        assert_eq!(decode("<paste>open"), vec![InputEvent::Paste(String::from("open"))]);
+        assert_eq!(decode("<>"), vec![InputEvent::Char('<'), InputEvent::Char('>')]);
+    }
+
+    fn replayed(text: &str, name: char, register: &str, count: usize) -> String {
+        let mut editor = EditorPanel::new();
+        editor.set_text(text);
+        registers::store(name, Register::new(vec![register.to_string()], false));
+        editor.replay_macro(name, count);
+        editor.rope.to_string()
+    }
+
+    #[test]
+    fn replays_stop_at_a_failed_command() {
+        assert_eq!(replayed("a\nb\nc\n", 'w', "dd", 2), "c\n");
+        assert_eq!(replayed("a\nb\nc\n", 'x', "dd/none<enter>dd", 1), "b\nc\n");
+        assert_eq!(replayed("a\nb\nc\n", 'y', "/none<enter>dd", 3), "a\nb\nc\n");
+        assert_eq!(replayed("a\n", 'v', "@v", 1), "a\n");
+    }
+
+    #[test]
+    fn other_warnings_dont_stop_replays() {
+        let mut editor = EditorPanel::new();
+        editor.set_text("a\nb\nc\n");
+        registers::store('z', Register::new(vec![String::from("dd")], false));
+        editor.fail("typed before the replay");
+        notifications::warning("other", "not from the editor");
+        editor.replay_macro('z', 2);
+        assert_eq!(editor.rope.to_string(), "c\n");
+    }
+}
\ No newline at end of file
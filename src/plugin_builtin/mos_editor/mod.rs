@@ -1,3 +1,18 @@
 pub mod mos_editor;
 pub mod editor_panel;
-mod editor_logic;
\ No newline at end of file
+mod editor_logic;
+pub mod graphemes;
+pub mod viewport;
+pub mod gutter;
+pub mod folds;
+pub mod search;
+pub mod substitute;
+pub mod selection;
+pub mod multi_cursor;
+pub mod registers;
+pub mod mouse;
+pub mod macros;
+pub mod text_objects;
+pub mod surround;
+pub mod undo;
+pub mod syntax;
\ No newline at end of file
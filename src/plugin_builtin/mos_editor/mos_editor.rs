@@ -1,7 +1,10 @@
-use crate::panel::panel::Panel;
-use crate::plugin::plugin::{Plugin, PluginRegistration};
+use crate::plugin::dependency::Dependency;
+use crate::plugin::plugin::{Plugin, PluginContext};
+use crate::plugin::subscription::Subscription;
 use crate::plugin_builtin::mos_editor::editor_panel::EditorPanel;
-use crate::system::panel_registry::PanelRegistry;
+use crate::system::logger;
+
+pub const PLUGIN_ID: &str = "mos_builtin_editor";
 
 pub struct MosEditorPlugin {}
 
@@ -13,7 +16,7 @@ impl MosEditorPlugin {
 
 impl Plugin for MosEditorPlugin {
     fn id(&self) -> String {
-        String::from("mos_builtin_editor")
+        String::from(PLUGIN_ID)
     }
 
     fn name(&self) -> String {
@@ -28,20 +31,46 @@ impl Plugin for MosEditorPlugin {
         String::from("The built-in text editor plugin for Mos")
     }
 
-    fn enable(&mut self, panel_registry: &mut PanelRegistry) -> Result<(), String> {
-        //println!("(built-in) [{}] Enabled with plugin-id {:?}", self.name(), self.id()); -> go to log instead of screen
+    fn dependencies(&self) -> Vec<Dependency> {
+        Vec::new()
+    }
+
+    fn subscriptions(&self) -> Vec<Subscription> {
+        Vec::new()
+    }
 
-        panel_registry.register_panel_kind(self.id(), String::from("editor_panel"), || Box::new(EditorPanel::new()));
+    fn enable(&mut self, context: &mut PluginContext) -> Result<(), String> {
+        logger::debug(&self.id(), format!("(built-in) [{}] Enabled", self.name()));
+
+        context.panel_registry.register_panel_kind(self.id(), String::from("editor_panel"), || Box::new(EditorPanel::new()));
+
+        context.command_registry.register_command(self.id(), String::from("open"), String::from("Open a file in the active editor, optionally at a line and column"));
+        context.command_registry.register_command(self.id(), String::from("set_text"), String::from("Replace the contents of the active editor"));
+        context.command_registry.register_command(self.id(), String::from("read_only"), String::from("Make the active editor read-only, or writable again with off"));
+        context.command_registry.register_command(self.id(), String::from("scroll"), String::from("Scroll the active editor: line_down, line_up, half_page_down, half_page_up, page_down, page_up, center, top, bottom, left or right"));
+        context.command_registry.register_command(self.id(), String::from("undo"), String::from("Undo the last changes in the active editor, optionally a number of them"));
+        context.command_registry.register_command(self.id(), String::from("redo"), String::from("Redo changes undone in the active editor, optionally a number of them"));
+        context.command_registry.register_command(self.id(), String::from("syntax"), String::from("Highlight the active editor as rust, lua, toml, json or markdown, off, or auto to detect it again"));
+        context.command_registry.register_command(self.id(), String::from("select_next_occurrence"), String::from("Add a cursor on the next occurrence of the selection or the word under the cursor, ctrl-n as ctrl-d scrolls"));
+        context.command_registry.register_command(self.id(), String::from("skip_occurrence"), String::from("Select the occurrence after the one added last instead of it, ctrl-x"));
+        context.command_registry.register_command(self.id(), String::from("select_all_occurrences"), String::from("Add a cursor on every occurrence of the selection or the word under the cursor, alt-n"));
+        context.command_registry.register_command(self.id(), String::from("cursors_from_search"), String::from("Add a cursor selecting every match of the last search"));
+        context.command_registry.register_command(self.id(), String::from("cursors_at_line_ends"), String::from("Add a cursor at the end of every line the selections touch"));
+        context.command_registry.register_command(self.id(), String::from("align_cursors"), String::from("Pad with spaces so the cursors line up in one column"));
+        context.command_registry.register_command(self.id(), String::from("wrap"), String::from("Soft wrap long lines in the active editor: on, off or toggle"));
+        context.command_registry.register_command(self.id(), String::from("tab_width"), String::from("Set how many columns a tab stop is wide"));
+        context.command_registry.register_command(self.id(), String::from("scrolloff"), String::from("Set how many lines stay visible around the cursor"));
+        context.command_registry.register_command(self.id(), String::from("save"), String::from("Save the active editor, optionally to a new path"));
+        context.keymap_registry.register_keybinding(self.id(), vec![String::from("control"), String::from("s")], String::from("save"), Vec::new());
 
         Ok(())
     }
 
-    fn disable(&mut self) -> PluginRegistration {
-        todo!()
+    fn disable(&mut self) -> Result<(), String> {
+        Ok(())
     }
 
     fn handle_event(&mut self, _event: crate::event::event::Event) -> Result<(), String> {
-        //println!("Text Editor Plugin received event: {:?}", _event);
         Ok(())
     }
 
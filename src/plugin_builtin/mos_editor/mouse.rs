@@ -0,0 +1,101 @@
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel};
+use crate::plugin_builtin::mos_editor::viewport::WHEEL_LINES;
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use std::time::{Duration, Instant};
+
+const MULTI_CLICK: Duration = Duration::from_millis(400);
+
+// The last left click, a quick one on the same cell counts up to a double and a triple click
+#[derive(Clone, Copy, Debug)]
+pub struct Click {
+    at: Instant,
+    cell: (u16, u16),
+    count: usize,
+    anchor: (usize, usize), // where a drag from this click selects from
+}
+
+impl EditorPanel {
+    // The buffer position drawn at a cell of the panel, None on the prompt row. Below the last line is its end
+    pub fn position_at(&self, x: u16, y: u16) -> Option<(usize, usize)> {
+        let y = y as usize;
+        if y >= self.view_height() {
+            return None;
+        }
+
+        let top = (self.scroll_offset, self.scroll_row);
+        let (line, row) = self.step_rows(top, y as isize);
+        if self.rows_between(top, (line, row), y) < y {
+            return Some((line, Self::line_visible_len_rope(&self.rope, line)));
+        }
+
+        let x = (x as usize).saturating_sub(self.gutter_width());
+        let x = if self.soft_wrap { x } else { x + self.h_scroll };
+        Some((line, self.line_layout(line).column_at(row, x)))
+    }
+
+    pub fn handle_mouse(&mut self, mouse: MouseEvent) {
+        match mouse.kind {
+            MouseEventKind::ScrollDown => self.scroll_view(WHEEL_LINES as isize),
+            MouseEventKind::ScrollUp => self.scroll_view(-(WHEEL_LINES as isize)),
+            MouseEventKind::ScrollRight => self.scroll_view_horizontal(WHEEL_LINES as isize),
+            MouseEventKind::ScrollLeft => self.scroll_view_horizontal(-(WHEEL_LINES as isize)),
+            // Clicks would move the cursor away from what a prompt is working on
+            _ if self.search_prompt.is_some() || self.substitute_prompt.is_some() => {}
+            MouseEventKind::Down(MouseButton::Left) => self.click(mouse),
+            MouseEventKind::Drag(MouseButton::Left) => self.drag(mouse),
+            _ => {}
+        }
+    }
+
+    // A click places the cursor, alt-click adds one. Double click selects the word and triple click the line
+    fn click(&mut self, mouse: MouseEvent) {
+        let Some((line, column)) = self.position_at(mouse.column, mouse.row) else {
+            return;
+        };
+        let cell = (mouse.column, mouse.row);
+        let count = match self.last_click {
+            Some(last) if last.cell == cell && last.at.elapsed() < MULTI_CLICK => last.count % 3 + 1,
+            _ => 1,
+        };
+        self.occurrence = None;
+
+        let cursor = match count {
+            2 => match self.word_at(line, column) {
+                Some(word) => Cursor::selecting((line, word.start), line, word.end),
+                None => Cursor::new(line, column, column),
+            },
+            3 => Cursor::selecting((line, 0), line, Self::line_visible_len_rope(&self.rope, line)),
+            _ => Cursor::new(line, column, column),
+        };
+        let anchor = cursor.anchor.unwrap_or((line, column));
+        self.last_click = Some(Click { at: Instant::now(), cell, count, anchor });
+
+        if mouse.modifiers.contains(KeyModifiers::ALT) {
+            self.cursors.retain(|c| c.anchor != Some(anchor) && (c.line, c.column) != anchor);
+            self.cursors.push(cursor);
+            self.cursors.sort();
+        } else {
+            self.cursors = vec![cursor];
+        }
+        self.dedup_cursors();
+        self.update_goal_columns();
+    }
+
+    // Dragging selects from where the button went down, the cursor placed by that click follows the pointer
+    fn drag(&mut self, mouse: MouseEvent) {
+        let Some(anchor) = self.last_click.map(|click| click.anchor) else {
+            return;
+        };
+        // Past the bottom the last row in view, the cursor getting there scrolls with scrolloff
+        let row = mouse.row.min(self.view_height().saturating_sub(1) as u16);
+        let Some((line, column)) = self.position_at(mouse.column, row) else {
+            return;
+        };
+
+        self.cursors.retain(|c| c.anchor != Some(anchor) && (c.line, c.column) != anchor);
+        self.cursors.push(Cursor::selecting(anchor, line, column));
+        self.cursors.sort();
+        self.dedup_cursors();
+        self.update_goal_columns();
+    }
+}
\ No newline at end of file
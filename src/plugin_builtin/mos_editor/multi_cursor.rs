@@ -0,0 +1,315 @@
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel};
+use crate::plugin_builtin::mos_editor::mos_editor::PLUGIN_ID;
+use crate::plugin_builtin::mos_editor::search::{self, line_matches};
+use crate::system::notifications;
+use regex::Regex;
+use std::ops::Range;
+
+impl EditorPanel {
+    // Text within a line is looked for line by line, only a selection spanning lines needs the whole text
+    fn literal_matcher(text: &str) -> Option<Regex> {
+        if text.contains(['\n', '\r']) {
+            return None;
+        }
+        Regex::new(&regex::escape(text)).ok()
+    }
+
+    // Where the matcher finds the text on one line, as char ranges of the buffer
+    fn line_occurrences(&self, line: usize, matcher: &Regex) -> Vec<Range<usize>> {
+        let start = self.rope.line_to_char(line);
+        line_matches(&self.rope, line, matcher).into_iter()
+            .map(|r| start + r.start..start + r.end)
+            .collect()
+    }
+
+    // Where text occurs in the buffer as char ranges
+    fn occurrences(&self, text: &str) -> Vec<Range<usize>> {
+        if let Some(matcher) = Self::literal_matcher(text) {
+            return (0..self.rope.len_lines()).flat_map(|line| self.line_occurrences(line, &matcher)).collect();
+        }
+
+        let buffer = self.rope.to_string();
+        buffer.match_indices(text)
+            .map(|(byte, found)| {
+                let start = self.rope.byte_to_char(byte);
+                start..start + found.chars().count()
+            })
+            .collect()
+    }
+
+    // The text the occurrence commands look for, from the selection that was added last or else the first one
+    fn occurrence_text(&self) -> Option<String> {
+        let last_added = self.cursors.iter()
+            .find(|c| self.occurrence.is_some() && self.selection_range(c).map(|r| r.start) == self.occurrence);
+        last_added.or_else(|| self.cursors.iter().find(|c| c.selection().is_some()))
+            .and_then(|c| self.selected_text(c))
+            .filter(|text| !text.is_empty())
+    }
+
+    // Without a selection the first press selects the word under each cursor
+    fn select_words(&mut self) -> bool {
+        let words: Vec<Cursor> = self.cursors.iter()
+            .filter_map(|c| {
+                let word = self.word_at(c.line, c.column)?;
+                Some(Cursor::selecting((c.line, word.start), c.line, word.end))
+            })
+            .collect();
+        if words.is_empty() {
+            self.fail("No word under the cursor");
+            return false;
+        }
+
+        self.occurrence = words.first().map(|c| self.position_to_char(c.anchor.unwrap_or((c.line, c.column))));
+        self.cursors = words;
+        self.dedup_cursors();
+        true
+    }
+
+    fn add_occurrence(&mut self, range: Range<usize>) {
+        let cursor = self.select_range(range.clone());
+        self.reveal_line(cursor.line);
+        self.cursors.push(cursor);
+        self.cursors.sort();
+        self.dedup_cursors();
+        self.occurrence = Some(range.start);
+    }
+
+    // The next occurrence after one position that isn't selected yet, wrapping around the buffer
+    fn next_occurrence(&self, text: &str, after: usize) -> Option<Range<usize>> {
+        let selected: Vec<Range<usize>> = self.cursors.iter().filter_map(|c| self.selection_range(c)).collect();
+        let Some(matcher) = Self::literal_matcher(text) else {
+            let free: Vec<Range<usize>> = self.occurrences(text).into_iter().filter(|r| !selected.contains(r)).collect();
+            return free.iter().find(|r| r.start > after).or(free.first()).cloned();
+        };
+
+        // From the line of the position to the end, then from the top back to it, stopping at the first one found
+        let lines = self.rope.len_lines();
+        let first = self.rope.char_to_line(after.min(self.rope.len_chars()));
+        (first..lines).chain(0..=first)
+            .enumerate()
+            .flat_map(|(i, line)| {
+                let wrapped = i >= lines - first;
+                self.line_occurrences(line, &matcher).into_iter().filter(move |r| wrapped || r.start > after)
+            })
+            .find(|r| !selected.contains(r))
+    }
+
+    // Ctrl-n, adds a cursor selecting the next occurrence of the selected text
+    pub fn select_next_occurrence(&mut self) {
+        let Some(text) = self.occurrence_text() else {
+            self.select_words();
+            return;
+        };
+
+        let after = self.occurrence
+            .or_else(|| self.cursors.iter().filter_map(|c| self.selection_range(c)).map(|r| r.start).max())
+            .unwrap_or(0);
+        match self.next_occurrence(&text, after) {
+            Some(range) => self.add_occurrence(range),
+            None => notifications::info(PLUGIN_ID, "No more occurrences"),
+        }
+    }
+
+    // Ctrl-x, drops the occurrence added last and selects the one after it instead
+    pub fn skip_occurrence(&mut self) {
+        let (Some(text), Some(skipped)) = (self.occurrence_text(), self.occurrence) else {
+            self.fail("No occurrence to skip");
+            return;
+        };
+
+        if self.cursors.len() > 1 {
+            let remaining: Vec<Cursor> = self.cursors.iter()
+                .filter(|c| self.selection_range(c).map(|r| r.start) != Some(skipped))
+                .cloned()
+                .collect();
+            self.cursors = remaining;
+        }
+        match self.next_occurrence(&text, skipped) {
+            Some(range) => self.add_occurrence(range),
+            None => notifications::info(PLUGIN_ID, "No more occurrences"),
+        }
+    }
+
+    // Alt-n, a cursor on every occurrence of the selected text or the word under the cursor
+    pub fn select_all_occurrences(&mut self) {
+        if self.occurrence_text().is_none() && !self.select_words() {
+            return;
+        }
+        let Some(text) = self.occurrence_text() else {
+            return;
+        };
+
+        let occurrences = self.occurrences(&text);
+        self.cursors = occurrences.iter().map(|r| self.select_range(r.clone())).collect();
+        for line in self.cursors.iter().map(|c| c.line).collect::<Vec<_>>() {
+            self.reveal_line(line);
+        }
+        self.occurrence = occurrences.last().map(|r| r.start);
+        let plural = if occurrences.len() == 1 { "" } else { "s" };
+        notifications::info(PLUGIN_ID, format!("{} occurrence{}", occurrences.len(), plural));
+    }
+
+    // A cursor selecting every match of the last search
+    pub fn cursors_from_search(&mut self) {
+        let Some(matcher) = search::last_search().and_then(|s| s.matcher().ok()) else {
+            self.fail("No previous search");
+            return;
+        };
+
+        let mut cursors = Vec::new();
+        for line in 0..self.rope.len_lines() {
+            for range in line_matches(&self.rope, line, &matcher) {
+                cursors.push(Cursor::selecting((line, range.start), line, range.end));
+            }
+        }
+        if cursors.is_empty() {
+            self.fail("No matches");
+            return;
+        }
+
+        for line in cursors.iter().map(|c| c.line) {
+            self.reveal_line(line);
+        }
+        self.cursors = cursors;
+        self.occurrence = None;
+    }
+
+    // A cursor at the end of every line a selection touches, or of the cursor's line without one
+    pub fn cursors_at_line_ends(&mut self) {
+        let mut cursors: Vec<Cursor> = self.cursors.iter()
+            .flat_map(|c| {
+                let (first, last) = c.selection().map_or((c.line, c.line), |(start, end)| (start.0, end.0));
+                (first..=last).map(|line| {
+                    let end = Self::line_visible_len_rope(&self.rope, line);
+                    Cursor::new(line, end, end)
+                })
+            })
+            .filter(|c| !self.is_hidden(c.line))
+            .collect();
+        cursors.sort();
+        cursors.dedup();
+
+        self.cursors = cursors;
+        self.update_goal_columns();
+    }
+
+    // Pads with spaces before the first cursor of each line so they all reach the display column of the furthest one
+    pub fn align_cursors(&mut self) {
+        let first_on_lines: Vec<(usize, usize, usize)> = self.cursors.iter()
+            .enumerate()
+            .filter(|(i, c)| *i == 0 || self.cursors[i - 1].line != c.line)
+            .map(|(_, c)| (c.line, c.column, self.unwrapped_layout(c.line).position(c.column).1))
+            .collect();
+        let target = first_on_lines.iter().map(|(_, _, x)| *x).max().unwrap_or(0);
+
+        // Line, column and how many spaces go there
+        let padding: Vec<(usize, usize, usize)> = first_on_lines.into_iter()
+            .filter(|(_, _, x)| *x < target)
+            .map(|(line, column, x)| (line, column, target - x))
+            .collect();
+        if padding.is_empty() {
+            return;
+        }
+
+        let ranges = padding.iter()
+            .map(|(line, column, spaces)| {
+                let at = self.position_to_char((*line, *column));
+                (at..at, " ".repeat(*spaces))
+            })
+            .collect();
+        self.replace_ranges(ranges);
+
+        // Cursors on a padded line move right with the text
+        for c in self.cursors.iter_mut() {
+            let spaces = padding.iter().find(|(line, column, _)| *line == c.line && c.column >= *column).map(|p| p.2);
+            c.column += spaces.unwrap_or(0);
+        }
+        self.clear_selections();
+        self.update_goal_columns();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str, selection: ((usize, usize), (usize, usize))) -> EditorPanel {
+        let mut editor = EditorPanel::new();
+        editor.set_text(text);
+        let (anchor, (line, column)) = selection;
+        editor.cursors = vec![Cursor::selecting(anchor, line, column)];
+        editor
+    }
+
+    fn selections(editor: &EditorPanel) -> Vec<Range<usize>> {
+        editor.cursors.iter().filter_map(|c| editor.selection_range(c)).collect()
+    }
+
+    #[test]
+    fn selects_next_occurrences_and_wraps() {
+        let mut editor = buffer("foo bar foo\nfoo\n", ((0, 8), (0, 11)));
+        editor.select_next_occurrence();
+        assert_eq!(selections(&editor), vec![8..11, 12..15]);
+        editor.select_next_occurrence();
+        assert_eq!(selections(&editor), vec![0..3, 8..11, 12..15]);
+
+        // Every occurrence has a cursor, nothing more is added
+        editor.select_next_occurrence();
+        assert_eq!(selections(&editor).len(), 3);
+    }
+
+    #[test]
+    fn selects_the_word_under_the_cursor_first() {
+        let mut editor = buffer("foo bar foo\n", ((0, 0), (0, 0)));
+        editor.cursors = vec![Cursor::new(0, 1, 1)];
+        editor.select_next_occurrence();
+        assert_eq!(selections(&editor), vec![0..3]);
+        editor.select_next_occurrence();
+        assert_eq!(selections(&editor), vec![0..3, 8..11]);
+    }
+
+    #[test]
+    fn skips_the_occurrence_added_last() {
+        let mut editor = buffer("ab ab ab ab\n", ((0, 0), (0, 2)));
+        editor.select_next_occurrence();
+        editor.skip_occurrence();
+        assert_eq!(selections(&editor), vec![0..2, 6..8]);
+        editor.skip_occurrence();
+        assert_eq!(selections(&editor), vec![0..2, 9..11]);
+
+        let mut editor = buffer("ab\n", ((0, 0), (0, 0)));
+        editor.skip_occurrence();
+        assert!(editor.command_failed);
+    }
+
+    #[test]
+    fn finds_selections_spanning_lines() {
+        let mut editor = buffer("a\nb\nx\na\nb\n", ((0, 0), (1, 1)));
+        editor.select_next_occurrence();
+        assert_eq!(selections(&editor), vec![0..3, 6..9]);
+
+        // and wraps around for them too
+        let mut editor = buffer("a\nb\nx\na\nb\n", ((3, 0), (4, 1)));
+        editor.select_next_occurrence();
+        assert_eq!(selections(&editor), vec![0..3, 6..9]);
+        assert_eq!(editor.occurrences("a\nb"), vec![0..3, 6..9]);
+    }
+
+    #[test]
+    fn selects_all_occurrences() {
+        let mut editor = buffer("Foo foo foo\n", ((0, 4), (0, 7)));
+        editor.select_all_occurrences();
+        assert_eq!(selections(&editor), vec![4..7, 8..11]);
+    }
+
+    #[test]
+    fn aligns_cursors_on_the_furthest_column() {
+        let mut editor = buffer("a = 1\nlong = 2\n\tx = 3\n", ((0, 0), (0, 0)));
+        editor.tab_width = 4;
+        editor.cursors = vec![Cursor::new(0, 2, 2), Cursor::new(1, 5, 5), Cursor::new(2, 1, 1)];
+        editor.align_cursors();
+        assert_eq!(editor.rope.to_string(), "a    = 1\nlong = 2\n\t x = 3\n");
+        let columns: Vec<usize> = editor.cursors.iter().map(|c| c.column).collect();
+        assert_eq!(columns, vec![5, 5, 2]);
+    }
+}
\ No newline at end of file
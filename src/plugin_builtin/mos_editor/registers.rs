@@ -0,0 +1,290 @@
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel, Mode};
+use crate::system::clipboard;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Mutex;
+
+const RING_SIZE: usize = 10;
+pub const UNNAMED: char = '"';
+
+// Yanked text, a fragment per cursor. Linewise fragments are whole lines and paste on lines of their own
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Register {
+    pub fragments: Vec<String>,
+    pub linewise: bool,
+}
+
+impl Register {
+    pub fn new(fragments: Vec<String>, linewise: bool) -> Self {
+        Self { fragments, linewise }
+    }
+
+    // The fragments as one text, what every cursor gets when their counts don't match
+    pub fn text(&self) -> String {
+        if self.linewise {
+            self.fragments.concat()
+        } else {
+            self.fragments.join("\n")
+        }
+    }
+}
+
+struct Registers {
+    named: BTreeMap<char, Register>, // a to z
+    ring: Vec<Register>, // every yank and delete, newest first
+}
+
+impl Registers {
+    fn store(&mut self, name: char, register: Register) {
+        if name.is_ascii_uppercase() {
+            let named = self.named.entry(name.to_ascii_lowercase()).or_default();
+            named.fragments.extend(register.fragments);
+            // Appending lines makes the whole register linewise, like in vim
+            if register.linewise {
+                named.linewise = true;
+                for fragment in named.fragments.iter_mut().filter(|f| !f.ends_with('\n')) {
+                    fragment.push('\n');
+                }
+            }
+        } else if name.is_ascii_lowercase() {
+            self.named.insert(name, register);
+        }
+    }
+}
+
+static REGISTERS: Mutex<Registers> = Mutex::new(Registers {
+    named: BTreeMap::new(),
+    ring: Vec::new(),
+});
+
+pub fn is_register(name: char) -> bool {
+    matches!(name, '"' | '+' | '*' | '_' | '0'..='9' | 'a'..='z' | 'A'..='Z')
+}
+
+// " is the newest yank and 0 to 9 go back through the ring, + and * are the system clipboard
+pub fn get(name: char) -> Option<Register> {
+    if matches!(name, '+' | '*') {
+        return clipboard::paste().map(|text| {
+            let linewise = text.ends_with('\n');
+            Register::new(vec![text], linewise)
+        });
+    }
+
+    let registers = REGISTERS.lock().ok()?;
+    match name {
+        UNNAMED => registers.ring.first().cloned(),
+        '0'..='9' => registers.ring.get(name as usize - '0' as usize).cloned(),
+        _ => registers.named.get(&name.to_ascii_lowercase()).cloned(),
+    }
+}
+
+// An uppercase name appends to its register and _ throws the text away, the rest also goes on the ring
+pub fn set(name: char, register: Register) {
+    if name == '_' {
+        return;
+    }
+    if matches!(name, '+' | '*') {
+        clipboard::copy(&register.text());
+    }
+
+    let Ok(mut registers) = REGISTERS.lock() else {
+        return;
+    };
+    registers.store(name, register.clone());
+    registers.ring.insert(0, register);
+    registers.ring.truncate(RING_SIZE);
+}
+
+// Only into a named register and not on the ring, for recorded macros
+pub fn store(name: char, register: Register) {
+    if let Ok(mut registers) = REGISTERS.lock() {
+        registers.store(name, register);
+    }
+}
+
+// The ring and the named registers with what they hold, for the registers command
+pub fn list() -> Vec<(char, Register)> {
+    let Ok(registers) = REGISTERS.lock() else {
+        return Vec::new();
+    };
+    let ring = registers.ring.iter().enumerate().map(|(i, r)| ((b'0' + i as u8) as char, r.clone()));
+    ring.chain(registers.named.iter().map(|(name, r)| (*name, r.clone()))).collect()
+}
+
+impl EditorPanel {
+    // The register picked with "x for the next yank, delete or paste
+    pub fn take_register(&mut self) -> char {
+        self.register.take().unwrap_or(UNNAMED)
+    }
+
+    // The lines the cursors are on, each once
+    fn cursor_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self.cursors.iter().map(|c| c.line).collect();
+        lines.dedup();
+        lines
+    }
+
+    // Each selection becomes a fragment, without selections the lines of the cursors do
+    pub fn yank(&mut self, name: char) {
+        if !self.has_selection() {
+            self.yank_lines(name);
+            return;
+        }
+
+        let fragments = self.cursors.iter().filter_map(|c| self.selected_text(c)).collect();
+        set(name, Register::new(fragments, false));
+        self.clear_selections();
+    }
+
+    // yy, registers always hold \n line breaks, pasting turns them into the buffer's
+    pub fn yank_lines(&mut self, name: char) {
+        let fragments = self.cursor_lines().into_iter()
+            .map(|line| Self::line_text_rope(&self.rope, line) + "\n")
+            .collect();
+        set(name, Register::new(fragments, true));
+    }
+
+    // d and c on selections
+    pub fn cut_selections(&mut self, name: char) {
+        let fragments = self.cursors.iter().filter_map(|c| self.selected_text(c)).collect();
+        set(name, Register::new(fragments, false));
+        self.delete_selections();
+    }
+
+    // dd, lines next to each other go as one block. A block ending on the last line takes the line break
+    // before it along so no empty line is left behind
+    pub fn delete_lines(&mut self, name: char) {
+        self.yank_lines(name);
+
+        let mut blocks: Vec<(usize, usize)> = Vec::new();
+        for line in self.cursor_lines() {
+            match blocks.last_mut() {
+                Some((_, end)) if *end + 1 == line => *end = line,
+                _ => blocks.push((line, line)),
+            }
+        }
+
+        let last = self.rope.len_lines().saturating_sub(1);
+        let (ranges, positions): (Vec<Range<usize>>, Vec<usize>) = blocks.into_iter()
+            .map(|(first, end)| {
+                let start = self.rope.line_to_char(first);
+                if end < last {
+                    (start..self.rope.line_to_char(end + 1), start)
+                } else if first > 0 {
+                    let previous = self.rope.line_to_char(first - 1);
+                    (previous + Self::line_visible_len_rope(&self.rope, first - 1)..self.rope.len_chars(), previous)
+                } else {
+                    (start..self.rope.len_chars(), start)
+                }
+            })
+            .unzip();
+
+        self.delete_ranges(ranges, positions);
+        self.move_to_first_non_blank();
+    }
+
+    fn move_to_first_non_blank(&mut self) {
+        for c in self.cursors.iter_mut() {
+            c.column = Self::line_text_rope(&self.rope, c.line).chars().take_while(|ch| ch.is_whitespace()).count();
+        }
+        self.update_goal_columns();
+    }
+
+    // p pastes after the cursor and P before it
+    pub fn paste(&mut self, name: char, before: bool) {
+        match get(name).filter(|r| !r.fragments.is_empty()) {
+            Some(register) => self.paste_register(register, before),
+            None => self.fail(format!("Register {} is empty", name)),
+        }
+    }
+
+    // With as many fragments as cursors each cursor gets its own, otherwise every cursor gets all of it.
+    // Selections are replaced, everything goes in as one batch of edits
+    pub fn paste_register(&mut self, register: Register, before: bool) {
+        let newline = if self.line_ending() == "CRLF" { "\r\n" } else { "\n" };
+        let normalize = |text: &str| text.replace("\r\n", "\n").replace('\n', newline);
+
+        // A text with a line per cursor is spread over them, e.g. a column copied from elsewhere
+        let mut fragments = register.fragments.clone();
+        if let [text] = fragments.as_slice()
+            && !register.linewise
+            && self.cursors.len() > 1 {
+            let text = text.replace("\r\n", "\n");
+            let lines: Vec<String> = text.trim_end_matches('\n').split('\n').map(String::from).collect();
+            if lines.len() == self.cursors.len() {
+                fragments = lines;
+            }
+        }
+        let per_cursor = fragments.len() == self.cursors.len();
+        let whole = register.text();
+
+        // The range replaced, the text and where the cursor goes in it
+        let mut edits: Vec<(Range<usize>, String, usize)> = Vec::new();
+        for (i, c) in self.cursors.iter().enumerate() {
+            let text = normalize(if per_cursor { &fragments[i] } else { &whole });
+            let length = text.chars().count();
+            // Normal mode ends on the last char pasted, insert mode after it
+            let end = if self.mode == Mode::Normal { length.saturating_sub(1) } else { length };
+
+            if let Some(range) = self.selection_range(c) {
+                edits.push((range, text, end));
+            } else if register.linewise {
+                let text = if text.ends_with('\n') { text } else { text + newline };
+                if before {
+                    let at = self.rope.line_to_char(c.line);
+                    edits.push((at..at, text, 0));
+                } else if c.line + 1 < self.rope.len_lines() {
+                    let at = self.rope.line_to_char(c.line + 1);
+                    edits.push((at..at, text, 0));
+                } else {
+                    // After the last line, which has no line break to paste behind
+                    let at = self.rope.len_chars();
+                    let text = format!("{}{}", newline, text.strip_suffix(newline).unwrap_or(&text));
+                    edits.push((at..at, text, newline.chars().count()));
+                }
+            } else {
+                let column = if before || c.column >= Self::line_visible_len_rope(&self.rope, c.line) {
+                    c.column
+                } else {
+                    self.unwrapped_layout(c.line).next_column(c.column)
+                };
+                let at = self.position_to_char((c.line, column));
+                edits.push((at..at, text, end));
+            }
+        }
+        // Linewise text goes in once per line, also with several cursors on it
+        edits.dedup_by(|a, b| a.0 == b.0 && a.0.is_empty() && register.linewise);
+
+        let mut shift: isize = 0;
+        let mut positions = Vec::new();
+        for (range, text, offset) in &edits {
+            positions.push(range.start.saturating_add_signed(shift) + offset);
+            shift += text.chars().count() as isize - range.len() as isize;
+        }
+
+        self.replace_ranges(edits.into_iter().map(|(range, text, _)| (range, text)).collect());
+        self.cursors = positions.into_iter()
+            .map(|pos| {
+                let (line, column) = self.char_to_position(pos);
+                Cursor::new(line, column, column)
+            })
+            .collect();
+        self.dedup_cursors();
+        for line in self.cursor_lines() {
+            self.reveal_line(line);
+        }
+        if register.linewise {
+            self.move_to_first_non_blank();
+        }
+        self.update_goal_columns();
+    }
+
+    // A bracketed paste, goes in at the cursors as one edit in either mode
+    pub fn paste_text(&mut self, text: String) {
+        if self.read_only {
+            self.fail("Buffer is read-only");
+            return;
+        }
+        self.paste_register(Register::new(vec![text], false), true);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,460 @@
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel};
+use crate::plugin_builtin::mos_editor::mos_editor::PLUGIN_ID;
+use crate::system::{notifications, theme};
+use ratatui::text::{Line, Span};
+use regex::{Regex, RegexBuilder};
+use ropey::Rope;
+use std::ops::Range;
+use std::sync::Mutex;
+use unicode_width::UnicodeWidthStr;
+
+const MAX_HISTORY: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchDirection {
+    Forward, // /
+    Backward, // ?
+}
+
+impl SearchDirection {
+    pub fn prompt(&self) -> char {
+        match self {
+            SearchDirection::Forward => '/',
+            SearchDirection::Backward => '?',
+        }
+    }
+
+    fn reversed(&self) -> Self {
+        match self {
+            SearchDirection::Forward => SearchDirection::Backward,
+            SearchDirection::Backward => SearchDirection::Forward,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Search {
+    pub query: String,
+    pub regex: bool, // literal text otherwise
+    pub direction: SearchDirection,
+}
+
+// Uppercase outside of escapes like \S makes a search case-sensitive
+pub fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+// Pattern errors are cut down to their last line, which says what is wrong
+pub fn build_matcher(pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| match e {
+            regex::Error::Syntax(message) => message.lines().last().unwrap_or("").trim_start_matches("error: ").to_string(),
+            e => e.to_string(),
+        })
+}
+
+impl Search {
+    pub fn matcher(&self) -> Result<Regex, String> {
+        let pattern = if self.regex { self.query.clone() } else { regex::escape(&self.query) };
+        build_matcher(&pattern, !has_uppercase(&self.query, self.regex))
+    }
+}
+
+// What is being typed after / or ?, the cursor and view go back to where they were if it is cancelled
+pub struct SearchPrompt {
+    pub direction: SearchDirection,
+    pub text: String,
+    pub regex: bool,
+    pub error: Option<String>,
+    pub origin: Vec<Cursor>,
+    pub origin_scroll: (usize, usize, usize), // scroll_offset, scroll_row, h_scroll
+    history_index: Option<usize>, // browsing the history with up and down
+}
+
+struct SearchState {
+    history: Vec<String>, // oldest first
+    last: Option<Search>, // what n and N repeat, shared by every editor
+}
+
+static SEARCH: Mutex<SearchState> = Mutex::new(SearchState {
+    history: Vec::new(),
+    last: None,
+});
+
+pub fn history() -> Vec<String> {
+    SEARCH.lock().map(|s| s.history.clone()).unwrap_or_default()
+}
+
+pub fn last_search() -> Option<Search> {
+    SEARCH.lock().ok().and_then(|s| s.last.clone())
+}
+
+pub fn remember(search: &Search) {
+    if let Ok(mut state) = SEARCH.lock() {
+        state.history.retain(|q| *q != search.query);
+        state.history.push(search.query.clone());
+        if state.history.len() > MAX_HISTORY {
+            state.history.remove(0);
+        }
+        state.last = Some(search.clone());
+    }
+}
+
+// Char ranges of the matches in one line, each line is matched on its own so the buffer is never copied whole
+pub fn line_matches(rope: &Rope, line: usize, matcher: &Regex) -> Vec<Range<usize>> {
+    if line >= rope.len_lines() {
+        return Vec::new();
+    }
+
+    let text = EditorPanel::line_text_rope(rope, line);
+    let char_index = |byte: usize| text[..byte].chars().count();
+    matcher.find_iter(&text)
+        .filter(|m| !m.is_empty())
+        .map(|m| char_index(m.start())..char_index(m.end()))
+        .collect()
+}
+
+impl EditorPanel {
+    pub fn start_search(&mut self, direction: SearchDirection) {
+        self.search_prompt = Some(SearchPrompt {
+            direction,
+            text: String::new(),
+            regex: last_search().map(|s| s.regex).unwrap_or(false),
+            error: None,
+            origin: self.cursors.clone(),
+            origin_scroll: (self.scroll_offset, self.scroll_row, self.h_scroll),
+            history_index: None,
+        });
+        self.search_highlight = true;
+    }
+
+    // The search matches are highlighted with, the one being typed or the last one
+    pub fn active_search(&self) -> Option<Search> {
+        match &self.search_prompt {
+            Some(prompt) if !prompt.text.is_empty() => Some(Search {
+                query: prompt.text.clone(),
+                regex: prompt.regex,
+                direction: prompt.direction,
+            }),
+            Some(_) => None,
+            None if self.search_highlight => last_search(),
+            None => None,
+        }
+    }
+
+    // The first match after (or before) a position, wrapping around the buffer once
+    fn find_match(&self, matcher: &Regex, from: (usize, usize), direction: SearchDirection) -> Option<((usize, usize), bool)> {
+        let lines = self.rope.len_lines();
+        let (line, column) = from;
+
+        for step in 0..=lines {
+            let (current, wrapped) = match direction {
+                SearchDirection::Forward => ((line + step) % lines, line + step >= lines),
+                SearchDirection::Backward => ((line + lines - step % lines) % lines, step > line),
+            };
+            let matches = line_matches(&self.rope, current, matcher);
+
+            let found = match (direction, step) {
+                (SearchDirection::Forward, 0) => matches.iter().find(|m| m.start > column),
+                (SearchDirection::Backward, 0) => matches.iter().rev().find(|m| m.start < column),
+                (SearchDirection::Forward, _) if step == lines => matches.iter().find(|m| m.start <= column),
+                (SearchDirection::Backward, _) if step == lines => matches.iter().rev().find(|m| m.start >= column),
+                (SearchDirection::Forward, _) => matches.first(),
+                (SearchDirection::Backward, _) => matches.last(),
+            };
+            if let Some(found) = found {
+                return Some(((current, found.start), wrapped));
+            }
+        }
+
+        None
+    }
+
+    fn jump_to_match(&mut self, position: (usize, usize)) {
+        let (line, column) = position;
+        self.cursors = vec![Cursor::new(line, column, column)];
+        self.reveal_line(line);
+        self.update_goal_columns();
+        self.scroll_to_cursor();
+    }
+
+    // Moves to the next match of a search from the primary cursor, with a message when it wraps
+    pub fn search(&mut self, search: &Search, direction: SearchDirection) -> bool {
+        let matcher = match search.matcher() {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                self.fail_error(format!("Invalid pattern: {}", e));
+                return false;
+            }
+        };
+
+        let from = self.cursors.first().map(|c| (c.line, c.column)).unwrap_or((0, 0));
+        match self.find_match(&matcher, from, direction) {
+            Some((position, wrapped)) => {
+                if wrapped {
+                    let message = match direction {
+                        SearchDirection::Forward => "Search hit BOTTOM, continuing at TOP",
+                        SearchDirection::Backward => "Search hit TOP, continuing at BOTTOM",
+                    };
+                    notifications::info(PLUGIN_ID, message);
+                }
+                self.jump_to_match(position);
+                true
+            }
+            None => {
+                self.fail(format!("Pattern not found: {}", search.query));
+                false
+            }
+        }
+    }
+
+    // n repeats the last search the way it went, N the other way
+    pub fn search_next(&mut self, reverse: bool) {
+        let Some(search) = last_search() else {
+            self.fail("No previous search");
+            return;
+        };
+
+        let direction = if reverse { search.direction.reversed() } else { search.direction };
+        self.search_highlight = true;
+        self.search(&search, direction);
+    }
+
+    // Runs a search like a confirmed prompt, for the search command
+    pub fn search_for(&mut self, search: Search) {
+        remember(&search);
+        self.search_highlight = true;
+        self.search(&search, search.direction);
+    }
+
+    // Moves the cursor to the first match of what has been typed so far, from where the search started
+    fn preview_search(&mut self) {
+        let Some(prompt) = &self.search_prompt else {
+            return;
+        };
+
+        self.cursors = prompt.origin.clone();
+        (self.scroll_offset, self.scroll_row, self.h_scroll) = prompt.origin_scroll;
+        if prompt.text.is_empty() {
+            return;
+        }
+
+        let search = Search { query: prompt.text.clone(), regex: prompt.regex, direction: prompt.direction };
+        let matcher = search.matcher();
+        let found = matcher.as_ref().ok().and_then(|m| {
+            let from = prompt.origin.first().map(|c| (c.line, c.column)).unwrap_or((0, 0));
+            self.find_match(m, from, prompt.direction)
+        });
+
+        if let Some(prompt) = self.search_prompt.as_mut() {
+            prompt.error = matcher.err();
+        }
+        if let Some((position, _)) = found {
+            self.jump_to_match(position);
+        }
+    }
+
+    fn cancel_search(&mut self) {
+        if let Some(prompt) = self.search_prompt.take() {
+            self.cursors = prompt.origin;
+            (self.scroll_offset, self.scroll_row, self.h_scroll) = prompt.origin_scroll;
+        }
+        self.search_highlight = last_search().is_some();
+    }
+
+    fn confirm_search(&mut self) {
+        let Some(prompt) = self.search_prompt.take() else {
+            return;
+        };
+        if prompt.text.is_empty() {
+            // An empty search repeats the last one, like in vim
+            self.cursors = prompt.origin;
+            self.search_next(prompt.direction == SearchDirection::Backward);
+            return;
+        }
+
+        let search = Search { query: prompt.text, regex: prompt.regex, direction: prompt.direction };
+        remember(&search);
+        self.cursors = prompt.origin;
+        (self.scroll_offset, self.scroll_row, self.h_scroll) = prompt.origin_scroll;
+        self.search(&search, search.direction);
+    }
+
+    // Up goes back in the history, down forward and past the newest entry to an empty prompt
+    fn browse_history(&mut self, older: bool) {
+        let history = history();
+        let Some(prompt) = self.search_prompt.as_mut() else {
+            return;
+        };
+        if history.is_empty() {
+            return;
+        }
+
+        let index = match (prompt.history_index, older) {
+            (None, true) => Some(history.len() - 1),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i + 1 < history.len() => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        prompt.history_index = index;
+        prompt.text = index.map(|i| history[i].clone()).unwrap_or_default();
+        self.preview_search();
+    }
+
+    // Keys while the prompt is open, everything goes to it
+    pub fn handle_search_keys(&mut self, keys: &[&str]) {
+        match keys {
+            ["esc"] => self.cancel_search(),
+            ["enter"] => self.confirm_search(),
+            ["up"] => self.browse_history(true),
+            ["down"] => self.browse_history(false),
+            ["backspace"] => {
+                let Some(prompt) = self.search_prompt.as_mut() else {
+                    return;
+                };
+                if prompt.text.pop().is_none() {
+                    self.cancel_search();
+                    return;
+                }
+                self.preview_search();
+            }
+            // ctrl-r switches between literal and regex search
+            ["control", "r"] => {
+                if let Some(prompt) = self.search_prompt.as_mut() {
+                    prompt.regex = !prompt.regex;
+                }
+                self.preview_search();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn handle_search_char(&mut self, ch: char) {
+        if let Some(prompt) = self.search_prompt.as_mut() {
+            prompt.text.push(ch);
+            prompt.history_index = None;
+        }
+        self.preview_search();
+    }
+
+    // The prompt row and where its cursor goes, e.g. "/fo+ [regex]" with the pattern error after it
+    pub fn search_prompt_line(&self) -> Option<(Line<'static>, usize)> {
+        let prompt = self.search_prompt.as_ref()?;
+        let text = format!("{}{}", prompt.direction.prompt(), prompt.text);
+        let cursor_x = text.width();
+
+        let mut spans = vec![Span::raw(text)];
+        if prompt.regex {
+            spans.push(Span::styled(" [regex]", theme::style("ui.text.dim")));
+        }
+        if let Some(error) = &prompt.error {
+            spans.push(Span::styled(format!("  {}", error), theme::style("error")));
+        }
+        Some((Line::from(spans), cursor_x))
+    }
+
+    // Matches on one line for highlighting, with the current one flagged
+    pub fn search_matches(&self, matcher: &Regex, line: usize) -> Vec<(Range<usize>, bool)> {
+        let cursor = self.cursors.first().map(|c| (c.line, c.column));
+        line_matches(&self.rope, line, matcher).into_iter()
+            .map(|m| {
+                let current = cursor == Some((line, m.start));
+                (m, current)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(query: &str, regex: bool) -> Result<Regex, String> {
+        Search { query: query.to_string(), regex, direction: SearchDirection::Forward }.matcher()
+    }
+
+    fn buffer(text: &str) -> EditorPanel {
+        let mut editor = EditorPanel::new();
+        editor.set_text(text);
+        editor
+    }
+
+    #[test]
+    fn uppercase_makes_searches_case_sensitive() {
+        assert!(!has_uppercase("foo", false));
+        assert!(has_uppercase("fOo", false));
+        assert!(!has_uppercase(r"\S+", true));
+        assert!(has_uppercase(r"\S+", false));
+        assert!(has_uppercase(r"\sA", true));
+
+        assert!(search("foo", false).unwrap().is_match("FOO"));
+        assert!(!search("Foo", false).unwrap().is_match("FOO"));
+        assert!(search(r"f\w+", true).unwrap().is_match("FOO"));
+    }
+
+    #[test]
+    fn literal_searches_escape_the_query() {
+        assert!(search("a.b", false).unwrap().is_match("a.b"));
+        assert!(!search("a.b", false).unwrap().is_match("axb"));
+        assert!(search("a.b", true).unwrap().is_match("axb"));
+    }
+
+    #[test]
+    fn invalid_patterns_say_what_is_wrong() {
+        let error = search("(a", true).unwrap_err();
+        assert!(!error.contains('\n'));
+        assert!(error.contains("unclosed group"), "{}", error);
+        assert!(search("(a", false).is_ok());
+    }
+
+    #[test]
+    fn matches_lines_by_char() {
+        let rope = Rope::from_str("é foo\r\nfoofoo\n");
+        let matcher = search("foo", false).unwrap();
+        assert_eq!(line_matches(&rope, 0, &matcher), vec![2..5]);
+        assert_eq!(line_matches(&rope, 1, &matcher), vec![0..3, 3..6]);
+        assert!(line_matches(&rope, 9, &matcher).is_empty());
+        assert!(line_matches(&rope, 0, &search("x*", true).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn finds_matches_forward_and_wraps() {
+        let editor = buffer("foo\nbar foo\nbaz\n");
+        let matcher = search("foo", false).unwrap();
+        let find = |from| editor.find_match(&matcher, from, SearchDirection::Forward);
+        assert_eq!(find((0, 0)), Some(((1, 4), false)));
+        assert_eq!(find((1, 2)), Some(((1, 4), false)));
+        assert_eq!(find((1, 4)), Some(((0, 0), true)));
+        assert_eq!(find((2, 0)), Some(((0, 0), true)));
+
+        // The only match is found again from itself, after going round the buffer
+        let editor = buffer("a foo b\nc\n");
+        assert_eq!(editor.find_match(&matcher, (0, 2), SearchDirection::Forward), Some(((0, 2), true)));
+        assert_eq!(editor.find_match(&search("nothing", false).unwrap(), (0, 0), SearchDirection::Forward), None);
+    }
+
+    #[test]
+    fn finds_matches_backward_and_wraps() {
+        let editor = buffer("foo\nbar foo\nbaz\n");
+        let matcher = search("foo", false).unwrap();
+        let find = |from| editor.find_match(&matcher, from, SearchDirection::Backward);
+        assert_eq!(find((2, 0)), Some(((1, 4), false)));
+        assert_eq!(find((1, 4)), Some(((0, 0), false)));
+        assert_eq!(find((1, 5)), Some(((1, 4), false)));
+        assert_eq!(find((0, 0)), Some(((1, 4), true)));
+
+        let editor = buffer("a foo b\nc\n");
+        assert_eq!(editor.find_match(&matcher, (0, 2), SearchDirection::Backward), Some(((0, 2), true)));
+    }
+}
\ No newline at end of file
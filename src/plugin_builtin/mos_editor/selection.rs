@@ -0,0 +1,114 @@
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel};
+use std::ops::Range;
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl EditorPanel {
+    pub fn has_selection(&self) -> bool {
+        self.cursors.iter().any(|c| c.selection().is_some())
+    }
+
+    pub fn position_to_char(&self, position: (usize, usize)) -> usize {
+        let (line, column) = position;
+        self.rope.line_to_char(line.min(self.rope.len_lines().saturating_sub(1))) + column
+    }
+
+    pub fn char_to_position(&self, pos: usize) -> (usize, usize) {
+        let line = self.rope.char_to_line(pos.min(self.rope.len_chars()));
+        (line, pos - self.rope.line_to_char(line))
+    }
+
+    // The chars of the buffer a cursor has selected
+    pub fn selection_range(&self, cursor: &Cursor) -> Option<Range<usize>> {
+        let (start, end) = cursor.selection()?;
+        Some(self.position_to_char(start)..self.position_to_char(end))
+    }
+
+    pub fn selected_text(&self, cursor: &Cursor) -> Option<String> {
+        self.selection_range(cursor).map(|range| self.rope.slice(range).to_string())
+    }
+
+    // A cursor selecting a char range, ending after it
+    pub fn select_range(&self, range: Range<usize>) -> Cursor {
+        let (line, column) = self.char_to_position(range.end);
+        Cursor::selecting(self.char_to_position(range.start), line, column)
+    }
+
+    // The columns of a line a cursor has selected, for highlighting
+    pub fn selection_columns(&self, cursor: &Cursor, line: usize) -> Option<Range<usize>> {
+        let (start, end) = cursor.selection()?;
+        if line < start.0 || line > end.0 {
+            return None;
+        }
+
+        let first = if line == start.0 { start.1 } else { 0 };
+        let last = if line == end.0 { end.1 } else { Self::line_visible_len_rope(&self.rope, line) };
+        Some(first..last)
+    }
+
+    // v starts a selection at every cursor, the cursors then move its end. Pressed again it drops them
+    pub fn toggle_selection(&mut self) {
+        if self.cursors.iter().any(|c| c.anchor.is_some()) {
+            self.clear_selections();
+            return;
+        }
+        for c in self.cursors.iter_mut() {
+            c.anchor = Some((c.line, c.column));
+        }
+    }
+
+    pub fn clear_selections(&mut self) {
+        for c in self.cursors.iter_mut() {
+            c.anchor = None;
+        }
+    }
+
+    // The columns of the word a column is on, None off a word
+    pub fn word_at(&self, line: usize, column: usize) -> Option<Range<usize>> {
+        let chars: Vec<char> = Self::line_text_rope(&self.rope, line).chars().collect();
+        if !chars.get(column).is_some_and(|c| is_word_char(*c)) {
+            return None;
+        }
+
+        let start = chars[..column].iter().rposition(|c| !is_word_char(*c)).map_or(0, |i| i + 1);
+        let end = chars[column..].iter().position(|c| !is_word_char(*c)).map_or(chars.len(), |i| column + i);
+        Some(start..end)
+    }
+
+    // Deletes char ranges in one batch, overlapping ones once, and moves the cursors to where positions ended up
+    pub fn delete_ranges(&mut self, mut ranges: Vec<Range<usize>>, positions: Vec<usize>) {
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        let positions: Vec<usize> = positions.into_iter()
+            .map(|pos| pos - merged.iter().map(|r| r.end.min(pos).saturating_sub(r.start)).sum::<usize>())
+            .collect();
+
+        self.replace_ranges(merged.into_iter().map(|r| (r, String::new())).collect());
+        self.cursors = positions.into_iter()
+            .map(|pos| {
+                let (line, column) = self.char_to_position(pos);
+                Cursor::new(line, column, column)
+            })
+            .collect();
+        self.dedup_cursors();
+        self.update_goal_columns();
+    }
+
+    // Each cursor ends up where its selection started
+    pub fn delete_selections(&mut self) {
+        let ranges = self.cursors.iter().filter_map(|c| self.selection_range(c)).collect();
+        let positions = self.cursors.iter()
+            .map(|c| self.selection_range(c).map(|r| r.start).unwrap_or_else(|| self.position_to_char((c.line, c.column))))
+            .collect();
+        self.delete_ranges(ranges, positions);
+    }
+}
\ No newline at end of file
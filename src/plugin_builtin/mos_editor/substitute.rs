@@ -0,0 +1,528 @@
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel};
+use crate::plugin_builtin::mos_editor::mos_editor::PLUGIN_ID;
+use crate::plugin_builtin::mos_editor::search::{self, Search, SearchDirection};
+use crate::system::{notifications, theme};
+use ratatui::text::{Line, Span};
+use regex::Regex;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::Chars;
+use unicode_width::UnicodeWidthStr;
+
+// [range]s/pattern/replacement/[flags], e.g. %s/(\w+) (\w+)/\2 \1/g
+#[derive(Clone, Debug)]
+pub struct Substitute {
+    pub lines: (usize, usize), // first and last, inclusive
+    pub within: Vec<Range<usize>>, // char ranges of the buffer matches must lie in with '<,'>, empty for anywhere on the lines
+    pub pattern: String, // a regex, empty repeats the last search
+    pub replacement: String,
+    pub global: bool, // every match on a line, only the first otherwise
+    pub confirm: bool, // asks about each match
+    pub ignore_case: Option<bool>, // from the i and I flags, smart-case otherwise
+}
+
+impl Substitute {
+    pub fn matcher(&self) -> Result<Regex, String> {
+        let ignore_case = self.ignore_case.unwrap_or(!search::has_uppercase(&self.pattern, true));
+        search::build_matcher(&self.pattern, ignore_case)
+    }
+}
+
+// One change a substitute makes, the range is in chars of the line
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replacement {
+    pub line: usize,
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+// The : prompt, the matches show in the buffer while typing. With the c flag it then asks about them one at a time
+pub struct SubstitutePrompt {
+    pub text: String,
+    pub error: Option<String>,
+    pub replacements: Vec<Replacement>,
+    pub confirming: Option<usize>, // the match being asked about
+    accepted: Vec<Replacement>,
+    origin: Vec<Cursor>,
+    origin_scroll: (usize, usize, usize), // scroll_offset, scroll_row, h_scroll
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> usize {
+    let mut number: usize = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        number = number.saturating_mul(10).saturating_add(digit as usize);
+        chars.next();
+    }
+    number
+}
+
+// A line number, . for the cursor line or $ for the last one, followed by any +N or -N
+fn parse_address(chars: &mut Peekable<Chars>, current: usize, last: usize) -> Result<Option<usize>, String> {
+    let mut line = match chars.peek() {
+        Some('.') => {
+            chars.next();
+            current as isize
+        }
+        Some('$') => {
+            chars.next();
+            last as isize
+        }
+        Some(c) if c.is_ascii_digit() => parse_number(chars).saturating_sub(1) as isize,
+        Some('+' | '-') => current as isize,
+        _ => return Ok(None),
+    };
+
+    while let Some(sign @ ('+' | '-')) = chars.peek().copied() {
+        chars.next();
+        let offset = if chars.peek().is_some_and(|c| c.is_ascii_digit()) { parse_number(chars) } else { 1 } as isize;
+        line += if sign == '+' { offset } else { -offset };
+    }
+
+    if line < 0 || line > last as isize {
+        return Err(format!("Line {} is out of range", line + 1));
+    }
+    Ok(Some(line as usize))
+}
+
+// % is the whole buffer, '<,'> the lines the cursors are on, no range the cursor line.
+// '<,'> is also limited to the selections if there are any, parse_substitute takes care of that
+fn parse_range(chars: &mut Peekable<Chars>, current: usize, last: usize, cursor_lines: (usize, usize)) -> Result<(usize, usize), String> {
+    if chars.next_if_eq(&'%').is_some() {
+        return Ok((0, last));
+    }
+    if chars.clone().take(5).eq("'<,'>".chars()) {
+        chars.nth(4);
+        return Ok(cursor_lines);
+    }
+
+    let Some(start) = parse_address(chars, current, last)? else {
+        return Ok((current, current));
+    };
+    let end = match chars.next_if_eq(&',') {
+        Some(_) => parse_address(chars, current, last)?.ok_or("Expected a line after ,")?,
+        None => start,
+    };
+    Ok((start.min(end), start.max(end)))
+}
+
+// Up to the next delimiter that isn't escaped, an escaped delimiter stands for itself
+fn parse_part(chars: &mut Peekable<Chars>, delimiter: char) -> String {
+    let mut part = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&delimiter) => part.push(chars.next().unwrap_or(delimiter)),
+            '\\' => {
+                part.push('\\');
+                part.extend(chars.next());
+            }
+            c if c == delimiter => break,
+            c => part.push(c),
+        }
+    }
+    part
+}
+
+// Vim style \1 and & become the regex crate's ${1} and ${0}, $1 and ${name} work as they are
+fn expansion_template(replacement: &str, newline: &str) -> String {
+    let mut template = String::new();
+    let mut chars = replacement.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => template.push_str("${0}"),
+            '\\' => match chars.next() {
+                Some(digit) if digit.is_ascii_digit() => template.push_str(&format!("${{{}}}", digit)),
+                Some('n' | 'r') => template.push_str(newline),
+                Some('t') => template.push('\t'),
+                Some('$') => template.push_str("$$"),
+                Some(other) => template.push(other),
+                None => template.push('\\'),
+            },
+            c => template.push(c),
+        }
+    }
+
+    template
+}
+
+fn plural(count: usize, one: &str, many: &str) -> String {
+    format!("{} {}", count, if count == 1 { one } else { many })
+}
+
+impl EditorPanel {
+    pub fn parse_substitute(&self, text: &str) -> Result<Substitute, String> {
+        let current = self.cursors.first().map(|c| c.line).unwrap_or(0);
+        let last = self.rope.len_lines().saturating_sub(1);
+        let cursor_lines = (
+            self.cursors.iter().map(|c| c.line).min().unwrap_or(current),
+            self.cursors.iter().map(|c| c.line).max().unwrap_or(current),
+        );
+
+        let text = text.trim_start();
+        let within = match text.starts_with("'<,'>") {
+            true => self.cursors.iter().filter_map(|c| self.selection_range(c)).collect(),
+            false => Vec::new(),
+        };
+
+        let mut chars = text.chars().peekable();
+        let lines = parse_range(&mut chars, current, last, cursor_lines)?;
+
+        if chars.next() != Some('s') {
+            return Err(String::from("Expected [range]s/pattern/replacement/[flags]"));
+        }
+        let delimiter = match chars.next() {
+            Some(c) if !c.is_alphanumeric() && !c.is_whitespace() && c != '\\' => c,
+            _ => return Err(String::from("Expected a delimiter after s, like /")),
+        };
+
+        let mut pattern = parse_part(&mut chars, delimiter);
+        let replacement = parse_part(&mut chars, delimiter);
+        if pattern.is_empty() {
+            // An empty pattern is the last search, like in vim
+            let last_search = search::last_search().ok_or("No previous search pattern")?;
+            pattern = if last_search.regex { last_search.query } else { regex::escape(&last_search.query) };
+        }
+
+        let mut substitute = Substitute { lines, within, pattern, replacement, global: false, confirm: false, ignore_case: None };
+        for flag in chars.filter(|c| !c.is_whitespace()) {
+            match flag {
+                'g' => substitute.global = true,
+                'c' => substitute.confirm = true,
+                'i' => substitute.ignore_case = Some(true),
+                'I' => substitute.ignore_case = Some(false),
+                _ => return Err(format!("Unknown flag '{}'", flag)),
+            }
+        }
+
+        Ok(substitute)
+    }
+
+    // Every replacement a substitute would make, each line is matched on its own like search
+    pub fn plan_substitute(&self, substitute: &Substitute) -> Result<Vec<Replacement>, String> {
+        let matcher = substitute.matcher()?;
+        let newline = if self.line_ending() == "CRLF" { "\r\n" } else { "\n" };
+        let template = expansion_template(&substitute.replacement, newline);
+        let (first, last) = substitute.lines;
+
+        let mut replacements = Vec::new();
+        for line in first..=last.min(self.rope.len_lines().saturating_sub(1)) {
+            let text = Self::line_text_rope(&self.rope, line);
+            let char_index = |byte: usize| text[..byte].chars().count();
+            let line_start = self.rope.line_to_char(line);
+
+            for captures in matcher.captures_iter(&text) {
+                let Some(found) = captures.get(0) else {
+                    continue;
+                };
+                let range = char_index(found.start())..char_index(found.end());
+                let inside = |s: &Range<usize>| s.start <= line_start + range.start && line_start + range.end <= s.end;
+                if !substitute.within.is_empty() && !substitute.within.iter().any(inside) {
+                    continue;
+                }
+
+                let mut expanded = String::new();
+                captures.expand(&template, &mut expanded);
+                replacements.push(Replacement { line, range, text: expanded });
+                if !substitute.global {
+                    break;
+                }
+            }
+        }
+
+        Ok(replacements)
+    }
+
+    // All in one batch of edits, the cursor ends on the first non-blank of the line the last replacement was on
+    fn apply_replacements(&mut self, replacements: &[Replacement]) {
+        let Some(last) = replacements.last() else {
+            return;
+        };
+
+        // Where the last replacement starts once the ones before it changed the length of the text
+        let shift: isize = replacements[..replacements.len() - 1].iter()
+            .map(|r| r.text.chars().count() as isize - r.range.len() as isize)
+            .sum();
+        let last_start = (self.rope.line_to_char(last.line) + last.range.start).saturating_add_signed(shift);
+
+        let ranges = replacements.iter()
+            .map(|r| {
+                let start = self.rope.line_to_char(r.line);
+                (start + r.range.start..start + r.range.end, r.text.clone())
+            })
+            .collect();
+        self.replace_ranges(ranges);
+
+        let line = self.rope.char_to_line(last_start.min(self.rope.len_chars()));
+        let column = Self::line_text_rope(&self.rope, line).chars().take_while(|c| c.is_whitespace()).count();
+        self.cursors = vec![Cursor::new(line, column, column)];
+        self.reveal_line(line);
+        self.update_goal_columns();
+
+        let mut lines: Vec<usize> = replacements.iter().map(|r| r.line).collect();
+        lines.dedup();
+        notifications::info(PLUGIN_ID, format!("{} on {}", plural(replacements.len(), "substitution", "substitutions"), plural(lines.len(), "line", "lines")));
+    }
+
+    // Runs a substitute, with the c flag it asks about each match before changing anything
+    pub fn substitute(&mut self, text: &str) {
+        if self.read_only {
+            self.fail("Buffer is read-only");
+            return;
+        }
+
+        let planned = self.parse_substitute(text).and_then(|s| self.plan_substitute(&s).map(|r| (s, r)));
+        let (substitute, replacements) = match planned {
+            Ok(planned) => planned,
+            Err(e) => {
+                self.fail_error(e);
+                return;
+            }
+        };
+
+        // n and N then go through the same pattern
+        search::remember(&Search { query: substitute.pattern.clone(), regex: true, direction: SearchDirection::Forward });
+
+        if replacements.is_empty() {
+            self.fail(format!("Pattern not found: {}", substitute.pattern));
+        } else if substitute.confirm {
+            self.substitute_prompt = Some(SubstitutePrompt {
+                text: text.to_string(),
+                error: None,
+                replacements,
+                confirming: Some(0),
+                accepted: Vec::new(),
+                origin: self.cursors.clone(),
+                origin_scroll: (self.scroll_offset, self.scroll_row, self.h_scroll),
+            });
+            self.show_confirm_match();
+        } else {
+            self.apply_replacements(&replacements);
+        }
+    }
+
+    // Opens the : prompt, with a selection or several cursors it starts on them like a visual selection in vim
+    pub fn start_substitute(&mut self) {
+        let lines = self.cursors.iter().map(|c| c.line);
+        let text = if lines.clone().min() != lines.max() || self.has_selection() { "'<,'>" } else { "" };
+
+        self.substitute_prompt = Some(SubstitutePrompt {
+            text: text.to_string(),
+            error: None,
+            replacements: Vec::new(),
+            confirming: None,
+            accepted: Vec::new(),
+            origin: self.cursors.clone(),
+            origin_scroll: (self.scroll_offset, self.scroll_row, self.h_scroll),
+        });
+    }
+
+    // What has been typed so far is planned again on every key for the preview
+    fn preview_substitute(&mut self) {
+        let Some(prompt) = &self.substitute_prompt else {
+            return;
+        };
+
+        let planned = match prompt.text.trim() {
+            "" => Ok(Vec::new()),
+            text => self.parse_substitute(text).and_then(|s| self.plan_substitute(&s)),
+        };
+        if let Some(prompt) = self.substitute_prompt.as_mut() {
+            (prompt.replacements, prompt.error) = match planned {
+                Ok(replacements) => (replacements, None),
+                Err(e) => (Vec::new(), Some(e)),
+            };
+        }
+    }
+
+    // Jumps to the match being asked about, or applies what was accepted once every match had an answer
+    fn show_confirm_match(&mut self) {
+        let Some(prompt) = &self.substitute_prompt else {
+            return;
+        };
+
+        match prompt.confirming.and_then(|i| prompt.replacements.get(i)) {
+            Some(replacement) => {
+                let (line, column) = (replacement.line, replacement.range.start);
+                self.cursors = vec![Cursor::new(line, column, column)];
+                self.reveal_line(line);
+                self.update_goal_columns();
+            }
+            None => {
+                let Some(prompt) = self.substitute_prompt.take() else {
+                    return;
+                };
+                self.cursors = prompt.origin;
+                (self.scroll_offset, self.scroll_row, self.h_scroll) = prompt.origin_scroll;
+                self.apply_replacements(&prompt.accepted);
+            }
+        }
+    }
+
+    // y replaces, n skips, a replaces this and the rest, l replaces this and stops, q stops
+    fn answer_confirm(&mut self, answer: char) {
+        let Some(prompt) = self.substitute_prompt.as_mut() else {
+            return;
+        };
+        let Some(index) = prompt.confirming else {
+            return;
+        };
+
+        let end = prompt.replacements.len();
+        let (accept, next) = match answer {
+            'y' => (index..index + 1, index + 1),
+            'n' => (index..index, index + 1),
+            'a' => (index..end, end),
+            'l' => (index..index + 1, end),
+            'q' => (index..index, end),
+            _ => return,
+        };
+        prompt.accepted.extend_from_slice(&prompt.replacements[accept]);
+        prompt.confirming = Some(next);
+        self.show_confirm_match();
+    }
+
+    fn cancel_substitute(&mut self) {
+        if let Some(prompt) = self.substitute_prompt.take() {
+            self.cursors = prompt.origin;
+            (self.scroll_offset, self.scroll_row, self.h_scroll) = prompt.origin_scroll;
+        }
+    }
+
+    fn confirm_substitute(&mut self) {
+        let Some(prompt) = self.substitute_prompt.take() else {
+            return;
+        };
+        self.cursors = prompt.origin;
+        (self.scroll_offset, self.scroll_row, self.h_scroll) = prompt.origin_scroll;
+        if !prompt.text.trim().is_empty() {
+            self.substitute(&prompt.text);
+        }
+    }
+
+    // Keys while the prompt is open, esc while confirming keeps what was already accepted
+    pub fn handle_substitute_keys(&mut self, keys: &[&str]) {
+        let confirming = self.substitute_prompt.as_ref().is_some_and(|p| p.confirming.is_some());
+        match keys {
+            ["esc"] if confirming => self.answer_confirm('q'),
+            ["enter"] if confirming => self.answer_confirm('y'),
+            ["esc"] => self.cancel_substitute(),
+            ["enter"] => self.confirm_substitute(),
+            ["backspace"] if !confirming => {
+                let Some(prompt) = self.substitute_prompt.as_mut() else {
+                    return;
+                };
+                if prompt.text.pop().is_none() {
+                    self.cancel_substitute();
+                    return;
+                }
+                self.preview_substitute();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn handle_substitute_char(&mut self, ch: char) {
+        match self.substitute_prompt.as_mut() {
+            Some(prompt) if prompt.confirming.is_some() => self.answer_confirm(ch),
+            Some(prompt) => {
+                prompt.text.push(ch);
+                self.preview_substitute();
+            }
+            None => {}
+        }
+    }
+
+    // The prompt row, ":%s/a/b/g  3 matches on 2 lines" while typing and what the current match turns into while confirming
+    pub fn substitute_prompt_line(&self) -> Option<(Line<'static>, usize)> {
+        let prompt = self.substitute_prompt.as_ref()?;
+
+        if let Some(replacement) = prompt.confirming.and_then(|i| prompt.replacements.get(i)) {
+            let line = Self::line_text_rope(&self.rope, replacement.line);
+            let old: String = line.chars().skip(replacement.range.start).take(replacement.range.len()).collect();
+            let spans = vec![
+                Span::raw("replace "),
+                Span::styled(old.escape_debug().to_string(), theme::style("diff.removed")),
+                Span::raw(" with "),
+                Span::styled(replacement.text.escape_debug().to_string(), theme::style("diff.added")),
+                Span::raw(" (y/n/a/l/q)?"),
+            ];
+            let cursor_x = spans.iter().map(|s| s.content.width()).sum();
+            return Some((Line::from(spans), cursor_x));
+        }
+
+        let text = format!(":{}", prompt.text);
+        let cursor_x = text.width();
+        let mut spans = vec![Span::raw(text)];
+        if let Some(error) = &prompt.error {
+            spans.push(Span::styled(format!("  {}", error), theme::style("error")));
+        } else if !prompt.replacements.is_empty() {
+            let mut lines: Vec<usize> = prompt.replacements.iter().map(|r| r.line).collect();
+            lines.dedup();
+            let summary = format!("  {} on {}", plural(prompt.replacements.len(), "match", "matches"), plural(lines.len(), "line", "lines"));
+            spans.push(Span::styled(summary, theme::style("ui.text.dim")));
+        }
+        Some((Line::from(spans), cursor_x))
+    }
+
+    // What the prompt would replace on one line, for the preview, with the match being asked about flagged
+    pub fn substitute_matches(&self, line: usize) -> Vec<(Range<usize>, bool)> {
+        let Some(prompt) = &self.substitute_prompt else {
+            return Vec::new();
+        };
+
+        let first = prompt.confirming.unwrap_or(0);
+        prompt.replacements.iter()
+            .enumerate()
+            .skip(first)
+            .filter(|(_, r)| r.line == line)
+            .map(|(i, r)| (r.range.clone(), prompt.confirming == Some(i)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(text: &str, current: usize, last: usize) -> Result<(usize, usize), String> {
+        parse_range(&mut text.chars().peekable(), current, last, (2, 4))
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(range("s/a/b/", 3, 9), Ok((3, 3)));
+        assert_eq!(range("%s/a/b/", 3, 9), Ok((0, 9)));
+        assert_eq!(range("'<,'>s/a/b/", 3, 9), Ok((2, 4)));
+        assert_eq!(range("2,5s", 3, 9), Ok((1, 4)));
+        assert_eq!(range("5,2s", 3, 9), Ok((1, 4)));
+        assert_eq!(range(".,$s", 3, 9), Ok((3, 9)));
+        assert_eq!(range(".+2s", 3, 9), Ok((5, 5)));
+        assert_eq!(range("-,+s", 3, 9), Ok((2, 4)));
+        assert_eq!(range("$-3,$s", 3, 9), Ok((6, 9)));
+    }
+
+    #[test]
+    fn rejects_bad_ranges() {
+        assert!(range("11s", 3, 9).is_err());
+        assert!(range(".-5s", 3, 9).is_err());
+        assert!(range("2,s", 3, 9).is_err());
+    }
+
+    #[test]
+    fn leaves_the_rest_after_the_range() {
+        let mut chars = "1,2s/a/b/".chars().peekable();
+        parse_range(&mut chars, 0, 5, (0, 0)).unwrap();
+        assert_eq!(chars.collect::<String>(), "s/a/b/");
+    }
+
+    #[test]
+    fn expands_vim_replacements() {
+        assert_eq!(expansion_template("\\2 \\1", "\n"), "${2} ${1}");
+        assert_eq!(expansion_template("[&]", "\n"), "[${0}]");
+        assert_eq!(expansion_template("a\\nb", "\r\n"), "a\r\nb");
+        assert_eq!(expansion_template("\\t\\$5", "\n"), "\t$$5");
+        assert_eq!(expansion_template("\\&\\/", "\n"), "&/");
+        assert_eq!(expansion_template("${name} $1", "\n"), "${name} $1");
+        assert_eq!(expansion_template("end\\", "\n"), "end\\");
+    }
+}
\ No newline at end of file
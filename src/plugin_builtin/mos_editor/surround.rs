@@ -0,0 +1,132 @@
+use crate::plugin_builtin::mos_editor::editor_panel::EditorPanel;
+use crate::plugin_builtin::mos_editor::text_objects::{bracket_pair, quote_pair};
+use std::ops::Range;
+
+// What a key surrounds with, an opening bracket puts spaces inside like in vim-surround
+fn surround_with(ch: char) -> (String, String) {
+    match ch {
+        '(' | '[' | '{' => {
+            let (open, close) = bracket_pair(ch).unwrap_or((ch, ch));
+            (format!("{} ", open), format!(" {}", close))
+        }
+        _ => match bracket_pair(ch) {
+            Some((open, close)) => (open.to_string(), close.to_string()),
+            None => (ch.to_string(), ch.to_string()),
+        },
+    }
+}
+
+impl EditorPanel {
+    // The delimiters of the pair a key stands for around a position. An opening bracket takes the spaces inside along
+    fn surrounding_pair(&self, pos: usize, ch: char) -> Option<(Range<usize>, Range<usize>)> {
+        if ch == 't' {
+            return self.enclosing_tag(pos);
+        }
+        let Some((open, close)) = bracket_pair(ch) else {
+            let line = self.rope.char_to_line(pos);
+            let line_start = self.rope.line_to_char(line);
+            let chars: Vec<char> = Self::line_text_rope(&self.rope, line).chars().collect();
+            let (open, close) = quote_pair(&chars, pos - line_start, ch)?;
+            return Some((line_start + open..line_start + open + 1, line_start + close..line_start + close + 1));
+        };
+
+        let (start, end) = self.enclosing_brackets(pos, open, close)?;
+        if !matches!(ch, '(' | '[' | '{') {
+            return Some((start..start + 1, end..end + 1));
+        }
+        let is_space = |c: Option<char>| matches!(c, Some(' ' | '\t'));
+        let mut inner_start = start + 1;
+        while inner_start < end && is_space(self.rope.get_char(inner_start)) {
+            inner_start += 1;
+        }
+        let mut inner_end = end;
+        while inner_end > inner_start && is_space(self.rope.get_char(inner_end - 1)) {
+            inner_end -= 1;
+        }
+        Some((start..inner_start, inner_end..end + 1))
+    }
+
+    // Replaces pairs of delimiter ranges in one batch, pairs around several cursors once. Cursors go to where the pairs start
+    fn replace_pairs(&mut self, pairs: Vec<(Range<usize>, Range<usize>)>, open: &str, close: &str) {
+        let mut pairs = pairs;
+        pairs.sort_by_key(|(open, _)| open.start);
+        pairs.dedup();
+
+        // Nested pairs are fine, their delimiters just can't overlap
+        let mut ranges: Vec<(Range<usize>, String)> = pairs.iter()
+            .flat_map(|(o, c)| [(o.clone(), open.to_string()), (c.clone(), close.to_string())])
+            .collect();
+        ranges.sort_by_key(|(range, _)| (range.start, range.end));
+        if ranges.windows(2).any(|w| w[0].0.end > w[1].0.start) {
+            self.fail("Surroundings overlap");
+            return;
+        }
+
+        let positions = pairs.iter()
+            .map(|(o, _)| {
+                let before: isize = ranges.iter()
+                    .filter(|(range, _)| range.start < o.start && range.end <= o.start)
+                    .map(|(range, text)| text.chars().count() as isize - range.len() as isize)
+                    .sum();
+                o.start.saturating_add_signed(before)
+            })
+            .collect();
+        self.replace_ranges(ranges);
+        self.move_to_positions(positions);
+    }
+
+    // ys with a text object and what to put around it, inside the whitespace the object took along
+    pub fn surround_objects(&mut self, around: bool, object: char, ch: char) {
+        let is_space = |pos: usize| self.rope.get_char(pos).is_some_and(char::is_whitespace);
+        let pairs = self.cursors.iter()
+            .filter_map(|c| self.text_object(self.position_to_char((c.line, c.column)), object, around))
+            .map(|mut r| {
+                while r.end > r.start && is_space(r.end - 1) {
+                    r.end -= 1;
+                }
+                while r.start < r.end && is_space(r.start) {
+                    r.start += 1;
+                }
+                (r.start..r.start, r.end..r.end)
+            })
+            .collect();
+        let (open, close) = surround_with(ch);
+        self.replace_pairs(pairs, &open, &close);
+    }
+
+    // S, puts something around every selection
+    pub fn surround_selections(&mut self, ch: char) {
+        let pairs = self.cursors.iter()
+            .filter_map(|c| self.selection_range(c))
+            .map(|r| (r.start..r.start, r.end..r.end))
+            .collect();
+        let (open, close) = surround_with(ch);
+        self.replace_pairs(pairs, &open, &close);
+    }
+
+    fn surrounding_pairs(&mut self, ch: char) -> Option<Vec<(Range<usize>, Range<usize>)>> {
+        let pairs: Vec<(Range<usize>, Range<usize>)> = self.cursors.iter()
+            .filter_map(|c| self.surrounding_pair(self.position_to_char((c.line, c.column)), ch))
+            .collect();
+        if pairs.is_empty() {
+            self.fail(format!("No {} around the cursor", ch));
+            return None;
+        }
+        Some(pairs)
+    }
+
+    // ds
+    pub fn delete_surround(&mut self, ch: char) {
+        if let Some(pairs) = self.surrounding_pairs(ch) {
+            self.replace_pairs(pairs, "", "");
+        }
+    }
+
+    // cs, a tag can only be replaced by a pair, there is no prompt for the new tag's name
+    pub fn change_surround(&mut self, old: char, new: char) {
+        if let Some(pairs) = self.surrounding_pairs(old) {
+            let (open, close) = surround_with(new);
+            self.replace_pairs(pairs, &open, &close);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,173 @@
+use crate::plugin_builtin::mos_editor::syntax::syntax::{Grammar, LineRule, Region};
+use std::path::Path;
+
+const RUST: Grammar = Grammar {
+    name: "rust",
+    extensions: &["rs"],
+    file_names: &[],
+    shebangs: &[],
+    line_rules: &[],
+    regions: &[
+        Region::new("comment", "/*", "*/").nested().multiline(),
+        Region::line("comment", "//"),
+        Region::new("string", "r#\"", "\"#").multiline(),
+        Region::new("string", "b\"", "\"").escape('\\').multiline(),
+        Region::new("string", "r\"", "\"").multiline(),
+        Region::new("string", "\"", "\"").escape('\\').multiline(),
+        Region::new("attribute", "#[", "]"),
+        Region::new("attribute", "#![", "]"),
+    ],
+    keywords: &[
+        ("keyword", &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn", "for",
+            "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct",
+            "super", "trait", "type", "unsafe", "use", "where", "while", "yield",
+        ]),
+        ("type", &[
+            "Self", "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+            "i128", "isize", "f32", "f64",
+        ]),
+        ("constant", &["true", "false", "self", "None", "Some", "Ok", "Err"]),
+    ],
+    numbers: true,
+    char_literals: true,
+    function_calls: true,
+    macros: true,
+    capitalized_types: true,
+    key_separator: None,
+};
+
+const LUA: Grammar = Grammar {
+    name: "lua",
+    extensions: &["lua"],
+    file_names: &[],
+    shebangs: &["lua", "luajit"],
+    line_rules: &[],
+    regions: &[
+        Region::new("comment", "--[[", "]]").multiline(),
+        Region::line("comment", "--"),
+        Region::new("string", "[[", "]]").multiline(),
+        Region::new("string", "\"", "\"").escape('\\'),
+        Region::new("string", "'", "'").escape('\\'),
+    ],
+    keywords: &[
+        ("keyword", &[
+            "and", "break", "do", "else", "elseif", "end", "for", "function", "goto", "if", "in", "local", "not", "or",
+            "repeat", "return", "then", "until", "while",
+        ]),
+        ("constant", &["true", "false", "nil", "self"]),
+        ("type", &["string", "table", "math", "io", "os", "coroutine", "debug", "utf8", "mos"]),
+    ],
+    numbers: true,
+    char_literals: false,
+    function_calls: true,
+    macros: false,
+    capitalized_types: false,
+    key_separator: None,
+};
+
+const TOML: Grammar = Grammar {
+    name: "toml",
+    extensions: &["toml"],
+    file_names: &["Cargo.lock"],
+    shebangs: &[],
+    line_rules: &[
+        LineRule::Prefix { prefix: "[", group: "type", whole_line: true },
+    ],
+    regions: &[
+        Region::line("comment", "#"),
+        Region::new("string", "\"\"\"", "\"\"\"").escape('\\').multiline(),
+        Region::new("string", "'''", "'''").multiline(),
+        Region::new("string", "\"", "\"").escape('\\'),
+        Region::new("string", "'", "'"),
+    ],
+    keywords: &[
+        ("constant", &["true", "false", "inf", "nan"]),
+    ],
+    numbers: true,
+    char_literals: false,
+    function_calls: false,
+    macros: false,
+    capitalized_types: false,
+    key_separator: Some('='),
+};
+
+const JSON: Grammar = Grammar {
+    name: "json",
+    extensions: &["json", "jsonc"],
+    file_names: &[],
+    shebangs: &[],
+    line_rules: &[],
+    regions: &[
+        Region::line("comment", "//"),
+        Region::new("string", "\"", "\"").escape('\\'),
+    ],
+    keywords: &[
+        ("constant", &["true", "false", "null"]),
+    ],
+    numbers: true,
+    char_literals: false,
+    function_calls: false,
+    macros: false,
+    capitalized_types: false,
+    key_separator: Some(':'),
+};
+
+const MARKDOWN: Grammar = Grammar {
+    name: "markdown",
+    extensions: &["md", "markdown"],
+    file_names: &[],
+    shebangs: &[],
+    line_rules: &[
+        LineRule::Prefix { prefix: "#", group: "heading", whole_line: true },
+        LineRule::Prefix { prefix: ">", group: "markup.quote", whole_line: true },
+        LineRule::Prefix { prefix: "- ", group: "markup.list", whole_line: false },
+        LineRule::Prefix { prefix: "* ", group: "markup.list", whole_line: false },
+        LineRule::Prefix { prefix: "+ ", group: "markup.list", whole_line: false },
+        LineRule::OrderedList { group: "markup.list" },
+    ],
+    regions: &[
+        Region::new("markup.code", "```", "```").multiline().line_start(),
+        Region::new("markup.code", "`", "`"),
+        Region::new("markup.bold", "**", "**"),
+        Region::new("markup.italic", "*", "*"),
+        Region::new("markup.link", "](", ")"),
+        Region::new("comment", "<!--", "-->").multiline(),
+    ],
+    keywords: &[],
+    numbers: false,
+    char_literals: false,
+    function_calls: false,
+    macros: false,
+    capitalized_types: false,
+    key_separator: None,
+};
+
+pub static GRAMMARS: [Grammar; 5] = [RUST, LUA, TOML, JSON, MARKDOWN];
+
+pub fn by_name(name: &str) -> Option<&'static Grammar> {
+    GRAMMARS.iter().find(|g| g.name == name)
+}
+
+// "#!/usr/bin/env lua" and "#!/usr/bin/lua5.1" are both lua
+fn shebang_interpreter(first_line: &str) -> Option<String> {
+    let command = first_line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+    Some(program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.').to_string())
+}
+
+// Picks a grammar by file name, then extension, then shebang
+pub fn detect(path: Option<&Path>, first_line: &str) -> Option<&'static Grammar> {
+    let file_name = path.and_then(|p| p.file_name()).map(|n| n.to_string_lossy().to_string());
+    let extension = path.and_then(|p| p.extension()).map(|e| e.to_string_lossy().to_lowercase());
+    let interpreter = shebang_interpreter(first_line);
+
+    GRAMMARS.iter()
+        .find(|g| file_name.as_deref().is_some_and(|n| g.file_names.contains(&n)))
+        .or_else(|| GRAMMARS.iter().find(|g| extension.as_deref().is_some_and(|e| g.extensions.contains(&e))))
+        .or_else(|| GRAMMARS.iter().find(|g| interpreter.as_deref().is_some_and(|i| g.shebangs.contains(&i))))
+}
\ No newline at end of file
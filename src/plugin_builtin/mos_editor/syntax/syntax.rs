@@ -0,0 +1,421 @@
+use crate::system::theme;
+use ratatui::text::Span;
+use ropey::Rope;
+use std::ops::Range;
+
+// Text between start and end, e.g. a string or a comment, the only tokens that can span lines
+pub struct Region {
+    pub group: &'static str,
+    pub start: &'static str,
+    pub end: Option<&'static str>, // None runs to the end of the line, like a line comment
+    pub escape: Option<char>,
+    pub nested: bool, // rust block comments
+    pub multiline: bool,
+    pub line_start: bool, // only after leading whitespace, markdown code fences
+}
+
+impl Region {
+    pub const fn new(group: &'static str, start: &'static str, end: &'static str) -> Self {
+        Self { group, start, end: Some(end), escape: None, nested: false, multiline: false, line_start: false }
+    }
+
+    pub const fn line(group: &'static str, start: &'static str) -> Self {
+        Self { group, start, end: None, escape: None, nested: false, multiline: false, line_start: false }
+    }
+
+    pub const fn escape(mut self, escape: char) -> Self {
+        self.escape = Some(escape);
+        self
+    }
+
+    pub const fn nested(mut self) -> Self {
+        self.nested = true;
+        self
+    }
+
+    pub const fn multiline(mut self) -> Self {
+        self.multiline = true;
+        self
+    }
+
+    pub const fn line_start(mut self) -> Self {
+        self.line_start = true;
+        self
+    }
+}
+
+// Checked once at the first non-whitespace char of a line
+pub enum LineRule {
+    Prefix { prefix: &'static str, group: &'static str, whole_line: bool },
+    OrderedList { group: &'static str }, // "1. "
+}
+
+// A language, all matching is done on chars so grammars stay plain data
+pub struct Grammar {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub file_names: &'static [&'static str],
+    pub shebangs: &'static [&'static str], // interpreter names, "lua" for #!/usr/bin/env lua
+    pub line_rules: &'static [LineRule],
+    pub regions: &'static [Region], // tried in order, so longer starts go first
+    pub keywords: &'static [(&'static str, &'static [&'static str])], // group and its words
+    pub numbers: bool,
+    pub char_literals: bool, // 'a' is a string, 'a without a closing quote a label
+    pub function_calls: bool, // word( is a function
+    pub macros: bool, // word! is a macro
+    pub capitalized_types: bool, // Word is a type
+    pub key_separator: Option<char>, // a word or string followed by it is a property, ':' in json
+}
+
+// Where a line starts, inside a region that was opened on an earlier line or not
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LineState {
+    region: Option<usize>,
+    depth: usize,
+}
+
+pub struct Token {
+    pub range: Range<usize>, // chars in the line
+    pub group: &'static str,
+}
+
+fn starts_with(chars: &[char], at: usize, pattern: &str) -> bool {
+    pattern.chars().enumerate().all(|(i, p)| chars.get(at + i) == Some(&p))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Grammar {
+    // Lexes one line without its line break, returns the tokens and the state the next line starts in
+    pub fn lex_line(&self, chars: &[char], state: LineState) -> (Vec<Token>, LineState) {
+        let mut tokens = Vec::new();
+        let mut pos = 0;
+        let mut state = state;
+
+        if let Some(index) = state.region {
+            let (end, next) = self.region_end(&self.regions[index], chars, 0, state.depth);
+            tokens.push(Token { range: 0..end, group: self.regions[index].group });
+            pos = end;
+            state = next.map(|depth| LineState { region: Some(index), depth }).unwrap_or_default();
+            if state.region.is_some() {
+                return (tokens, state);
+            }
+        }
+
+        let first = chars.iter().position(|c| !c.is_whitespace()).unwrap_or(chars.len());
+        if pos <= first
+            && let Some(token) = self.line_rule(chars, first) {
+            pos = token.range.end;
+            tokens.push(token);
+        }
+
+        while pos < chars.len() {
+            let c = chars[pos];
+            let at_line_start = pos == first;
+
+            if let Some((index, region)) = self.regions.iter().enumerate()
+                .find(|(_, r)| (!r.line_start || at_line_start) && starts_with(chars, pos, r.start)) {
+                let body = pos + region.start.chars().count();
+                let (end, open) = self.region_end(region, chars, body, 1);
+                let group = if region.group == "string" && self.followed_by_key_separator(chars, end) {
+                    "property"
+                } else {
+                    region.group
+                };
+                tokens.push(Token { range: pos..end, group });
+                pos = end;
+
+                if let Some(depth) = open
+                    && region.multiline {
+                    return (tokens, LineState { region: Some(index), depth });
+                }
+                continue;
+            }
+
+            if self.char_literals && c == '\'' {
+                let end = self.char_literal_end(chars, pos);
+                let group = if end.is_some() { "string" } else { "label" };
+                let end = end.unwrap_or_else(|| Self::word_end(chars, pos + 1));
+                tokens.push(Token { range: pos..end, group });
+                pos = end;
+                continue;
+            }
+
+            let after_word = pos == 0 || !is_word_char(chars[pos - 1]);
+            if self.numbers && after_word && c.is_ascii_digit() {
+                let mut end = pos + 1;
+                while end < chars.len() && (is_word_char(chars[end]) || (chars[end] == '.' && chars.get(end + 1).is_some_and(|c| c.is_ascii_digit()))) {
+                    end += 1;
+                }
+                tokens.push(Token { range: pos..end, group: "number" });
+                pos = end;
+                continue;
+            }
+
+            if after_word && (c.is_alphabetic() || c == '_') {
+                let end = Self::word_end(chars, pos);
+                if let Some(group) = self.word_group(chars, pos, end) {
+                    tokens.push(Token { range: pos..end, group });
+                }
+                pos = end;
+                continue;
+            }
+
+            pos += 1;
+        }
+
+        (tokens, LineState::default())
+    }
+
+    fn line_rule(&self, chars: &[char], first: usize) -> Option<Token> {
+        for rule in self.line_rules {
+            match rule {
+                LineRule::Prefix { prefix, group, whole_line } if starts_with(chars, first, prefix) => {
+                    let end = if *whole_line { chars.len() } else { first + prefix.chars().count() };
+                    return Some(Token { range: first..end, group });
+                }
+                LineRule::OrderedList { group } => {
+                    let digits = chars[first..].iter().take_while(|c| c.is_ascii_digit()).count();
+                    if digits > 0 && starts_with(chars, first + digits, ". ") {
+                        return Some(Token { range: first..first + digits + 2, group });
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // Where a region that started before from ends, and the nesting depth if it is still open at the end of the line
+    fn region_end(&self, region: &Region, chars: &[char], from: usize, depth: usize) -> (usize, Option<usize>) {
+        let Some(end) = region.end else {
+            return (chars.len(), None);
+        };
+
+        let mut depth = depth;
+        let mut pos = from;
+        while pos < chars.len() {
+            if region.escape == Some(chars[pos]) {
+                pos += 2;
+            } else if region.nested && starts_with(chars, pos, region.start) {
+                depth += 1;
+                pos += region.start.chars().count();
+            } else if starts_with(chars, pos, end) {
+                pos += end.chars().count();
+                depth -= 1;
+                if depth == 0 {
+                    return (pos, None);
+                }
+            } else {
+                pos += 1;
+            }
+        }
+
+        (chars.len(), Some(depth))
+    }
+
+    // 'a', '\n' or '\u{1f600}', None for a lifetime like 'a
+    fn char_literal_end(&self, chars: &[char], pos: usize) -> Option<usize> {
+        let end = if chars.get(pos + 1) == Some(&'\\') {
+            (pos + 2..chars.len().min(pos + 12)).find(|&i| chars[i] == '\'' && i > pos + 2)?
+        } else {
+            pos + 2
+        };
+        (chars.get(end) == Some(&'\'')).then_some(end + 1)
+    }
+
+    fn word_end(chars: &[char], pos: usize) -> usize {
+        let mut end = pos;
+        while end < chars.len() && is_word_char(chars[end]) {
+            end += 1;
+        }
+        end
+    }
+
+    fn next_non_space(chars: &[char], pos: usize) -> Option<char> {
+        chars[pos.min(chars.len())..].iter().find(|c| !c.is_whitespace()).copied()
+    }
+
+    fn followed_by_key_separator(&self, chars: &[char], pos: usize) -> bool {
+        self.key_separator.is_some() && Self::next_non_space(chars, pos) == self.key_separator
+    }
+
+    fn word_group(&self, chars: &[char], start: usize, end: usize) -> Option<&'static str> {
+        let word: String = chars[start..end].iter().collect();
+
+        if let Some((group, _)) = self.keywords.iter().find(|(_, words)| words.contains(&word.as_str())) {
+            return Some(group);
+        }
+        if self.followed_by_key_separator(chars, end) {
+            return Some("property");
+        }
+        if self.macros && chars.get(end) == Some(&'!') {
+            return Some("macro");
+        }
+        if self.function_calls && Self::next_non_space(chars, end) == Some('(') {
+            return Some("function");
+        }
+        if self.capitalized_types && word.starts_with(|c: char| c.is_uppercase()) {
+            return Some("type");
+        }
+        None
+    }
+}
+
+// Highlights the lines of one buffer, the state at the start of each line is cached so only edited lines get lexed again
+pub struct Highlighter {
+    pub grammar: &'static Grammar,
+    states: Vec<LineState>, // states[n] is where line n starts, valid for every entry
+}
+
+impl Highlighter {
+    pub fn new(grammar: &'static Grammar) -> Self {
+        Self {
+            grammar,
+            states: vec![LineState::default()],
+        }
+    }
+
+    // Lines past the end are empty, the view can be left there until it is clamped
+    fn line_chars(rope: &Rope, line: usize) -> Vec<char> {
+        if line >= rope.len_lines() {
+            return Vec::new();
+        }
+        let mut chars: Vec<char> = rope.line(line).chars().collect();
+        while chars.last().is_some_and(|c| *c == '\n' || *c == '\r') {
+            chars.pop();
+        }
+        chars
+    }
+
+    // Everything from this line on has to be lexed again, earlier lines can't be affected by an edit here
+    pub fn invalidate(&mut self, line: usize) {
+        self.states.truncate(line + 1);
+    }
+
+    fn state_at(&mut self, rope: &Rope, line: usize) -> LineState {
+        while self.states.len() <= line {
+            let previous = self.states.len() - 1;
+            let (_, next) = self.grammar.lex_line(&Self::line_chars(rope, previous), self.states[previous]);
+            self.states.push(next);
+        }
+        self.states[line]
+    }
+
+    pub fn highlight(&mut self, rope: &Rope, line: usize) -> Vec<Span<'static>> {
+        if line >= rope.len_lines() {
+            return Vec::new();
+        }
+        let chars = Self::line_chars(rope, line);
+        let (tokens, _) = self.grammar.lex_line(&chars, self.state_at(rope, line));
+
+        let mut spans = Vec::new();
+        let mut pos = 0;
+        for token in tokens {
+            if token.range.start > pos {
+                spans.push(Span::raw(chars[pos..token.range.start].iter().collect::<String>()));
+            }
+            let text: String = chars[token.range.clone()].iter().collect();
+            spans.push(Span::styled(text, theme::style(token.group)));
+            pos = token.range.end;
+        }
+        if pos < chars.len() {
+            spans.push(Span::raw(chars[pos..].iter().collect::<String>()));
+        }
+
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin_builtin::mos_editor::syntax::grammars;
+
+    fn rust() -> &'static Grammar {
+        grammars::by_name("rust").unwrap()
+    }
+
+    fn lex(line: &str, state: LineState) -> (Vec<(String, &'static str)>, LineState) {
+        let chars: Vec<char> = line.chars().collect();
+        let (tokens, state) = rust().lex_line(&chars, state);
+        let tokens = tokens.into_iter().map(|t| (chars[t.range].iter().collect(), t.group)).collect();
+        (tokens, state)
+    }
+
+    fn text(spans: &[Span<'static>]) -> String {
+        spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn regions_span_lines() {
+        let (tokens, state) = lex("let a = /* one", LineState::default());
+        assert_eq!(tokens.last(), Some(&("/* one".to_string(), "comment")));
+        assert_eq!(state, LineState { region: Some(0), depth: 1 });
+
+        let (tokens, state) = lex("two", state);
+        assert_eq!(tokens, vec![("two".to_string(), "comment")]);
+        let (tokens, state) = lex("three */ fn", state);
+        assert_eq!(tokens, vec![("three */".to_string(), "comment"), ("fn".to_string(), "keyword")]);
+        assert_eq!(state, LineState::default());
+
+        // Only regions marked multiline stay open, a line comment or attribute ends with its line
+        assert_eq!(lex("// a /* b", LineState::default()).1, LineState::default());
+        assert_eq!(lex("#[derive(", LineState::default()).1, LineState::default());
+    }
+
+    #[test]
+    fn comments_nest() {
+        let (tokens, state) = lex("/* a /* b */ c */ x", LineState::default());
+        assert_eq!(tokens, vec![("/* a /* b */ c */".to_string(), "comment")]);
+        assert_eq!(state, LineState::default());
+
+        let (_, state) = lex("/* a /* b", LineState::default());
+        assert_eq!(state, LineState { region: Some(0), depth: 2 });
+        let (_, state) = lex("*/", state);
+        assert_eq!(state, LineState { region: Some(0), depth: 1 });
+        let (tokens, state) = lex("*/ true", state);
+        assert_eq!(tokens[1], ("true".to_string(), "constant"));
+        assert_eq!(state, LineState::default());
+    }
+
+    #[test]
+    fn escapes_stay_in_strings() {
+        let (tokens, _) = lex(r#""a\\"b" c"#, LineState::default());
+        assert_eq!(tokens[0], (r#""a\\""#.to_string(), "string"));
+
+        let (tokens, state) = lex(r#"x = "a\"b" + 1"#, LineState::default());
+        assert_eq!(tokens, vec![(r#""a\"b""#.to_string(), "string"), ("1".to_string(), "number")]);
+        assert_eq!(state, LineState::default());
+
+        // An escaped quote at the end of the line leaves the string open
+        let (_, state) = lex(r#""a\""#, LineState::default());
+        assert_eq!(state, LineState { region: Some(5), depth: 1 });
+    }
+
+    #[test]
+    fn invalidate_lexes_edited_lines_again() {
+        let mut rope = Rope::from_str("let x\nlet y\n");
+        let mut highlighter = Highlighter::new(rust());
+        assert_eq!(highlighter.highlight(&rope, 1)[0].style, theme::style("keyword"));
+
+        rope.insert(0, "/* ");
+        highlighter.invalidate(0);
+        assert_eq!(text(&highlighter.highlight(&rope, 0)), "/* let x");
+        assert_eq!(highlighter.highlight(&rope, 1)[0].style, theme::style("comment"));
+
+        rope.remove(0..3);
+        highlighter.invalidate(0);
+        assert_eq!(highlighter.highlight(&rope, 1)[0].style, theme::style("keyword"));
+    }
+
+    #[test]
+    fn lines_past_the_end_are_empty() {
+        let rope = Rope::from_str("fn main() {}");
+        let mut highlighter = Highlighter::new(rust());
+        assert!(highlighter.highlight(&rope, 90).is_empty());
+        assert_eq!(text(&highlighter.highlight(&rope, 0)), "fn main() {}");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,543 @@
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel, Mode};
+use crate::plugin_builtin::mos_editor::registers::{self, Register};
+use crate::plugin_builtin::mos_editor::selection::is_word_char;
+use regex::Regex;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+// A whole tag, its slash if it closes, its name and its slash if it closes itself
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^<(/?)([A-Za-z][\w:.-]*)[^<>]*?(/?)>$").unwrap());
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Select, // v followed by a text object
+    Surround, // ys
+}
+
+// A normal mode command waiting for more keys, e.g. ci waiting for the object in ci"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextPending {
+    Operator(Operator), // waiting for i or a
+    Object(Operator, bool), // waiting for the object, true after a
+    SurroundWith(bool, char), // ys with its object, waiting for what to surround it with
+    SurroundDelete, // ds
+    SurroundChange, // cs, waiting for the pair to replace
+    SurroundChangeTo(char), // and what replaces it
+    SurroundSelections, // S
+}
+
+// The bracket pair a key stands for, either bracket works and b and B are ( and {
+pub fn bracket_pair(ch: char) -> Option<(char, char)> {
+    match ch {
+        '(' | ')' | 'b' => Some(('(', ')')),
+        '[' | ']' => Some(('[', ']')),
+        '{' | '}' | 'B' => Some(('{', '}')),
+        '<' | '>' => Some(('<', '>')),
+        _ => None,
+    }
+}
+
+pub fn is_text_object(ch: char) -> bool {
+    matches!(ch, 'w' | 'W' | 's' | 'p' | '"' | '\'' | '`' | 't' | 'i') || bracket_pair(ch).is_some()
+}
+
+// Quotes on a line pair up from its start, escaped ones don't count. The pair around a column, or else the next one
+pub fn quote_pair(chars: &[char], column: usize, quote: char) -> Option<(usize, usize)> {
+    let quotes: Vec<usize> = chars.iter()
+        .enumerate()
+        .filter(|(i, c)| **c == quote && (*i == 0 || chars[i - 1] != '\\'))
+        .map(|(i, _)| i)
+        .collect();
+    quotes.chunks_exact(2).map(|pair| (pair[0], pair[1])).find(|(_, close)| column <= *close)
+}
+
+struct Tag {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+}
+
+fn parse_tag(text: &str) -> Option<Tag> {
+    let captures = TAG.captures(text)?;
+    Some(Tag { name: captures[2].to_string(), closing: &captures[1] == "/", self_closing: &captures[3] == "/" })
+}
+
+fn is_blank(text: &str) -> bool {
+    text.chars().all(char::is_whitespace)
+}
+
+impl EditorPanel {
+    // The chars of a text object around a position, around takes its whitespace or delimiters along
+    pub fn text_object(&self, pos: usize, object: char, around: bool) -> Option<Range<usize>> {
+        match object {
+            'w' => self.word_object(pos, around, false),
+            'W' => self.word_object(pos, around, true),
+            's' => self.sentence_object(pos, around),
+            'p' => self.paragraph_object(pos, around),
+            '"' | '\'' | '`' => self.quote_object(pos, object, around),
+            't' => {
+                let (open, close) = self.enclosing_tag(pos)?;
+                Some(if around { open.start..close.end } else { open.end..close.start })
+            }
+            'i' => self.indent_object(pos, around),
+            _ => {
+                let (open, close) = bracket_pair(object)?;
+                let (start, end) = self.enclosing_brackets(pos, open, close)?;
+                Some(if around { start..end + 1 } else { start + 1..end })
+            }
+        }
+    }
+
+    fn line_at(&self, pos: usize) -> (usize, usize, Vec<char>) {
+        let line = self.rope.char_to_line(pos.min(self.rope.len_chars()));
+        (line, self.rope.line_to_char(line), Self::line_text_rope(&self.rope, line).chars().collect())
+    }
+
+    // A run of word chars, of other non-blank chars or of whitespace, W only tells blank from non-blank
+    fn word_object(&self, pos: usize, around: bool, big: bool) -> Option<Range<usize>> {
+        let (_, line_start, chars) = self.line_at(pos);
+        if chars.is_empty() {
+            return None;
+        }
+        let class = |c: char| if c.is_whitespace() { 0 } else if big || is_word_char(c) { 1 } else { 2 };
+
+        let column = (pos - line_start).min(chars.len() - 1);
+        let kind = class(chars[column]);
+        let mut start = column;
+        while start > 0 && class(chars[start - 1]) == kind {
+            start -= 1;
+        }
+        let mut end = column + 1;
+        while end < chars.len() && class(chars[end]) == kind {
+            end += 1;
+        }
+
+        // Whitespace goes with the word after it, a word takes the whitespace after it or else before it
+        if around && kind == 0 {
+            if let Some(next) = chars.get(end).map(|c| class(*c)) {
+                while end < chars.len() && class(chars[end]) == next {
+                    end += 1;
+                }
+            }
+        } else if around {
+            let trailing = chars[end..].iter().take_while(|c| c.is_whitespace()).count();
+            if trailing > 0 {
+                end += trailing;
+            } else {
+                start -= chars[..start].iter().rev().take_while(|c| c.is_whitespace()).count();
+            }
+        }
+        Some(line_start + start..line_start + end)
+    }
+
+    // The lines around one that are all blank or all not, with whether they are blank
+    fn paragraph_lines(&self, line: usize) -> (usize, usize, bool) {
+        let blank = |line: usize| is_blank(&Self::line_text_rope(&self.rope, line));
+        let kind = blank(line);
+        let mut first = line;
+        while first > 0 && blank(first - 1) == kind {
+            first -= 1;
+        }
+        let mut last = line;
+        while last + 1 < self.rope.len_lines() && blank(last + 1) == kind {
+            last += 1;
+        }
+        (first, last, kind)
+    }
+
+    // Whole lines, around takes the blank lines after the paragraph along, or before it at the end of the buffer
+    fn paragraph_object(&self, pos: usize, around: bool) -> Option<Range<usize>> {
+        let (line, _, _) = self.line_at(pos);
+        let (mut first, mut last, _) = self.paragraph_lines(line);
+        if around {
+            if last + 1 < self.rope.len_lines() {
+                last = self.paragraph_lines(last + 1).1;
+            } else if first > 0 {
+                first = self.paragraph_lines(first - 1).0;
+            }
+        }
+        Some(self.rope.line_to_char(first)..self.rope.line_to_char(last + 1))
+    }
+
+    // Sentences end at . ! or ? followed by whitespace, and never go past their paragraph
+    fn sentence_object(&self, pos: usize, around: bool) -> Option<Range<usize>> {
+        let (line, _, _) = self.line_at(pos);
+        let (first, last, blank) = self.paragraph_lines(line);
+        if blank {
+            return None;
+        }
+        let start = self.rope.line_to_char(first);
+        let end = self.rope.line_to_char(last) + Self::line_visible_len_rope(&self.rope, last);
+        let chars: Vec<char> = self.rope.slice(start..end).chars().collect();
+
+        // Each sentence as its start, its end and where the next one starts
+        let mut sentences: Vec<(usize, usize, usize)> = Vec::new();
+        let (mut from, mut i) = (0, 0);
+        while i < chars.len() {
+            if matches!(chars[i], '.' | '!' | '?') {
+                let close = i + 1 + chars[i + 1..].iter().take_while(|c| matches!(c, ')' | ']' | '"' | '\'')).count();
+                if close == chars.len() || chars[close].is_whitespace() {
+                    let next = close + chars[close..].iter().take_while(|c| c.is_whitespace()).count();
+                    sentences.push((from, close, next));
+                    (from, i) = (next, next);
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        if from < chars.len() {
+            let end = chars.len() - chars[from..].iter().rev().take_while(|c| c.is_whitespace()).count();
+            sentences.push((from, end, chars.len()));
+        }
+
+        let offset = pos.saturating_sub(start);
+        let index = sentences.iter().position(|(_, _, next)| offset < *next).unwrap_or(sentences.len().checked_sub(1)?);
+        let (from, to, next) = sentences[index];
+        let range = match (around, index.checked_sub(1)) {
+            (false, _) => from..to,
+            (true, _) if next > to => from..next,
+            // The last sentence has no whitespace after it, it takes the whitespace before it
+            (true, Some(previous)) => sentences[previous].1..to,
+            (true, None) => from..to,
+        };
+        Some(start + range.start..start + range.end)
+    }
+
+    // Quotes pair up on the line of the position, around takes the whitespace after the closing one along
+    fn quote_object(&self, pos: usize, quote: char, around: bool) -> Option<Range<usize>> {
+        let (_, line_start, chars) = self.line_at(pos);
+        let (open, close) = quote_pair(&chars, pos - line_start, quote)?;
+        if !around {
+            return Some(line_start + open + 1..line_start + close);
+        }
+
+        let trailing = chars[close + 1..].iter().take_while(|c| c.is_whitespace()).count();
+        Some(line_start + open..line_start + close + 1 + trailing)
+    }
+
+    // The positions of the brackets enclosing a position, on the opening bracket counts as inside
+    pub fn enclosing_brackets(&self, pos: usize, open: char, close: char) -> Option<(usize, usize)> {
+        let start = if self.rope.get_char(pos) == Some(open) {
+            pos
+        } else {
+            let mut chars = self.rope.chars_at(pos);
+            let (mut at, mut depth) = (pos, 0);
+            loop {
+                let c = chars.prev()?;
+                at -= 1;
+                if c == close {
+                    depth += 1;
+                } else if c == open && depth == 0 {
+                    break at;
+                } else if c == open {
+                    depth -= 1;
+                }
+            }
+        };
+
+        let mut depth = 0;
+        for (i, c) in self.rope.chars_at(start + 1).enumerate() {
+            if c == open {
+                depth += 1;
+            } else if c == close && depth == 0 {
+                return Some((start, start + 1 + i));
+            } else if c == close {
+                depth -= 1;
+            }
+        }
+        None
+    }
+
+    // The opening and closing tag of the innermost element around a position, self-closing and unclosed tags are skipped.
+    // Walks back from the position to each opening tag not closed before it, then forward to where that one closes
+    pub fn enclosing_tag(&self, pos: usize) -> Option<(Range<usize>, Range<usize>)> {
+        let from = (pos + 1).min(self.rope.len_chars());
+        let mut chars = self.rope.chars_at(from);
+        let mut at = from;
+        let mut closed: Vec<String> = Vec::new();
+        while let Some(c) = chars.prev() {
+            at -= 1;
+            if c != '<' {
+                continue;
+            }
+            let Some((tag, range)) = self.tag_at(at) else {
+                continue;
+            };
+            if tag.self_closing {
+                continue;
+            }
+            if tag.closing {
+                // One the position is in closes the element it is looking for
+                if range.end <= pos {
+                    closed.push(tag.name);
+                }
+                continue;
+            }
+            if let Some(index) = closed.iter().rposition(|name| *name == tag.name) {
+                closed.truncate(index);
+                continue;
+            }
+            if let Some(close) = self.closing_tag(&tag.name, range.end)
+                && pos < close.end {
+                return Some((range, close));
+            }
+        }
+        None
+    }
+
+    // Where an element opened right before a position closes, none when something outside it closes first
+    fn closing_tag(&self, name: &str, from: usize) -> Option<Range<usize>> {
+        let mut open: Vec<String> = Vec::new();
+        for (i, c) in self.rope.chars_at(from).enumerate() {
+            if c != '<' {
+                continue;
+            }
+            let Some((tag, range)) = self.tag_at(from + i) else {
+                continue;
+            };
+            if tag.self_closing {
+                continue;
+            }
+            if !tag.closing {
+                open.push(tag.name);
+            } else if let Some(index) = open.iter().rposition(|open| *open == tag.name) {
+                open.truncate(index);
+            } else {
+                return (tag.name == name).then_some(range);
+            }
+        }
+        None
+    }
+
+    // The tag starting at a <, read up to its > without copying more of the buffer
+    fn tag_at(&self, start: usize) -> Option<(Tag, Range<usize>)> {
+        let mut text = String::from('<');
+        for c in self.rope.chars_at(start + 1) {
+            if c == '<' {
+                return None;
+            }
+            text.push(c);
+            if c == '>' {
+                let end = start + text.chars().count();
+                return Some((parse_tag(&text)?, start..end));
+            }
+        }
+        None
+    }
+
+    fn indent_width(&self, line: usize) -> usize {
+        Self::line_text_rope(&self.rope, line).chars()
+            .take_while(|c| c.is_whitespace())
+            .map(|c| if c == '\t' { self.tab_width } else { 1 })
+            .sum()
+    }
+
+    // The lines indented at least as deep as the position's, blank lines in between too. Around adds the line above
+    fn indent_object(&self, pos: usize, around: bool) -> Option<Range<usize>> {
+        let (line, _, chars) = self.line_at(pos);
+        if chars.iter().all(|c| c.is_whitespace()) {
+            return None;
+        }
+        let blank = |line: usize| is_blank(&Self::line_text_rope(&self.rope, line));
+        let level = self.indent_width(line);
+        let inside = |other: usize| blank(other) || self.indent_width(other) >= level;
+
+        let mut first = line;
+        while first > 0 && inside(first - 1) {
+            first -= 1;
+        }
+        let mut last = line;
+        while last + 1 < self.rope.len_lines() && inside(last + 1) {
+            last += 1;
+        }
+        while blank(first) {
+            first += 1;
+        }
+        while blank(last) {
+            last -= 1;
+        }
+        if around && first > 0 {
+            first -= 1;
+        }
+        Some(self.rope.line_to_char(first)..self.rope.line_to_char(last + 1))
+    }
+
+    fn is_whole_lines(&self, range: &Range<usize>) -> bool {
+        let at_line_start = |pos: usize| self.rope.line_to_char(self.rope.char_to_line(pos)) == pos;
+        range.start < range.end && at_line_start(range.start) && (range.end == self.rope.len_chars() || at_line_start(range.end))
+    }
+
+    // d, c, y or v followed by i or a and the object, for every cursor. Cursors without the object stay where they are
+    pub fn apply_text_object(&mut self, operator: Operator, around: bool, object: char) {
+        if !is_text_object(object) {
+            self.fail(format!("Unknown text object {}", object));
+            return;
+        }
+        let ranges: Vec<Option<Range<usize>>> = self.cursors.iter()
+            .map(|c| self.text_object(self.position_to_char((c.line, c.column)), object, around))
+            .collect();
+        if ranges.iter().all(Option::is_none) {
+            self.fail(format!("No text object {} here", object));
+            return;
+        }
+        if matches!(operator, Operator::Delete | Operator::Change | Operator::Surround) && self.read_only {
+            self.fail("Buffer is read-only");
+            return;
+        }
+
+        let found: Vec<Range<usize>> = ranges.iter().flatten().cloned().collect();
+        let linewise = found.iter().all(|r| self.is_whole_lines(r));
+        let positions: Vec<usize> = self.cursors.iter()
+            .zip(&ranges)
+            .map(|(c, range)| range.as_ref().map_or_else(|| self.position_to_char((c.line, c.column)), |r| r.start))
+            .collect();
+
+        match operator {
+            Operator::Select => {
+                self.cursors = self.cursors.iter()
+                    .zip(ranges)
+                    .map(|(c, range)| range.map_or_else(|| c.clone(), |r| self.select_range(r)))
+                    .collect();
+                self.dedup_cursors();
+            }
+            Operator::Yank => {
+                let fragments = found.iter().map(|r| self.rope.slice(r.clone()).to_string()).collect();
+                registers::set(self.take_register(), Register::new(fragments, linewise));
+                self.move_to_positions(positions);
+            }
+            Operator::Delete => {
+                let fragments = found.iter().map(|r| self.rope.slice(r.clone()).to_string()).collect();
+                registers::set(self.take_register(), Register::new(fragments, linewise));
+                self.delete_ranges(found, positions);
+            }
+            // Changing whole lines keeps a line to type on
+            Operator::Change => {
+                let fragments = found.iter().map(|r| self.rope.slice(r.clone()).to_string()).collect();
+                registers::set(self.take_register(), Register::new(fragments, linewise));
+                let found = found.into_iter()
+                    .map(|r| match linewise {
+                        true => {
+                            let last = self.rope.char_to_line(r.end - 1);
+                            r.start..self.rope.line_to_char(last) + Self::line_visible_len_rope(&self.rope, last)
+                        }
+                        false => r,
+                    })
+                    .collect();
+                self.delete_ranges(found, positions);
+                self.mode = Mode::Insert;
+            }
+            // ys waits for what to surround the object with
+            Operator::Surround => self.text_pending = Some(TextPending::SurroundWith(around, object)),
+        }
+    }
+
+    pub fn move_to_positions(&mut self, positions: Vec<usize>) {
+        self.cursors = positions.into_iter()
+            .map(|pos| {
+                let (line, column) = self.char_to_position(pos);
+                Cursor::new(line, column, column)
+            })
+            .collect();
+        self.dedup_cursors();
+        self.update_goal_columns();
+    }
+
+    // The key after d, c, y or v with i or a and after ys, cs, ds or S
+    pub fn continue_text_pending(&mut self, pending: TextPending, ch: char) {
+        match pending {
+            TextPending::Operator(operator) if ch == 'i' || ch == 'a' => self.text_pending = Some(TextPending::Object(operator, ch == 'a')),
+            TextPending::Operator(_) => {}
+            TextPending::Object(operator, around) => self.apply_text_object(operator, around, ch),
+            TextPending::SurroundWith(around, object) => self.surround_objects(around, object, ch),
+            TextPending::SurroundDelete => self.delete_surround(ch),
+            TextPending::SurroundChange => self.text_pending = Some(TextPending::SurroundChangeTo(ch)),
+            TextPending::SurroundChangeTo(old) => self.change_surround(old, ch),
+            TextPending::SurroundSelections => self.surround_selections(ch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> EditorPanel {
+        let mut editor = EditorPanel::new();
+        editor.set_text(text);
+        editor
+    }
+
+    fn text(editor: &EditorPanel, range: Option<Range<usize>>) -> Option<String> {
+        range.map(|range| editor.rope.slice(range).to_string())
+    }
+
+    fn tag(editor: &EditorPanel, pos: usize, around: bool) -> Option<String> {
+        text(editor, editor.text_object(pos, 't', around))
+    }
+
+    #[test]
+    fn pairs_quotes() {
+        let chars: Vec<char> = r#"a "b" c "d\"e" f"#.chars().collect();
+        assert_eq!(quote_pair(&chars, 3, '"'), Some((2, 4)));
+        assert_eq!(quote_pair(&chars, 2, '"'), Some((2, 4)));
+        assert_eq!(quote_pair(&chars, 0, '"'), Some((2, 4)));
+        assert_eq!(quote_pair(&chars, 6, '"'), Some((8, 13)));
+        assert_eq!(quote_pair(&chars, 15, '"'), None);
+        assert_eq!(quote_pair(&chars, 3, '\''), None);
+
+        let unpaired: Vec<char> = "'a' 'b".chars().collect();
+        assert_eq!(quote_pair(&unpaired, 5, '\''), None);
+    }
+
+    #[test]
+    fn selects_words() {
+        let editor = buffer("foo.bar  baz\n");
+        let word = |pos, around, big| text(&editor, editor.word_object(pos, around, big));
+        assert_eq!(word(1, false, false).as_deref(), Some("foo"));
+        assert_eq!(word(1, false, true).as_deref(), Some("foo.bar"));
+        assert_eq!(word(3, false, false).as_deref(), Some("."));
+        assert_eq!(word(5, true, false).as_deref(), Some("bar  "));
+        assert_eq!(word(7, false, false).as_deref(), Some("  "));
+        assert_eq!(word(7, true, false).as_deref(), Some("  baz"));
+        assert_eq!(word(10, true, false).as_deref(), Some("  baz"));
+        assert_eq!(editor.word_object(13, false, false), None);
+    }
+
+    #[test]
+    fn parses_tags() {
+        let tag = parse_tag(r#"<a href="x">"#).unwrap();
+        assert_eq!((tag.name.as_str(), tag.closing, tag.self_closing), ("a", false, false));
+        let tag = parse_tag("</svg:g>").unwrap();
+        assert_eq!((tag.name.as_str(), tag.closing, tag.self_closing), ("svg:g", true, false));
+        let tag = parse_tag("<br/>").unwrap();
+        assert_eq!((tag.name.as_str(), tag.closing, tag.self_closing), ("br", false, true));
+        assert!(parse_tag("< a>").is_none());
+        assert!(parse_tag("<a> b").is_none());
+    }
+
+    #[test]
+    fn finds_the_enclosing_tag() {
+        let editor = buffer("<div><p>one <br/> two</p> three</div>");
+        assert_eq!(tag(&editor, 9, false).as_deref(), Some("one <br/> two"));
+        assert_eq!(tag(&editor, 13, false).as_deref(), Some("one <br/> two"));
+        assert_eq!(tag(&editor, 6, true).as_deref(), Some("<p>one <br/> two</p>"));
+        assert_eq!(tag(&editor, 23, true).as_deref(), Some("<p>one <br/> two</p>"));
+        assert_eq!(tag(&editor, 27, false).as_deref(), Some("<p>one <br/> two</p> three"));
+        assert_eq!(tag(&editor, 0, false).as_deref(), Some("<p>one <br/> two</p> three"));
+
+        let nested = buffer("<a><a>x</a> y</a>");
+        assert_eq!(tag(&nested, 12, false).as_deref(), Some("<a>x</a> y"));
+        assert_eq!(tag(&nested, 6, false).as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn skips_unclosed_tags() {
+        let editor = buffer("<ul><li>one<li>two</ul> <b>");
+        assert_eq!(tag(&editor, 16, false).as_deref(), Some("<li>one<li>two"));
+        assert_eq!(tag(&editor, 25, false), None);
+        assert_eq!(tag(&editor, 1, false).as_deref(), Some("<li>one<li>two"));
+    }
+}
\ No newline at end of file
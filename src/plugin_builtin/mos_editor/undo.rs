@@ -0,0 +1,232 @@
+use crate::event::event::InputEvent;
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel};
+use crate::plugin_builtin::mos_editor::mos_editor::PLUGIN_ID;
+use crate::system::notifications;
+
+const MAX_UNDO: usize = 1000; // transactions kept, the oldest are dropped first
+
+// One edit as it was applied, with the text it removed so it can be taken back
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub at: usize, // char index in the buffer as it was right before
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Change {
+    // Takes this change back when applied to the buffer as this change left it
+    pub fn inverse(&self) -> Change {
+        Change { at: self.at, removed: self.inserted.clone(), inserted: self.removed.clone() }
+    }
+}
+
+// The edits made between two rests of the editor, undone and redone as one step
+#[derive(Clone, Default)]
+pub struct Transaction {
+    pub id: u64, // given when committed, tells the states of the buffer apart
+    pub changes: Vec<Change>, // in the order they were applied
+    pub cursors: Vec<Cursor>, // before the first change, undo puts them back
+    pub input: Vec<InputEvent>, // what was typed for the last of its changes, . replays it
+}
+
+#[derive(Default)]
+pub struct History {
+    pub undo: Vec<Transaction>,
+    pub redo: Vec<Transaction>,
+    pub pending: Option<Transaction>, // grows until the editor is at rest again
+    next_id: u64,
+    base: u64, // the state before the oldest transaction kept, 0 until one is dropped
+    saved: u64, // the state last written to disk
+}
+
+impl History {
+    pub fn record(&mut self, change: Change, cursors: &[Cursor]) {
+        self.pending
+            .get_or_insert_with(|| Transaction { cursors: cursors.to_vec(), ..Default::default() })
+            .changes
+            .push(change);
+    }
+
+    // How many changes the pending transaction has, to tell whether some input edited
+    pub fn pending_len(&self) -> usize {
+        self.pending.as_ref().map(|t| t.changes.len()).unwrap_or(0)
+    }
+
+    pub fn set_input(&mut self, input: Vec<InputEvent>) {
+        if let Some(transaction) = self.pending.as_mut() {
+            transaction.input = input;
+        }
+    }
+
+    // Closes the pending transaction, a new one makes what was undone unreachable
+    pub fn commit(&mut self) -> Option<&Transaction> {
+        let mut transaction = self.pending.take().filter(|t| !t.changes.is_empty())?;
+        self.next_id += 1;
+        transaction.id = self.next_id;
+        self.undo.push(transaction);
+        if self.undo.len() > MAX_UNDO {
+            self.base = self.undo.remove(0).id;
+        }
+        self.redo.clear();
+        self.undo.last()
+    }
+
+    fn revision(&self) -> u64 {
+        self.undo.last().map(|t| t.id).unwrap_or(self.base)
+    }
+
+    // The buffer as it is now is on disk, pending changes are committed first
+    pub fn mark_saved(&mut self) {
+        self.commit();
+        self.saved = self.revision();
+    }
+
+    // Whether undo or redo led back to what was saved
+    pub fn at_saved(&self) -> bool {
+        self.pending_len() == 0 && self.revision() == self.saved
+    }
+
+    pub fn clear(&mut self) {
+        *self = History::default();
+    }
+}
+
+impl EditorPanel {
+    // Edits are grouped until the editor is at rest, a whole macro or . replay is one step.
+    // The input that made it is what . repeats from then on
+    pub fn commit_transaction(&mut self) {
+        if self.macro_depth > 0 || !self.at_rest() {
+            return;
+        }
+        if let Some(transaction) = self.history.commit()
+            && !transaction.input.is_empty() {
+            self.last_change = transaction.input.clone();
+        }
+    }
+
+    // u, takes back the last transactions and puts the cursors where they were before them
+    pub fn undo(&mut self, count: usize) {
+        self.history.commit();
+        for _ in 0..count {
+            let Some(transaction) = self.history.undo.pop() else {
+                notifications::info(PLUGIN_ID, "Already at the oldest change");
+                break;
+            };
+            self.apply_changes(transaction.changes.iter().rev().map(Change::inverse).collect());
+            self.history.pending = None;
+            self.cursors = transaction.cursors.iter()
+                .map(|c| Self::clamp_cursor(&self.rope, Cursor::new(c.line, c.column, c.goal_column)))
+                .collect();
+            self.history.redo.push(transaction);
+        }
+        self.modified = !self.history.at_saved();
+        self.dedup_cursors();
+        self.update_goal_columns();
+    }
+
+    // ctrl-r, applies what undo took back again, the cursor goes to where it starts
+    pub fn redo(&mut self, count: usize) {
+        self.history.commit();
+        for _ in 0..count {
+            let Some(transaction) = self.history.redo.pop() else {
+                notifications::info(PLUGIN_ID, "Already at the newest change");
+                break;
+            };
+            self.apply_changes(transaction.changes.clone());
+            self.history.pending = None;
+            let start = transaction.changes.iter().map(|c| c.at).min().unwrap_or(0).min(self.rope.len_chars());
+            let (line, column) = self.char_to_position(start);
+            self.cursors = vec![Self::clamp_cursor(&self.rope, Cursor::new(line, column, column))];
+            self.history.undo.push(transaction);
+        }
+        self.modified = !self.history.at_saved();
+        self.update_goal_columns();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::event::{EditorEvent, Event};
+    use crate::system::event_bus;
+    use ropey::Rope;
+
+    fn buffer(text: &str) -> EditorPanel {
+        let mut editor = EditorPanel::new();
+        editor.set_text(text);
+        editor
+    }
+
+    fn edit(editor: &mut EditorPanel, replacements: Vec<(std::ops::Range<usize>, &str)>) {
+        editor.replace_ranges(replacements.into_iter().map(|(range, text)| (range, text.to_string())).collect());
+        editor.commit_transaction();
+    }
+
+    #[test]
+    fn undo_back_to_the_saved_state_is_unmodified() {
+        let mut editor = buffer("abc");
+        edit(&mut editor, vec![(0..1, "x")]);
+        assert!(editor.modified);
+        editor.undo(1);
+        assert_eq!(editor.rope.to_string(), "abc");
+        assert!(!editor.modified);
+        editor.redo(1);
+        assert!(editor.modified);
+
+        // Saved after the edit, as save_file does
+        editor.history.mark_saved();
+        editor.modified = false;
+        edit(&mut editor, vec![(1..2, "y")]);
+        editor.undo(1);
+        assert!(!editor.modified);
+        editor.undo(1);
+        assert!(editor.modified);
+        editor.redo(2);
+        assert!(editor.modified);
+    }
+
+    #[test]
+    fn a_new_edit_after_undo_stays_modified() {
+        let mut editor = buffer("abc");
+        edit(&mut editor, vec![(0..1, "x")]);
+        editor.history.mark_saved();
+        editor.undo(1);
+        edit(&mut editor, vec![(0..1, "x")]);
+        assert_eq!(editor.rope.to_string(), "xbc");
+        assert!(editor.modified);
+    }
+
+    #[test]
+    fn dropped_transactions_never_match_the_saved_state() {
+        let mut history = History::default();
+        for at in 0..MAX_UNDO + 1 {
+            history.record(Change { at, removed: String::new(), inserted: "a".to_string() }, &[]);
+            history.commit();
+        }
+        history.undo.clear();
+        assert!(!history.at_saved());
+    }
+
+    #[test]
+    fn undo_events_apply_one_after_the_other() {
+        let mut editor = buffer("abc def ghi");
+        edit(&mut editor, vec![(0..3, "x"), (4..7, "yyyy"), (8..11, "")]);
+        assert_eq!(editor.rope.to_string(), "x yyyy ");
+
+        event_bus::drain();
+        editor.undo(1);
+        // The ranges of an event refer to the buffer before it, so they are applied from the end
+        let mut text = Rope::from_str("x yyyy ");
+        for event in event_bus::drain() {
+            if let Event::Editor(EditorEvent::BufferChanged { panel, mut edits, .. }) = event
+                && panel == editor.id {
+                edits.sort_by_key(|range| std::cmp::Reverse(range.start));
+                for range in edits {
+                    text.remove(range.start..range.end);
+                    text.insert(range.start, &range.text);
+                }
+            }
+        }
+        assert_eq!(text.to_string(), "abc def ghi");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,253 @@
+use crate::plugin_builtin::mos_editor::editor_panel::{Cursor, EditorPanel};
+use crate::plugin_builtin::mos_editor::graphemes::LineLayout;
+
+const FALLBACK_HEIGHT: usize = 24; // before the first render, when the terminal size is unknown too
+const FALLBACK_WIDTH: usize = 80;
+const SIDE_SCROLLOFF: usize = 5; // columns kept visible left and right of the cursor without soft wrap
+pub const WHEEL_LINES: usize = 3;
+
+// A row on screen, the buffer line and which of its wrapped rows, always 0 without soft wrap
+pub type VisualPos = (usize, usize);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scroll {
+    LineDown,
+    LineUp,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+    Center, // zz, the primary cursor line in the middle
+    Top, // zt
+    Bottom, // zb
+    Left, // zh, only without soft wrap
+    Right, // zl
+}
+
+impl Scroll {
+    pub fn from_name(name: &str) -> Option<Scroll> {
+        match name {
+            "line_down" => Some(Scroll::LineDown),
+            "line_up" => Some(Scroll::LineUp),
+            "half_page_down" => Some(Scroll::HalfPageDown),
+            "half_page_up" => Some(Scroll::HalfPageUp),
+            "page_down" => Some(Scroll::PageDown),
+            "page_up" => Some(Scroll::PageUp),
+            "center" => Some(Scroll::Center),
+            "top" => Some(Scroll::Top),
+            "bottom" => Some(Scroll::Bottom),
+            "left" => Some(Scroll::Left),
+            "right" => Some(Scroll::Right),
+            _ => None,
+        }
+    }
+}
+
+impl EditorPanel {
+    // Lines of text that fit in the panel at the last render
+    pub fn view_height(&self) -> usize {
+        match self.view_height.get() {
+            0 => crossterm::terminal::size()
+                .map(|(_, rows)| (rows as usize).saturating_sub(1)) // minus the status line
+                .ok()
+                .filter(|rows| *rows > 0)
+                .unwrap_or(FALLBACK_HEIGHT),
+            height => height,
+        }
+    }
+
+    // Columns of text next to the gutter at the last render
+    pub fn view_width(&self) -> usize {
+        match self.view_width.get() {
+            0 => crossterm::terminal::size()
+                .map(|(columns, _)| (columns as usize).saturating_sub(self.gutter_width()))
+                .ok()
+                .filter(|columns| *columns > 0)
+                .unwrap_or(FALLBACK_WIDTH),
+            width => width,
+        }
+    }
+
+    // Never more than half the view, otherwise the cursor couldn't move without scrolling
+    fn effective_scrolloff(&self) -> usize {
+        self.scrolloff.min(self.view_height().saturating_sub(1) / 2)
+    }
+
+    fn last_line(&self) -> usize {
+        self.rope.len_lines().saturating_sub(1)
+    }
+
+    // A line laid out the way it is drawn, wrapped at the view width with soft wrap
+    pub fn line_layout(&self, line: usize) -> LineLayout {
+        let wrap_width = self.soft_wrap.then(|| self.view_width());
+        LineLayout::new(&Self::line_text_rope(&self.rope, line), self.tab_width, wrap_width)
+    }
+
+    // How many rows a line takes, a cursor at the end of a full row gets a row of its own
+    pub fn line_rows(&self, line: usize) -> usize {
+        if self.soft_wrap {
+            self.line_layout(line).rows()
+        } else {
+            1
+        }
+    }
+
+    pub fn cursor_visual_pos(&self, cursor: &Cursor) -> VisualPos {
+        if self.soft_wrap {
+            (cursor.line, self.line_layout(cursor.line).position(cursor.column).0)
+        } else {
+            (cursor.line, 0)
+        }
+    }
+
+    fn top(&self) -> VisualPos {
+        (self.scroll_offset, self.scroll_row)
+    }
+
+    fn set_top(&mut self, (line, row): VisualPos) {
+        self.scroll_offset = line;
+        self.scroll_row = row;
+    }
+
+    // Moves a position by delta rows, stopping at the start and end of the buffer
+    pub fn step_rows(&self, (mut line, mut row): VisualPos, delta: isize) -> VisualPos {
+        for _ in 0..delta.unsigned_abs() {
+            if delta > 0 {
+                if row + 1 < self.line_rows(line) {
+                    row += 1;
+                } else if let Some(next) = self.next_visible_line(line) {
+                    line = next;
+                    row = 0;
+                } else {
+                    break;
+                }
+            } else if row > 0 {
+                row -= 1;
+            } else if let Some(prev) = self.prev_visible_line(line) {
+                line = prev;
+                row = self.line_rows(line) - 1;
+            } else {
+                break;
+            }
+        }
+        (line, row)
+    }
+
+    // Rows from one position down to a later one, counting at most limit rows
+    pub fn rows_between(&self, from: VisualPos, to: VisualPos, limit: usize) -> usize {
+        let mut pos = from;
+        let mut rows = 0;
+        while pos < to && rows < limit {
+            pos = self.step_rows(pos, 1);
+            rows += 1;
+        }
+        rows
+    }
+
+    // Keeps the primary cursor in view with scrolloff rows around it, where the buffer has them
+    pub fn scroll_to_cursor(&mut self) {
+        let Some(cursor) = self.cursors.first().cloned() else {
+            return;
+        };
+
+        let height = self.view_height();
+        let scrolloff = self.effective_scrolloff() as isize;
+        let pos = self.cursor_visual_pos(&cursor);
+        let top_margin = self.step_rows(pos, -scrolloff);
+        let bottom_margin = self.rows_between(pos, self.step_rows(pos, scrolloff), height);
+
+        if top_margin < self.top() {
+            self.set_top(top_margin);
+        } else if self.rows_between(self.top(), pos, height) + bottom_margin >= height {
+            self.set_top(self.step_rows(pos, -((height - 1 - bottom_margin) as isize)));
+        }
+
+        if self.soft_wrap {
+            self.h_scroll = 0;
+            return;
+        }
+
+        let width = self.view_width();
+        let side_scrolloff = SIDE_SCROLLOFF.min(width.saturating_sub(1) / 2);
+        let x = self.unwrapped_layout(cursor.line).position(cursor.column).1;
+        if x < self.h_scroll + side_scrolloff {
+            self.h_scroll = x.saturating_sub(side_scrolloff);
+        } else if x + side_scrolloff >= self.h_scroll + width {
+            self.h_scroll = x + side_scrolloff + 1 - width;
+        }
+    }
+
+    // Keeps the top of the view on the buffer after its text changed underneath, e.g. on reload
+    pub fn clamp_scroll(&mut self) {
+        let line = self.scroll_offset.min(self.last_line());
+        let row = self.scroll_row.min(self.line_rows(line) - 1);
+        self.set_top((line, row));
+    }
+
+    // Moves the view without touching the cursors, e.g. for the mouse wheel
+    pub fn scroll_view(&mut self, delta: isize) {
+        self.set_top(self.step_rows(self.top(), delta));
+    }
+
+    pub fn scroll_view_horizontal(&mut self, delta: isize) {
+        if !self.soft_wrap {
+            self.h_scroll = self.h_scroll.saturating_add_signed(delta);
+        }
+    }
+
+    // Moves the view and the cursors by the same number of lines, like vim's ctrl-d and ctrl-f
+    fn scroll_with_cursors(&mut self, delta: isize) {
+        self.scroll_view(delta);
+
+        let last_line = self.last_line();
+        let mut cursors = std::mem::take(&mut self.cursors);
+        for c in cursors.iter_mut() {
+            c.line = self.visible_line(c.line.saturating_add_signed(delta).min(last_line));
+            c.column = self.unwrapped_layout(c.line).column_at(0, c.goal_column);
+        }
+        cursors.dedup();
+        self.cursors = cursors;
+        self.scroll_to_cursor();
+    }
+
+    // Up and down by wrapped rows, keeping the display column within the row where possible
+    pub fn move_visual(&mut self, delta: isize) {
+        let width = self.view_width();
+
+        let mut cursors = std::mem::take(&mut self.cursors);
+        for c in cursors.iter_mut() {
+            let (line, row) = self.step_rows(self.cursor_visual_pos(c), delta);
+            c.line = line;
+            c.column = self.line_layout(line).column_at(row, c.goal_column % width);
+        }
+        cursors.dedup();
+        self.cursors = cursors;
+    }
+
+    pub fn scroll(&mut self, scroll: Scroll) {
+        let height = self.view_height() as isize;
+        let pos = self.cursors.first().map(|c| self.cursor_visual_pos(c)).unwrap_or((0, 0));
+        let scrolloff = self.effective_scrolloff() as isize;
+
+        match scroll {
+            Scroll::LineDown => self.scroll_view(1),
+            Scroll::LineUp => self.scroll_view(-1),
+            Scroll::HalfPageDown => self.scroll_with_cursors((height / 2).max(1)),
+            Scroll::HalfPageUp => self.scroll_with_cursors(-(height / 2).max(1)),
+            Scroll::PageDown => self.scroll_with_cursors((height - 2).max(1)),
+            Scroll::PageUp => self.scroll_with_cursors(-(height - 2).max(1)),
+            Scroll::Center => self.set_top(self.step_rows(pos, -(height / 2))),
+            Scroll::Top => self.set_top(self.step_rows(pos, -scrolloff)),
+            Scroll::Bottom => self.set_top(self.step_rows(pos, -(height - 1 - scrolloff))),
+            Scroll::Left => self.scroll_view_horizontal(-(self.view_width() as isize / 2)),
+            Scroll::Right => self.scroll_view_horizontal(self.view_width() as isize / 2),
+        }
+    }
+
+    pub fn set_soft_wrap(&mut self, soft_wrap: bool) {
+        self.soft_wrap = soft_wrap;
+        self.scroll_row = 0;
+        self.h_scroll = 0;
+        self.scroll_to_cursor();
+    }
+}
\ No newline at end of file
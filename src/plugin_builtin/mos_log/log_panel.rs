@@ -0,0 +1,180 @@
+use crate::app::MosId;
+use crate::event::event::{Event, InputEvent};
+use crate::panel::panel::{Panel, PanelStatus};
+use crate::system::logger::{self, LogLevel, LogRecord};
+use crate::system::theme;
+use ratatui::layout::Rect;
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::cell::Cell;
+
+pub struct LogPanel {
+    pub id: MosId,
+    pub level: LogLevel, // show records at this level and above
+    pub follow: bool, // keep the newest record in view
+    pub scroll_offset: usize,
+    height: Cell<usize>, // lines available for records at the last render
+    seen_generation: u64,
+    dirty: bool,
+}
+
+impl LogPanel {
+    pub fn new() -> Self {
+        Self {
+            id: MosId::new(),
+            level: LogLevel::Debug,
+            follow: true,
+            scroll_offset: 0,
+            height: Cell::new(1),
+            seen_generation: 0,
+            dirty: true,
+        }
+    }
+
+    fn level_style(level: LogLevel) -> Style {
+        theme::style(&format!("log.{}", level.name()))
+    }
+
+    // The other lines of a multi-line message, like plugin.stats or a backtrace, go indented under the first
+    fn record_lines(record: &LogRecord) -> Vec<Line<'static>> {
+        let mut spans = vec![
+            Span::styled(format!("{} ", record.time_of_day()), theme::style("ui.text.dim")),
+            Span::styled(format!("{:5} ", record.level.name().to_uppercase()), Self::level_style(record.level)),
+            Span::styled(format!("[{}] ", record.target), theme::style("ui.text.source")),
+        ];
+        let indent = " ".repeat(spans.iter().map(|s| s.width()).sum());
+        let mut message = record.message.lines();
+        spans.push(Span::raw(message.next().unwrap_or("").to_string()));
+
+        let mut lines = vec![Line::from(spans)];
+        lines.extend(message.map(|line| Line::from(format!("{}{}", indent, line))));
+        lines
+    }
+
+    fn bottom(&self) -> usize {
+        logger::record_count(self.level).saturating_sub(self.height.get())
+    }
+
+    fn scroll(&mut self, delta: isize) {
+        let max = self.bottom();
+        if self.follow {
+            self.scroll_offset = max;
+        }
+
+        self.follow = false;
+        self.scroll_offset = self.scroll_offset.saturating_add_signed(delta).min(max);
+        if self.scroll_offset == max && delta > 0 {
+            self.follow = true;
+        }
+    }
+
+    fn handle_char(&mut self, ch: char) {
+        match ch {
+            '1'..='5' => {
+                self.level = LogLevel::ALL[ch as usize - '1' as usize];
+                self.follow = true;
+            }
+            'f' => {
+                self.scroll_offset = self.bottom();
+                self.follow = !self.follow;
+            }
+            'j' => self.scroll(1),
+            'k' => self.scroll(-1),
+            'g' => {
+                self.follow = false;
+                self.scroll_offset = 0;
+            }
+            'G' => self.follow = true,
+            _ => {}
+        }
+    }
+}
+
+impl Panel for LogPanel {
+    fn id(&self) -> MosId {
+        self.id
+    }
+
+    fn title(&self) -> &str {
+        "Log"
+    }
+
+    fn kind(&self) -> &str {
+        "log_viewer"
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        let Event::Input(input) = event else {
+            return;
+        };
+
+        match input {
+            InputEvent::Char(ch) => self.handle_char(ch),
+            InputEvent::Keyboard(keys) => match keys.iter().map(|k| k.as_str()).collect::<Vec<_>>().as_slice() {
+                ["down"] => self.scroll(1),
+                ["up"] => self.scroll(-1),
+                ["pagedown"] => self.scroll(self.height.get() as isize),
+                ["pageup"] => self.scroll(-(self.height.get() as isize)),
+                _ => return,
+            },
+            _ => return,
+        }
+
+        self.dirty = true;
+    }
+
+    fn render(&self, frame: &mut Frame, area: Rect) {
+        if area.height == 0 {
+            return;
+        }
+
+        let total = logger::record_count(self.level);
+        let height = area.height as usize - 1;
+        self.height.set(height.max(1));
+
+        // Following, as many of the newest records as fit with all their lines
+        let records = if self.follow {
+            let mut newest = logger::records(self.level, total.saturating_sub(height), height);
+            let mut rows = 0;
+            let fitting = newest.iter().rev()
+                .take_while(|r| {
+                    rows += r.message.lines().count().max(1);
+                    rows <= height
+                })
+                .count()
+                .max(1);
+            newest.drain(..newest.len().saturating_sub(fitting));
+            newest
+        } else {
+            logger::records(self.level, self.scroll_offset.min(total), height)
+        };
+
+        let header = Line::from(vec![
+            Span::styled(format!(" Log: {}+ ", self.level), theme::style("ui.header")),
+            Span::styled(
+                format!(" {} records{}  1-5 level  f follow  j/k scroll", total, if self.follow { ", following" } else { "" }),
+                theme::style("ui.text.dim"),
+            ),
+        ]);
+
+        let mut lines = vec![header];
+        lines.extend(records.iter().flat_map(Self::record_lines).take(height));
+
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+
+    fn status(&self) -> PanelStatus {
+        PanelStatus::default()
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty || (self.follow && logger::generation() != self.seen_generation)
+    }
+
+    fn clear_dirty(&mut self) {
+        self.dirty = false;
+        self.seen_generation = logger::generation();
+    }
+}
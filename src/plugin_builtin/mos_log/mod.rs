@@ -0,0 +1,2 @@
+pub mod mos_log;
+pub mod log_panel;
\ No newline at end of file
@@ -0,0 +1,137 @@
+use crate::app::MosId;
+use crate::event::event::{Event, InputEvent};
+use crate::floating_panel::toast_panel::ToastPanel;
+use crate::panel::panel::{Panel, PanelStatus};
+use crate::system::logger;
+use crate::system::notifications::{self, Notification};
+use crate::system::theme;
+use ratatui::layout::Rect;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::cell::Cell;
+
+// All the notifications shown so far, newest at the bottom
+pub struct MessagesPanel {
+    pub id: MosId,
+    pub scroll_offset: Option<usize>, // None keeps the newest message in view
+    height: Cell<usize>,
+    seen_generation: u64,
+    dirty: bool,
+}
+
+impl MessagesPanel {
+    pub fn new() -> Self {
+        Self {
+            id: MosId::new(),
+            scroll_offset: None,
+            height: Cell::new(1),
+            seen_generation: 0,
+            dirty: true,
+        }
+    }
+
+    // A multi-line message, e.g. plugin.stats, goes on with its other lines indented under the first
+    fn message_lines(notification: &Notification) -> Vec<Line<'static>> {
+        let mut spans = vec![
+            Span::styled(format!("#{:<4} {} ", notification.id, logger::time_of_day(notification.time)), theme::style("ui.text.dim")),
+            Span::styled(
+                format!("{:7} ", notification.severity.name()),
+                ToastPanel::severity_style(notification.severity),
+            ),
+            Span::styled(format!("[{}] ", notification.source), theme::style("ui.text.source")),
+        ];
+        let indent = " ".repeat(spans.iter().map(|s| s.width()).sum());
+        let mut message = notification.message.lines();
+        spans.push(Span::raw(message.next().unwrap_or("").to_string()));
+
+        let mut lines = vec![Line::from(spans)];
+        lines.extend(message.map(|line| Line::from(format!("{}{}", indent, line))));
+        lines
+    }
+
+    fn history_lines(history: &[Notification]) -> Vec<Line<'static>> {
+        history.iter().flat_map(Self::message_lines).collect()
+    }
+
+    fn scroll(&mut self, delta: isize) {
+        let bottom = Self::history_lines(&notifications::history()).len().saturating_sub(self.height.get());
+        let offset = self.scroll_offset.unwrap_or(bottom).saturating_add_signed(delta).min(bottom);
+
+        self.scroll_offset = if offset == bottom { None } else { Some(offset) };
+    }
+}
+
+impl Panel for MessagesPanel {
+    fn id(&self) -> MosId {
+        self.id
+    }
+
+    fn title(&self) -> &str {
+        "Messages"
+    }
+
+    fn kind(&self) -> &str {
+        "message_history"
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        let Event::Input(input) = event else {
+            return;
+        };
+
+        match input {
+            InputEvent::Char('j') => self.scroll(1),
+            InputEvent::Char('k') => self.scroll(-1),
+            InputEvent::Char('g') => self.scroll_offset = Some(0),
+            InputEvent::Char('G') => self.scroll_offset = None,
+            InputEvent::Char('c') => notifications::clear_history(),
+            InputEvent::Keyboard(keys) => match keys.iter().map(|k| k.as_str()).collect::<Vec<_>>().as_slice() {
+                ["down"] => self.scroll(1),
+                ["up"] => self.scroll(-1),
+                _ => return,
+            },
+            _ => return,
+        }
+
+        self.dirty = true;
+    }
+
+    fn render(&self, frame: &mut Frame, area: Rect) {
+        if area.height == 0 {
+            return;
+        }
+
+        let history = notifications::history();
+        let rows = Self::history_lines(&history);
+        let height = area.height as usize - 1;
+        self.height.set(height.max(1));
+
+        let start = self.scroll_offset
+            .unwrap_or(rows.len().saturating_sub(height))
+            .min(rows.len());
+
+        let header = Line::from(vec![
+            Span::styled(" Messages ", theme::style("ui.header")),
+            Span::styled(format!(" {} messages  j/k scroll  c clear", history.len()), theme::style("ui.text.dim")),
+        ]);
+
+        let mut lines = vec![header];
+        lines.extend(rows.into_iter().skip(start).take(height));
+
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+
+    fn status(&self) -> PanelStatus {
+        PanelStatus::default()
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.dirty || notifications::generation() != self.seen_generation
+    }
+
+    fn clear_dirty(&mut self) {
+        self.dirty = false;
+        self.seen_generation = notifications::generation();
+    }
+}
@@ -0,0 +1,2 @@
+pub mod mos_messages;
+pub mod messages_panel;
\ No newline at end of file
@@ -0,0 +1,54 @@
+use crate::event::event::Event;
+use crate::plugin::dependency::Dependency;
+use crate::plugin::plugin::{Plugin, PluginContext};
+use crate::plugin::subscription::Subscription;
+use crate::plugin_builtin::mos_messages::messages_panel::MessagesPanel;
+
+pub struct MosMessagesPlugin {}
+
+impl MosMessagesPlugin {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Plugin for MosMessagesPlugin {
+    fn id(&self) -> String {
+        String::from("mos_builtin_messages")
+    }
+
+    fn name(&self) -> String {
+        String::from("MosMessages")
+    }
+
+    fn version(&self) -> String {
+        String::from("0.1.0")
+    }
+
+    fn description(&self) -> String {
+        String::from("The built-in message history for Mos")
+    }
+
+    fn dependencies(&self) -> Vec<Dependency> {
+        Vec::new()
+    }
+
+    fn subscriptions(&self) -> Vec<Subscription> {
+        Vec::new()
+    }
+
+    fn enable(&mut self, context: &mut PluginContext) -> Result<(), String> {
+        context.panel_registry.register_panel_kind(self.id(), String::from("message_history"), || Box::new(MessagesPanel::new()));
+        context.keymap_registry.register_keybinding(self.id(), vec![String::from("alt"), String::from("m")], String::from("panel.open"), vec![String::from("message_history")]);
+
+        Ok(())
+    }
+
+    fn disable(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn handle_event(&mut self, _event: Event) -> Result<(), String> {
+        Ok(())
+    }
+}
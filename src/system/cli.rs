@@ -0,0 +1,277 @@
+use crate::system::logger::LogLevel;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage: mos [options] [file[:line[:column]] | dir | -]...
+
+Opens each file in its own tab, at the given line and column if any.
+A directory is opened as the workspace, - reads a buffer from stdin.
+
+Options:
+  -c, --config <file>      Load this lua config instead of ./test.lua
+  -w, --workspace <dir>    Open this directory as the workspace
+  -R, --read-only          Open the files read-only
+  -l, --log-level <level>  What goes to mos.log: error, warn, info, debug or trace (default info)
+  -t, --tick-rate <ms>     How often plugins subscribed to ticks get one (default 250)
+  -h, --help               Print this help and exit
+  -V, --version            Print the version and exit
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileSource {
+    Path(PathBuf),
+    Stdin,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileArg {
+    pub source: FileSource,
+    pub line: Option<usize>, // 1-based like the editor shows them
+    pub column: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub files: Vec<FileArg>,
+    pub config: Option<PathBuf>,
+    pub workspace: Option<PathBuf>,
+    pub read_only: bool,
+    pub log_level: LogLevel,
+    pub tick_rate: Duration,
+    pub stdin: Option<String>, // filled by read_stdin, before the terminal is taken over
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliAction {
+    Run(Options),
+    Help,
+    Version,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            config: None,
+            workspace: None,
+            read_only: false,
+            log_level: LogLevel::Info,
+            tick_rate: Duration::from_millis(250),
+            stdin: None,
+        }
+    }
+
+    pub fn reads_stdin(&self) -> bool {
+        self.files.iter().any(|f| f.source == FileSource::Stdin)
+    }
+
+    pub fn read_stdin(&mut self) -> Result<(), String> {
+        if !self.reads_stdin() {
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text).map_err(|e| format!("Failed to read stdin: {}", e))?;
+        self.stdin = Some(text);
+        Ok(())
+    }
+
+    fn set_workspace(&mut self, dir: PathBuf) -> Result<(), String> {
+        if let Some(existing) = &self.workspace
+            && *existing != dir {
+            return Err(format!("Only one workspace can be opened, got {} and {}", existing.display(), dir.display()));
+        }
+
+        self.workspace = Some(dir);
+        Ok(())
+    }
+}
+
+// "file:42:7" -> (file, 42, 7), a path that exists as written is never split so "a:1" can still be opened
+pub fn split_position(arg: &str) -> (PathBuf, Option<usize>, Option<usize>) {
+    if Path::new(arg).exists() {
+        return (PathBuf::from(arg), None, None);
+    }
+
+    let mut numbers = Vec::new();
+    let mut rest = arg;
+    while numbers.len() < 2
+        && let Some((head, tail)) = rest.rsplit_once(':')
+        && !head.is_empty()
+        && let Ok(n) = tail.parse::<usize>() {
+        numbers.insert(0, n);
+        rest = head;
+    }
+
+    (PathBuf::from(rest), numbers.first().copied(), numbers.get(1).copied())
+}
+
+// Milliseconds between ticks, at least 1 so a tick subscriber can't spin the loop
+pub fn parse_tick_rate(ms: &str) -> Result<Duration, String> {
+    match ms.parse::<u64>() {
+        Ok(ms) if ms > 0 => Ok(Duration::from_millis(ms)),
+        _ => Err(format!("Invalid tick rate '{}', expected milliseconds above 0", ms)),
+    }
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<CliAction, String> {
+    let mut options = Options::new();
+    let mut args = args.into_iter();
+    let mut flags_done = false;
+
+    while let Some(arg) = args.next() {
+        if flags_done || arg == "-" || !arg.starts_with('-') {
+            if arg == "-" {
+                if options.reads_stdin() {
+                    return Err("stdin can only be read once".to_string());
+                }
+                options.files.push(FileArg { source: FileSource::Stdin, line: None, column: None });
+                continue;
+            }
+
+            let (path, line, column) = split_position(&arg);
+            if path.is_dir() {
+                options.set_workspace(path)?;
+            } else {
+                options.files.push(FileArg { source: FileSource::Path(path), line, column });
+            }
+            continue;
+        }
+
+        if arg == "--" {
+            flags_done = true;
+            continue;
+        }
+
+        // --flag=value and --flag value are both accepted
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value.clone()
+                .or_else(|| args.next())
+                .ok_or(format!("{} requires a value", name))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(CliAction::Help),
+            "-V" | "--version" => return Ok(CliAction::Version),
+            "-R" | "--read-only" => options.read_only = true,
+            "-c" | "--config" => options.config = Some(PathBuf::from(value(&flag)?)),
+            "-w" | "--workspace" => {
+                let dir = PathBuf::from(value(&flag)?);
+                if !dir.is_dir() {
+                    return Err(format!("{} is not a directory", dir.display()));
+                }
+                options.set_workspace(dir)?;
+            }
+            "-l" | "--log-level" => {
+                let level = value(&flag)?;
+                options.log_level = LogLevel::from_name(&level).ok_or(format!("Unknown log level '{}'", level))?;
+            }
+            "-t" | "--tick-rate" => options.tick_rate = parse_tick_rate(&value(&flag)?)?,
+            _ => return Err(format!("Unknown option '{}'", arg)),
+        }
+    }
+
+    Ok(CliAction::Run(options))
+}
+
+// Files and the config are relative to where mos was started, make them absolute before moving into the workspace
+pub fn enter_workspace(options: &mut Options) -> Result<(), String> {
+    let Some(dir) = options.workspace.clone() else {
+        return Ok(());
+    };
+
+    for file in options.files.iter_mut() {
+        if let FileSource::Path(path) = &file.source {
+            file.source = FileSource::Path(std::path::absolute(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+        }
+    }
+    if let Some(config) = &options.config {
+        options.config = Some(std::path::absolute(config).map_err(|e| format!("{}: {}", config.display(), e))?);
+    }
+
+    std::env::set_current_dir(&dir).map_err(|e| format!("Failed to open workspace {}: {}", dir.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<Options, String> {
+        match parse(args.iter().map(|a| a.to_string()))? {
+            CliAction::Run(options) => Ok(options),
+            other => Err(format!("expected options, got {:?}", other)),
+        }
+    }
+
+    fn file(path: &str, line: Option<usize>, column: Option<usize>) -> FileArg {
+        FileArg { source: FileSource::Path(PathBuf::from(path)), line, column }
+    }
+
+    #[test]
+    fn splits_line_and_column() {
+        assert_eq!(split_position("no/such/file.rs:42:7"), (PathBuf::from("no/such/file.rs"), Some(42), Some(7)));
+        assert_eq!(split_position("no/such/file.rs:42"), (PathBuf::from("no/such/file.rs"), Some(42), None));
+        assert_eq!(split_position("no/such/file.rs"), (PathBuf::from("no/such/file.rs"), None, None));
+        assert_eq!(split_position("a:b:3"), (PathBuf::from("a:b"), Some(3), None));
+        assert_eq!(split_position("a:1:2:3"), (PathBuf::from("a:1"), Some(2), Some(3)));
+        assert_eq!(split_position(":5"), (PathBuf::from(":5"), None, None));
+    }
+
+    #[test]
+    fn existing_paths_are_not_split() {
+        let path = std::env::temp_dir().join(format!("mos-cli-test-{}:3", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let arg = path.to_string_lossy().to_string();
+        let split = split_position(&arg);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(split, (path, None, None));
+    }
+
+    #[test]
+    fn parses_files_and_flags() {
+        let options = run(&["-R", "--log-level=debug", "-t", "100", "x.rs:3", "-", "y.rs"]).unwrap();
+        assert!(options.read_only);
+        assert_eq!(options.log_level, LogLevel::Debug);
+        assert_eq!(options.tick_rate, Duration::from_millis(100));
+        assert_eq!(options.files, vec![
+            file("x.rs", Some(3), None),
+            FileArg { source: FileSource::Stdin, line: None, column: None },
+            file("y.rs", None, None),
+        ]);
+
+        let options = run(&["--config", "init.lua", "--", "-R"]).unwrap();
+        assert_eq!(options.config, Some(PathBuf::from("init.lua")));
+        assert!(!options.read_only);
+        assert_eq!(options.files, vec![file("-R", None, None)]);
+    }
+
+    #[test]
+    fn directories_open_as_the_workspace() {
+        let dir = std::env::temp_dir();
+        let options = run(&[dir.to_str().unwrap()]).unwrap();
+        assert_eq!(options.workspace, Some(dir.clone()));
+        assert!(options.files.is_empty());
+
+        let options = run(&["-w", dir.to_str().unwrap(), dir.to_str().unwrap()]).unwrap();
+        assert_eq!(options.workspace, Some(dir));
+    }
+
+    #[test]
+    fn help_version_and_errors() {
+        assert_eq!(parse(["-h".to_string()]), Ok(CliAction::Help));
+        assert_eq!(parse(["--version".to_string(), "--bogus".to_string()]), Ok(CliAction::Version));
+        assert!(run(&["--bogus"]).unwrap_err().contains("Unknown option"));
+        assert!(run(&["--config"]).unwrap_err().contains("requires a value"));
+        assert!(run(&["-l", "loud"]).unwrap_err().contains("Unknown log level"));
+        assert!(run(&["-t", "0"]).is_err());
+        assert!(run(&["-", "-"]).is_err());
+        assert!(run(&["-w", "no/such/dir"]).is_err());
+    }
+}
\ No newline at end of file
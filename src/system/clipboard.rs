@@ -0,0 +1,154 @@
+use std::io::{Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// What was copied last, pasted back when there is no clipboard tool to read from. OSC 52 can only write
+static LAST_COPY: Mutex<Option<String>> = Mutex::new(None);
+
+// A clipboard owner that doesn't answer would otherwise freeze the editor, the tool is killed after this
+const TOOL_TIMEOUT: Duration = Duration::from_millis(500);
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// A local clipboard program, the program and its args to copy from stdin and to paste to stdout
+struct Tool {
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+const PBCOPY: Tool = Tool { copy: &["pbcopy"], paste: &["pbpaste"] };
+const WL_COPY: Tool = Tool { copy: &["wl-copy"], paste: &["wl-paste", "--no-newline"] };
+const XCLIP: Tool = Tool { copy: &["xclip", "-selection", "clipboard"], paste: &["xclip", "-selection", "clipboard", "-o"] };
+const XSEL: Tool = Tool { copy: &["xsel", "--clipboard", "--input"], paste: &["xsel", "--clipboard", "--output"] };
+
+// The tools that can work in this session, in the order they are tried
+fn tools() -> Vec<&'static Tool> {
+    let mut tools = Vec::new();
+    if cfg!(target_os = "macos") {
+        tools.push(&PBCOPY);
+    }
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(&WL_COPY);
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        tools.extend([&XCLIP, &XSEL]);
+    }
+    tools
+}
+
+// Waits for a tool to exit, one that takes too long is killed
+fn wait(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(5)),
+            _ => {
+                child.kill().ok();
+                child.wait().ok();
+                return None;
+            }
+        }
+    }
+}
+
+// Runs a paste tool, its output is read on a thread so a full pipe can't keep it from exiting
+fn read_tool(command: &[&str], timeout: Duration) -> Option<String> {
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        stdout.read_to_end(&mut bytes).ok();
+        bytes
+    });
+
+    let status = wait(&mut child, timeout)?;
+    let bytes = reader.join().ok()?;
+    status.success().then(|| String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn copy_with_tool(text: &str) -> bool {
+    tools().into_iter().any(|tool| {
+        let child = Command::new(tool.copy[0])
+            .args(&tool.copy[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(mut child) = child else {
+            return false;
+        };
+        let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        written && wait(&mut child, TOOL_TIMEOUT).is_some_and(|status| status.success())
+    })
+}
+
+// Copies to the system clipboard, with an OSC 52 escape sequence so it also works over ssh and with a local tool when there is one
+pub fn copy(text: &str) {
+    if let Ok(mut last) = LAST_COPY.lock() {
+        *last = Some(text.to_string());
+    }
+
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes())).ok();
+    stdout.flush().ok();
+
+    copy_with_tool(text);
+}
+
+// The system clipboard from a local tool, or else what mos copied last
+pub fn paste() -> Option<String> {
+    let from_tool = tools().into_iter().find_map(|tool| read_tool(tool.paste, TOOL_TIMEOUT));
+    from_tool.or_else(|| LAST_COPY.lock().ok().and_then(|last| last.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"a"), "YQ==");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"abc"), "YWJj");
+        assert_eq!(base64("é!".as_bytes()), "w6kh");
+    }
+
+    #[test]
+    fn reads_tool_output() {
+        assert_eq!(read_tool(&["printf", "a\\nb"], TOOL_TIMEOUT).as_deref(), Some("a\nb"));
+        assert_eq!(read_tool(&["false"], TOOL_TIMEOUT), None);
+        assert_eq!(read_tool(&["no-such-clipboard-tool"], TOOL_TIMEOUT), None);
+    }
+
+    #[test]
+    fn kills_a_tool_that_hangs() {
+        let started = Instant::now();
+        assert_eq!(read_tool(&["sleep", "5"], Duration::from_millis(50)), None);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
\ No newline at end of file
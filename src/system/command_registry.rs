@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+
+pub struct CommandRegistry {
+    commands: HashMap<String, (String, String)>, // command name -> (plugin_id, description)
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self {
+            commands: HashMap::new(),
+        }
+    }
+
+    pub fn register_command(&mut self, plugin_id: String, name: String, description: String) {
+        self.commands.insert(name, (plugin_id, description));
+    }
+
+    pub fn unregister_commands_by_plugin(&mut self, plugin_id: &str) {
+        self.commands.retain(|_, (p_id, _)| p_id != plugin_id);
+    }
+
+    pub fn get_commands(&self) -> &HashMap<String, (String, String)> {
+        &self.commands
+    }
+
+    pub fn get_command(&self, name: &str) -> Option<&(String, String)> {
+        self.commands.get(name)
+    }
+}
@@ -0,0 +1,45 @@
+use crate::event::event::Event;
+use crate::system::event_loop::LoopMessage;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+// Events emitted by panels, plugins and core code, drained by Mos::update and dispatched to the plugins.
+// Global so that anything can emit without having to thread a context through every call.
+static EVENT_QUEUE: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
+
+// Wakes the main loop when something is emitted, only one wake-up is queued at a time
+static WAKER: Mutex<Option<Sender<LoopMessage>>> = Mutex::new(None);
+static WAKE_PENDING: AtomicBool = AtomicBool::new(false);
+
+pub fn set_waker(sender: Sender<LoopMessage>) {
+    if let Ok(mut waker) = WAKER.lock() {
+        *waker = Some(sender);
+    }
+}
+
+pub fn wake() {
+    if WAKE_PENDING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    if let Ok(waker) = WAKER.lock()
+        && let Some(sender) = waker.as_ref() {
+        sender.send(LoopMessage::Wake).ok();
+    }
+}
+
+pub fn emit(event: Event) {
+    if let Ok(mut queue) = EVENT_QUEUE.lock() {
+        queue.push_back(event);
+    }
+    wake();
+}
+
+pub fn drain() -> Vec<Event> {
+    WAKE_PENDING.store(false, Ordering::SeqCst);
+    EVENT_QUEUE.lock()
+        .map(|mut queue| queue.drain(..).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,24 @@
+use std::sync::mpsc::Sender;
+use std::thread;
+
+// Everything that can wake up the main loop
+pub enum LoopMessage {
+    Terminal(crossterm::event::Event),
+    TerminalError(String),
+    Wake, // an event was emitted on the event bus, possibly from another thread
+}
+
+// Reads terminal input on its own thread so the main loop can block on a single channel
+pub fn spawn_input_thread(sender: Sender<LoopMessage>) {
+    thread::spawn(move || loop {
+        let message = match crossterm::event::read() {
+            Ok(event) => LoopMessage::Terminal(event),
+            Err(e) => LoopMessage::TerminalError(format!("Failed to read event: {}", e)),
+        };
+
+        let failed = matches!(message, LoopMessage::TerminalError(_));
+        if sender.send(message).is_err() || failed {
+            break;
+        }
+    });
+}
@@ -0,0 +1,27 @@
+use std::collections::HashMap;
+
+// Global keybindings, checked before the input reaches the active panel
+pub struct KeymapRegistry {
+    bindings: HashMap<Vec<String>, (String, String, Vec<String>)>, // sorted keys -> (plugin_id, command, args)
+}
+
+impl KeymapRegistry {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    pub fn register_keybinding(&mut self, plugin_id: String, mut keys: Vec<String>, command: String, args: Vec<String>) {
+        keys.sort();
+        self.bindings.insert(keys, (plugin_id, command, args));
+    }
+
+    pub fn unregister_keybindings_by_plugin(&mut self, plugin_id: &str) {
+        self.bindings.retain(|_, (p_id, _, _)| p_id != plugin_id);
+    }
+
+    pub fn get_binding(&self, keys: &[String]) -> Option<&(String, String, Vec<String>)> {
+        self.bindings.get(keys)
+    }
+}
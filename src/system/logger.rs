@@ -0,0 +1,215 @@
+use crate::system::paths;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_RECORDS: usize = 10_000; // kept in memory for the log viewer
+const MAX_FILE_SIZE: u64 = 1024 * 1024; // rotate mos.log when it grows past this
+const MAX_ROTATED_FILES: usize = 3; // mos.log.1 .. mos.log.3
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 5] = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LogLevel> {
+        LogLevel::ALL.into_iter().find(|l| l.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub time: SystemTime,
+    pub level: LogLevel,
+    pub target: String, // "mos" for core code, the plugin id for plugins
+    pub message: String,
+}
+
+// HH:MM:SS in UTC, good enough to line things up without pulling in a time crate
+pub fn time_of_day(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) % 86_400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl LogRecord {
+    pub fn time_of_day(&self) -> String {
+        time_of_day(self.time)
+    }
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:5} [{}] {}", self.time_of_day(), self.level.name().to_uppercase(), self.target, self.message)
+    }
+}
+
+struct Logger {
+    level: LogLevel, // written to mos.log at this level and above, every record is kept in memory
+    records: VecDeque<LogRecord>,
+    generation: u64, // bumped on every record, lets the log viewer know when to redraw
+    file: Option<(PathBuf, File)>,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    level: LogLevel::Info,
+    records: VecDeque::new(),
+    generation: 0,
+    file: None,
+});
+
+impl Logger {
+    fn rotate(&mut self) {
+        let Some((path, _)) = self.file.take() else {
+            return;
+        };
+
+        for i in (1..MAX_ROTATED_FILES).rev() {
+            std::fs::rename(path.with_extension(format!("log.{}", i)), path.with_extension(format!("log.{}", i + 1))).ok();
+        }
+        std::fs::rename(&path, path.with_extension("log.1")).ok();
+
+        if let Ok(file) = File::create(&path) {
+            self.file = Some((path, file));
+        }
+    }
+
+    fn write_to_file(&mut self, record: &LogRecord) {
+        let too_big = self.file.as_ref()
+            .and_then(|(_, file)| file.metadata().ok())
+            .is_some_and(|m| m.len() >= MAX_FILE_SIZE);
+        if too_big {
+            self.rotate();
+        }
+
+        if let Some((_, file)) = self.file.as_mut() {
+            writeln!(file, "{}", record).ok();
+        }
+    }
+}
+
+// Starts writing to mos.log in the data directory, records from before init are only kept in memory
+pub fn init(level: LogLevel) -> Result<PathBuf, String> {
+    let dir = paths::data_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+
+    let path = dir.join("mos.log");
+    let file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut logger = LOGGER.lock().map_err(|_| "Logger is poisoned".to_string())?;
+    logger.level = level;
+    logger.file = Some((path.clone(), file));
+
+    Ok(path)
+}
+
+pub fn set_level(level: LogLevel) {
+    if let Ok(mut logger) = LOGGER.lock() {
+        logger.level = level;
+    }
+}
+
+pub fn log(level: LogLevel, target: &str, message: impl Into<String>) {
+    let Ok(mut logger) = LOGGER.lock() else {
+        return;
+    };
+
+    let record = LogRecord {
+        time: SystemTime::now(),
+        level,
+        target: target.to_string(),
+        message: message.into(),
+    };
+
+    // The level only decides what goes to mos.log, the log viewer filters the records kept in memory itself
+    if level <= logger.level {
+        logger.write_to_file(&record);
+    }
+
+    if logger.records.len() >= MAX_RECORDS {
+        logger.records.pop_front();
+    }
+    logger.records.push_back(record);
+    logger.generation += 1;
+}
+
+pub fn error(target: &str, message: impl Into<String>) {
+    log(LogLevel::Error, target, message);
+}
+
+pub fn warn(target: &str, message: impl Into<String>) {
+    log(LogLevel::Warn, target, message);
+}
+
+pub fn info(target: &str, message: impl Into<String>) {
+    log(LogLevel::Info, target, message);
+}
+
+pub fn debug(target: &str, message: impl Into<String>) {
+    log(LogLevel::Debug, target, message);
+}
+
+pub fn generation() -> u64 {
+    LOGGER.lock().map(|l| l.generation).unwrap_or(0)
+}
+
+// How many of the records kept in memory are at or above the given level
+pub fn record_count(level: LogLevel) -> usize {
+    LOGGER.lock()
+        .map(|l| l.records.iter().filter(|r| r.level <= level).count())
+        .unwrap_or(0)
+}
+
+// A window of the records at or above the given level, oldest first, only those are cloned
+pub fn records(level: LogLevel, skip: usize, take: usize) -> Vec<LogRecord> {
+    LOGGER.lock()
+        .map(|l| l.records.iter().filter(|r| r.level <= level).skip(skip).take(take).cloned().collect())
+        .unwrap_or_default()
+}
+
+pub fn level() -> LogLevel {
+    LOGGER.lock().map(|l| l.level).unwrap_or(LogLevel::Info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_records_below_the_file_level() {
+        let target = "logger-test";
+        let count = |level| records(level, 0, MAX_RECORDS).iter().filter(|r| r.target == target).count();
+        set_level(LogLevel::Warn);
+        log(LogLevel::Trace, target, "trace");
+        log(LogLevel::Warn, target, "warn");
+        assert_eq!(count(LogLevel::Trace), 2);
+        assert_eq!(count(LogLevel::Info), 1);
+        assert_eq!(level(), LogLevel::Warn);
+    }
+}
\ No newline at end of file
@@ -1,4 +1,15 @@
-use mlua::{Error, Lua};
+use crate::plugin::dependency::Dependency;
+use crate::plugin::lua_plugin::{LuaPlugin, LuaRegistrations, SharedLuaRegistrations};
+use crate::plugin::subscription::{EventFilter, EventKind, Subscription};
+use crate::system::logger::{self, LogLevel};
+use crate::system::notifications::{self, Severity};
+use crate::system::signs::{self, Sign};
+use crate::system::status::{self, Align};
+use crate::system::theme::{self, Theme};
+use mlua::{Error, Function, Lua, Table, Value};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 pub struct LuaManager {
     lua: Lua,
@@ -13,17 +24,234 @@ impl LuaManager {
 
     pub fn init(&self) -> Result<(), Error> {
         let globals = self.lua.globals();
-        //let mos_api_table = L
+        let mos_api_table = self.lua.create_table()?;
+        mos_api_table.set("version", env!("CARGO_PKG_VERSION"))?;
 
-        globals.set("mos", "test")
+        globals.set("mos", mos_api_table)
     }
 
-    pub fn load_plugin(&self, plugin_id: &str) -> Result<(), Error> {
+    // filter is either nil or a table like { command = "save" }, { key = { "control", "s" } } or { filetypes = { "rs" } }
+    fn parse_filter(filter: Option<Table>) -> Result<EventFilter, Error> {
+        let Some(filter) = filter else {
+            return Ok(EventFilter::Any);
+        };
+
+        if let Some(command) = filter.get::<Option<String>>("command")? {
+            Ok(EventFilter::Command(command))
+        } else if let Some(mut keys) = filter.get::<Option<Vec<String>>>("key")? {
+            keys.sort();
+            Ok(EventFilter::Key(keys))
+        } else if let Some(filetypes) = filter.get::<Option<Vec<String>>>("filetypes")? {
+            Ok(EventFilter::FileType(filetypes))
+        } else {
+            Ok(EventFilter::Any)
+        }
+    }
+
+    // Theme tables go through the same parser as theme files
+    fn to_json(value: Value) -> Result<serde_json::Value, Error> {
+        Ok(match value {
+            Value::Nil => serde_json::Value::Null,
+            Value::Boolean(b) => serde_json::Value::Bool(b),
+            Value::Integer(n) => serde_json::Value::from(n),
+            Value::Number(n) => serde_json::Value::from(n),
+            Value::String(s) => serde_json::Value::String(s.to_str()?.to_string()),
+            Value::Table(table) if table.raw_len() > 0 => serde_json::Value::Array(
+                table.sequence_values::<Value>().map(|v| Self::to_json(v?)).collect::<Result<_, _>>()?,
+            ),
+            Value::Table(table) => {
+                let mut fields = serde_json::Map::new();
+                for pair in table.pairs::<String, Value>() {
+                    let (key, value) = pair?;
+                    fields.insert(key, Self::to_json(value)?);
+                }
+                serde_json::Value::Object(fields)
+            }
+            other => return Err(Error::runtime(format!("unexpected {} in a theme", other.type_name()))),
+        })
+    }
+
+    fn create_plugin_api(&self, plugin_id: &str, registrations: SharedLuaRegistrations) -> Result<Table, Error> {
+        let api = self.lua.create_table()?;
+        api.set("version", env!("CARGO_PKG_VERSION"))?;
+
+        // mos.log(level, message), logged with the plugin id as target
+        let target = plugin_id.to_string();
+        let log = self.lua.create_function(move |_, (level, message): (String, String)| {
+            let level = LogLevel::from_name(&level)
+                .ok_or_else(|| Error::runtime(format!("unknown log level '{}'", level)))?;
+            logger::log(level, &target, message);
+            Ok(())
+        })?;
+        api.set("log", log)?;
+
+        // mos.notify(message, [severity]), shown as a toast and kept in the message history
+        let source = plugin_id.to_string();
+        let notify = self.lua.create_function(move |_, (message, severity): (String, Option<String>)| {
+            let severity = match severity {
+                Some(name) => Severity::from_name(&name)
+                    .ok_or_else(|| Error::runtime(format!("unknown severity '{}'", name)))?,
+                None => Severity::Info,
+            };
+            notifications::notify(severity, &source, message);
+            Ok(())
+        })?;
+        api.set("notify", notify)?;
+
+        // mos.status(name, text, [align]), a status line segment, a nil text removes it
+        let owner = plugin_id.to_string();
+        let status_segment = self.lua.create_function(move |_, (name, text, align): (String, Option<String>, Option<String>)| {
+            let align = match align {
+                Some(name) => Align::from_name(&name)
+                    .ok_or_else(|| Error::runtime(format!("unknown alignment '{}'", name)))?,
+                None => Align::Left,
+            };
+            match text {
+                Some(text) => status::set_segment(&owner, &name, text, align),
+                None => status::remove_segment(&owner, &name),
+            }
+            Ok(())
+        })?;
+        api.set("status", status_segment)?;
+
+        // mos.sign(path, line, text, [group], [priority]), a gutter sign on a 1-based line, a nil text removes it
+        let owner = plugin_id.to_string();
+        let sign = self.lua.create_function(move |_, (path, line, text, group, priority): (String, usize, Option<String>, Option<String>, Option<i32>)| {
+            let path = PathBuf::from(path);
+            let line = line.saturating_sub(1);
+            match text {
+                Some(text) => signs::set_sign(Sign {
+                    owner: owner.clone(),
+                    path,
+                    line,
+                    text,
+                    group: group.unwrap_or_else(|| String::from("ui.gutter.sign")),
+                    priority: priority.unwrap_or(0),
+                }),
+                None => signs::remove_sign(&owner, &path, line),
+            }
+            Ok(())
+        })?;
+        api.set("sign", sign)?;
+
+        // mos.clear_signs([path]), the plugin's signs in one file or everywhere
+        let owner = plugin_id.to_string();
+        let clear_signs = self.lua.create_function(move |_, path: Option<String>| {
+            signs::clear_signs(&owner, path.map(PathBuf::from).as_deref());
+            Ok(())
+        })?;
+        api.set("clear_signs", clear_signs)?;
+
+        // mos.theme(name, [theme]), defines a theme from a table like a theme file, or switches to it without one.
+        // Defined right away so the plugin can switch to it while loading, and again whenever the plugin is enabled
+        let owner = plugin_id.to_string();
+        let themes = registrations.clone();
+        let define_theme = self.lua.create_function(move |_, (name, spec): (String, Option<Table>)| {
+            match spec {
+                Some(spec) => {
+                    let value = Self::to_json(Value::Table(spec))?;
+                    let mut definition = Theme::from_value(&name, &value).map_err(Error::runtime)?;
+                    definition.name = name;
+                    definition.owner = Some(owner.clone());
+                    themes.borrow_mut().themes.push(definition.clone());
+                    theme::define(definition);
+                    Ok(())
+                }
+                None => theme::set_current(&name).map_err(Error::runtime),
+            }
+        })?;
+        api.set("theme", define_theme)?;
+
+        // mos.subscribe(kind, [filter], callback)
+        let subscriptions = registrations.clone();
+        let subscribe = self.lua.create_function(move |_, (kind, filter, callback): (String, Value, Option<Function>)| {
+            let event_kind = EventKind::from_name(&kind)
+                .ok_or_else(|| Error::runtime(format!("unknown event kind '{}'", kind)))?;
+
+            let (filter, callback) = match (filter, callback) {
+                (Value::Function(callback), None) => (None, callback),
+                (Value::Table(filter), Some(callback)) => (Some(filter), callback),
+                (Value::Nil, Some(callback)) => (None, callback),
+                _ => return Err(Error::runtime("expected mos.subscribe(kind, [filter], callback)")),
+            };
+
+            let subscription = Subscription::new(event_kind).with_filter(Self::parse_filter(filter)?);
+            subscriptions.borrow_mut().subscriptions.push((subscription, callback));
+            Ok(())
+        })?;
+        api.set("subscribe", subscribe)?;
+
+        // mos.command(name, [description], callback)
+        let commands = registrations.clone();
+        let command = self.lua.create_function(move |_, (name, description, callback): (String, Value, Option<Function>)| {
+            let (description, callback) = match (description, callback) {
+                (Value::Function(callback), None) => (String::new(), callback),
+                (Value::String(description), Some(callback)) => (description.to_str()?.to_string(), callback),
+                _ => return Err(Error::runtime("expected mos.command(name, [description], callback)")),
+            };
+
+            let mut registrations = commands.borrow_mut();
+            registrations.subscriptions.push((Subscription::command(&name), callback));
+            registrations.commands.push((name, description));
+            Ok(())
+        })?;
+        api.set("command", command)?;
+
+        // mos.keymap(keys, command, [args]), e.g. mos.keymap({ "control", "s" }, "save")
+        let keymaps = registrations.clone();
+        let keymap = self.lua.create_function(move |_, (keys, command, args): (Vec<String>, String, Option<Vec<String>>)| {
+            keymaps.borrow_mut().keymaps.push((keys, command, args.unwrap_or_default()));
+            Ok(())
+        })?;
+        api.set("keymap", keymap)?;
+
+        Ok(api)
+    }
+
+    // The chunk may return a table with name, version, description and dependencies, the id is the file name.
+    // dependencies = { other_plugin = ">=0.2" }
+    pub fn load_plugin(&self, plugin_path: &str) -> Result<LuaPlugin, Error> {
+        let path = Path::new(plugin_path);
+        let id = path.file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| plugin_path.to_string());
+
+        let registrations: SharedLuaRegistrations = Rc::new(RefCell::new(LuaRegistrations::default()));
+
+        // Plugins get their own environment, reads fall through to the globals
         let env = self.lua.create_table()?;
-        env.set_metatable(Some(self.lua.globals()))?;
+        let env_meta = self.lua.create_table()?;
+        env_meta.set("__index", self.lua.globals())?;
+        env.set_metatable(Some(env_meta))?;
+        env.set("mos", self.create_plugin_api(&id, registrations.clone())?)?;
 
-        self.lua.load(plugin_id)
+        let info: Option<Table> = self.lua.load(path)
             .set_environment(env)
-            .exec()
+            .eval()?;
+
+        let mut plugin = LuaPlugin::new(id, self.lua.clone(), registrations);
+        if let Some(info) = info {
+            if let Some(name) = info.get::<Option<String>>("name")? {
+                plugin.name = name;
+            }
+            if let Some(version) = info.get::<Option<String>>("version")? {
+                plugin.version = version;
+            }
+            if let Some(description) = info.get::<Option<String>>("description")? {
+                plugin.description = description;
+            }
+            // { mos_editor = "^1.0" }, or a list of ids when any version will do
+            if let Some(dependencies) = info.get::<Option<Table>>("dependencies")? {
+                for pair in dependencies.pairs::<Value, String>() {
+                    let dependency = match pair? {
+                        (Value::String(plugin_id), version) => Dependency::new(&plugin_id.to_str()?, &version).map_err(Error::runtime)?,
+                        (_, plugin_id) => Dependency::any_version(&plugin_id),
+                    };
+                    plugin.dependencies.push(dependency);
+                }
+            }
+        }
+
+        Ok(plugin)
     }
 }
\ No newline at end of file
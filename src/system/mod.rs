@@ -1,3 +1,17 @@
 pub mod plugin_registry;
 pub mod panel_registry;
-pub mod lua_manager;
\ No newline at end of file
+pub mod lua_manager;
+pub mod event_bus;
+pub mod event_loop;
+pub mod command_registry;
+pub mod keymap_registry;
+pub mod panic_guard;
+pub mod terminal_session;
+pub mod paths;
+pub mod logger;
+pub mod notifications;
+pub mod cli;
+pub mod status;
+pub mod signs;
+pub mod theme;
+pub mod clipboard;
\ No newline at end of file
@@ -0,0 +1,141 @@
+use crate::system::event_bus;
+use crate::system::logger::{self, LogLevel};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+const MAX_HISTORY: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Success => "success",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Severity> {
+        [Severity::Info, Severity::Success, Severity::Warning, Severity::Error]
+            .into_iter()
+            .find(|s| s.name().eq_ignore_ascii_case(name))
+    }
+
+    // How long the toast stays on screen, errors stay longer so they can be read
+    pub fn duration(&self) -> Duration {
+        match self {
+            Severity::Info | Severity::Success => Duration::from_secs(3),
+            Severity::Warning => Duration::from_secs(5),
+            Severity::Error => Duration::from_secs(8),
+        }
+    }
+
+    fn log_level(&self) -> LogLevel {
+        match self {
+            Severity::Info | Severity::Success => LogLevel::Info,
+            Severity::Warning => LogLevel::Warn,
+            Severity::Error => LogLevel::Error,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub id: u64,
+    pub time: SystemTime,
+    pub severity: Severity,
+    pub source: String, // "mos" for core code, the plugin id for plugins
+    pub message: String,
+}
+
+struct Notifications {
+    next_id: u64,
+    history: VecDeque<Notification>,
+    pending: Vec<Notification>, // not yet shown as a toast
+    generation: u64, // bumped on every notification, lets the history panel know when to redraw
+}
+
+static NOTIFICATIONS: Mutex<Notifications> = Mutex::new(Notifications {
+    next_id: 0,
+    history: VecDeque::new(),
+    pending: Vec::new(),
+    generation: 0,
+});
+
+// Shows a toast and keeps the message in the history, usable from anywhere like the logger
+pub fn notify(severity: Severity, source: &str, message: impl Into<String>) {
+    let message = message.into();
+    logger::log(severity.log_level(), source, message.clone());
+
+    let Ok(mut notifications) = NOTIFICATIONS.lock() else {
+        return;
+    };
+
+    let notification = Notification {
+        id: notifications.next_id,
+        time: SystemTime::now(),
+        severity,
+        source: source.to_string(),
+        message,
+    };
+    notifications.next_id += 1;
+    notifications.generation += 1;
+
+    if notifications.history.len() >= MAX_HISTORY {
+        notifications.history.pop_front();
+    }
+    notifications.history.push_back(notification.clone());
+    notifications.pending.push(notification);
+    drop(notifications);
+
+    event_bus::wake();
+}
+
+pub fn info(source: &str, message: impl Into<String>) {
+    notify(Severity::Info, source, message);
+}
+
+pub fn success(source: &str, message: impl Into<String>) {
+    notify(Severity::Success, source, message);
+}
+
+pub fn warning(source: &str, message: impl Into<String>) {
+    notify(Severity::Warning, source, message);
+}
+
+pub fn error(source: &str, message: impl Into<String>) {
+    notify(Severity::Error, source, message);
+}
+
+// The notifications that haven't been shown as a toast yet
+pub fn take_pending() -> Vec<Notification> {
+    NOTIFICATIONS.lock()
+        .map(|mut n| std::mem::take(&mut n.pending))
+        .unwrap_or_default()
+}
+
+pub fn history() -> Vec<Notification> {
+    NOTIFICATIONS.lock()
+        .map(|n| n.history.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub fn generation() -> u64 {
+    NOTIFICATIONS.lock().map(|n| n.generation).unwrap_or(0)
+}
+
+pub fn clear_history() {
+    if let Ok(mut notifications) = NOTIFICATIONS.lock() {
+        notifications.history.clear();
+        notifications.generation += 1;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,60 @@
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+
+#[derive(Clone, Debug)]
+pub struct PanicReport {
+    pub message: String,
+    pub location: String,
+    pub backtrace: String,
+}
+
+thread_local! {
+    static GUARD_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static LAST_PANIC: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+pub fn payload_message(payload: &dyn Any) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    }
+}
+
+// Panics inside guard() are recorded instead of printed, everything else goes to the previous hook
+pub fn install_hook() {
+    let previous = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        if GUARD_DEPTH.with(|d| d.get()) == 0 {
+            previous(info);
+            return;
+        }
+
+        let report = PanicReport {
+            message: payload_message(info.payload()),
+            location: info.location().map(|l| l.to_string()).unwrap_or_default(),
+            backtrace: Backtrace::force_capture().to_string(),
+        };
+        LAST_PANIC.with(|p| *p.borrow_mut() = Some(report));
+    }));
+}
+
+// Runs a plugin or panel callback, turning a panic into an error so one faulty plugin can't take down mos
+pub fn guard<R>(f: impl FnOnce() -> R) -> Result<R, PanicReport> {
+    GUARD_DEPTH.with(|d| d.set(d.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    GUARD_DEPTH.with(|d| d.set(d.get() - 1));
+
+    result.map_err(|_| {
+        LAST_PANIC.with(|p| p.borrow_mut().take()).unwrap_or(PanicReport {
+            message: String::from("unknown panic"),
+            location: String::new(),
+            backtrace: String::new(),
+        })
+    })
+}
\ No newline at end of file
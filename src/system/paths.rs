@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+// Where mos keeps its logs and crash reports, $XDG_DATA_HOME/mos or ~/.local/share/mos
+pub fn data_dir() -> PathBuf {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .unwrap_or_else(std::env::temp_dir);
+
+    base.join("mos")
+}
+
+
+// Where mos looks for user files like themes, $XDG_CONFIG_HOME/mos or ~/.config/mos
+pub fn config_dir() -> PathBuf {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(std::env::temp_dir);
+
+    base.join("mos")
+}
\ No newline at end of file
@@ -1,15 +1,30 @@
+use crate::app::CORE_ID;
 use crate::event::event::Event;
-use crate::plugin::plugin::Plugin;
-use crate::system::panel_registry::PanelRegistry;
+use crate::plugin::plugin::{Plugin, PluginContext};
+use crate::plugin::subscription::{DispatchStats, EventKind, Subscription};
+use crate::system::panic_guard::{self, PanicReport};
+use crate::system::logger;
+use crate::system::{signs, status, theme};
+use std::collections::{HashMap, HashSet};
 
 pub struct PluginRegistry {
     plugins: Vec<Box<dyn Plugin>>,
+    enabled: HashSet<String>,
+    subscriptions: HashMap<String, Vec<Subscription>>, // plugin_id -> subscriptions, collected when enabled
+    stats: DispatchStats,
+    plugin_stats: HashMap<String, DispatchStats>,
+    faulted: Vec<(String, PanicReport)>, // plugins that panicked, to be disabled by Mos
 }
 
 impl PluginRegistry {
     pub fn new() -> Self {
         Self {
             plugins: Vec::new(),
+            enabled: HashSet::new(),
+            subscriptions: HashMap::new(),
+            stats: DispatchStats::default(),
+            plugin_stats: HashMap::new(),
+            faulted: Vec::new(),
         }
     }
 
@@ -20,20 +35,262 @@ impl PluginRegistry {
     pub fn get_plugins(&self) -> &Vec<Box<dyn Plugin>> {
         &self.plugins
     }
-    
-    pub fn enable_plugins(&mut self, panel_registry: &mut PanelRegistry) {
-        for plugin in self.plugins.iter_mut() {
-            if let Err(e) = plugin.enable(panel_registry) {
-                eprintln!("Failed to enable plugin {}: {}", plugin.name(), e);
+
+    pub fn is_enabled(&self, plugin_id: &str) -> bool {
+        self.enabled.contains(plugin_id)
+    }
+
+    pub fn get_plugin(&self, plugin_id: &str) -> Option<&dyn Plugin> {
+        self.plugins.iter().find(|p| p.id() == plugin_id).map(|p| p.as_ref())
+    }
+
+    // Enables every registered plugin, dependencies first, a plugin that fails doesn't stop the others
+    pub fn enable_plugins(&mut self, context: &mut PluginContext) {
+        let plugin_ids: Vec<String> = self.plugins.iter().map(|p| p.id()).collect();
+        for plugin_id in plugin_ids {
+            match self.enable_plugin(&plugin_id, context) {
+                Ok(()) => logger::debug(CORE_ID, format!("Enabled plugin {}", plugin_id)),
+                Err(e) => logger::error(CORE_ID, e),
             }
         }
     }
-    
+
+    // Orders the plugin and everything it depends on so that dependencies come before their dependents
+    pub fn enable_order(&self, plugin_id: &str) -> Result<Vec<String>, String> {
+        fn visit(registry: &PluginRegistry, plugin_id: &str, path: &mut Vec<String>, order: &mut Vec<String>) -> Result<(), String> {
+            if order.iter().any(|id| id == plugin_id) {
+                return Ok(());
+            }
+
+            if let Some(start) = path.iter().position(|id| id == plugin_id) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(plugin_id.to_string());
+                return Err(format!("Cyclic plugin dependency: {}", cycle.join(" -> ")));
+            }
+
+            let plugin = registry.get_plugin(plugin_id).ok_or_else(|| match path.last() {
+                Some(dependent) => format!("Plugin {} depends on {}, which is not installed", dependent, plugin_id),
+                None => format!("No plugin with id {}", plugin_id),
+            })?;
+
+            path.push(plugin_id.to_string());
+            for dependency in plugin.dependencies() {
+                visit(registry, &dependency.plugin_id, path, order)?;
+            }
+            path.pop();
+
+            order.push(plugin_id.to_string());
+            Ok(())
+        }
+
+        let mut order = Vec::new();
+        visit(self, plugin_id, &mut Vec::new(), &mut order)?;
+        Ok(order)
+    }
+
+    // The enabled plugins that depend on this one, directly or not, dependents before their dependencies
+    pub fn dependents(&self, plugin_id: &str) -> Vec<String> {
+        let mut dependents: Vec<String> = Vec::new();
+        let mut queue = vec![plugin_id.to_string()];
+
+        while let Some(current) = queue.pop() {
+            for plugin in self.plugins.iter().filter(|p| self.is_enabled(&p.id())) {
+                let depends = plugin.dependencies().iter().any(|d| d.plugin_id == current);
+                if depends && !dependents.contains(&plugin.id()) && plugin.id() != plugin_id {
+                    dependents.push(plugin.id());
+                    queue.push(plugin.id());
+                }
+            }
+        }
+
+        // A plugin has to be disabled before anything it depends on, so reverse the enable order
+        let mut ordered: Vec<String> = Vec::new();
+        for dependent in &dependents {
+            for id in self.enable_order(dependent).unwrap_or_default() {
+                if dependents.contains(&id) && !ordered.contains(&id) {
+                    ordered.push(id);
+                }
+            }
+        }
+        ordered.reverse();
+        ordered
+    }
+
+    // Enables the plugin after its dependencies, checking that their versions match the requirements
+    pub fn enable_plugin(&mut self, plugin_id: &str, context: &mut PluginContext) -> Result<(), String> {
+        if self.is_enabled(plugin_id) {
+            return Ok(());
+        }
+
+        for id in self.enable_order(plugin_id)? {
+            if self.is_enabled(&id) {
+                continue;
+            }
+
+            let plugin = self.get_plugin(&id).ok_or_else(|| format!("No plugin with id {}", id))?;
+            for dependency in plugin.dependencies() {
+                let installed = self.get_plugin(&dependency.plugin_id)
+                    .ok_or_else(|| format!("Plugin {} depends on {}, which is not installed", id, dependency.plugin_id))?;
+
+                dependency.check(&installed.version()).map_err(|e| format!("Plugin {} {}", id, e))?;
+
+                if !self.is_enabled(&dependency.plugin_id) {
+                    return Err(format!("Plugin {} depends on {}, which failed to enable", id, dependency.plugin_id));
+                }
+            }
+
+            let plugin = self.plugins.iter_mut()
+                .find(|p| p.id() == id)
+                .ok_or_else(|| format!("No plugin with id {}", id))?;
+
+            let result = panic_guard::guard(|| plugin.enable(context))
+                .unwrap_or_else(|report| Err(format!("panicked: {} at {}", report.message, report.location)));
+
+            if let Err(e) = result {
+                // Don't leave half of the registration behind
+                Self::unregister(&id, context);
+                return Err(format!("Failed to enable plugin {}: {}", plugin.name(), e));
+            }
+
+            self.subscriptions.insert(plugin.id(), plugin.subscriptions());
+            self.enabled.insert(plugin.id());
+        }
+
+        Ok(())
+    }
+
+    // Disables the plugin and everything depending on it, they stay in the registry so they can be enabled again.
+    // Returns the ids of the plugins that were disabled.
+    pub fn disable_plugin(&mut self, plugin_id: &str, context: &mut PluginContext) -> Result<Vec<String>, String> {
+        if !self.is_enabled(plugin_id) {
+            return Err(format!("Plugin {} is not enabled", plugin_id));
+        }
+
+        let mut to_disable = self.dependents(plugin_id);
+        to_disable.push(plugin_id.to_string());
+
+        let mut errors = Vec::new();
+        for id in &to_disable {
+            let Some(plugin) = self.plugins.iter_mut().find(|p| p.id() == *id) else {
+                continue;
+            };
+
+            let result = panic_guard::guard(|| plugin.disable())
+                .unwrap_or_else(|report| Err(format!("panicked: {} at {}", report.message, report.location)));
+
+            if let Err(e) = result {
+                errors.push(format!("Plugin {} failed to disable cleanly: {}", plugin.name(), e));
+            }
+
+            Self::unregister(id, context);
+            self.subscriptions.remove(id);
+            self.enabled.remove(id);
+        }
+
+        if errors.is_empty() {
+            Ok(to_disable)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    fn unregister(plugin_id: &str, context: &mut PluginContext) {
+        context.panel_registry.unregister_panels_by_plugin(plugin_id.to_string());
+        context.command_registry.unregister_commands_by_plugin(plugin_id);
+        context.keymap_registry.unregister_keybindings_by_plugin(plugin_id);
+        status::remove_segments_by_owner(plugin_id);
+        signs::remove_signs_by_owner(plugin_id);
+        theme::remove_themes_by_owner(plugin_id);
+    }
+
+    // Re-read the subscriptions of a plugin, e.g. after a lua plugin subscribed from a callback
+    pub fn refresh_subscriptions(&mut self, plugin_id: &str) {
+        if let Some(plugin) = self.plugins.iter().find(|p| p.id() == plugin_id)
+            && self.is_enabled(plugin_id) {
+            self.subscriptions.insert(plugin.id(), plugin.subscriptions());
+        }
+    }
+
+    pub fn get_subscriptions(&self, plugin_id: &str) -> &[Subscription] {
+        self.subscriptions.get(plugin_id).map(|s| s.as_slice()).unwrap_or(&[])
+    }
+
+    // Whether any enabled plugin subscribed to this kind of event, e.g. to only tick when someone listens
+    pub fn has_subscribers(&self, kind: EventKind) -> bool {
+        self.subscriptions.values().flatten().any(|s| s.kind == kind)
+    }
+
+    pub fn take_faulted(&mut self) -> Vec<(String, PanicReport)> {
+        std::mem::take(&mut self.faulted)
+    }
+
+    pub fn dispatch_stats(&self) -> &DispatchStats {
+        &self.stats
+    }
+
+    pub fn plugin_dispatch_stats(&self, plugin_id: &str) -> Option<&DispatchStats> {
+        self.plugin_stats.get(plugin_id)
+    }
+
+    // Dispatches an event to the plugins subscribed to it. A lua callback can subscribe to more events,
+    // so the plugins that got the event have their subscriptions read again afterwards
     pub fn handle_plugins_events(&mut self, event: Event) {
+        self.stats.events += 1;
+        let mut delivered = Vec::new();
+
         for plugin in self.plugins.iter_mut() {
-            plugin.handle_event(event.clone()).unwrap_or_else(|e| {
-                eprintln!("Error handling event in plugin {}: {}", plugin.name(), e);
-            });
+            let plugin_id = plugin.id();
+            let stats = self.plugin_stats.entry(plugin_id.clone()).or_default();
+            stats.events += 1;
+
+            let subscribed = self.subscriptions.get(&plugin_id)
+                .is_some_and(|subs| subs.iter().any(|s| s.matches(&event)));
+
+            if !subscribed {
+                stats.skipped += 1;
+                self.stats.skipped += 1;
+                continue;
+            }
+
+            stats.delivered += 1;
+            self.stats.delivered += 1;
+
+            match panic_guard::guard(|| plugin.handle_event(event.clone())) {
+                Ok(Ok(())) => delivered.push(plugin_id),
+                Ok(Err(e)) => {
+                    stats.errors += 1;
+                    self.stats.errors += 1;
+                    logger::error(&plugin_id, format!("Error handling event in plugin {}: {}", plugin.name(), e));
+                    delivered.push(plugin_id);
+                }
+                Err(report) => {
+                    stats.errors += 1;
+                    self.stats.errors += 1;
+                    // Stop sending it events right away, Mos disables it on the next update
+                    self.subscriptions.remove(&plugin_id);
+                    self.faulted.push((plugin_id, report));
+                }
+            }
+        }
+
+        for plugin_id in delivered {
+            self.refresh_subscriptions(&plugin_id);
+        }
+    }
+
+    // A line per plugin with what the dispatch did for it, for the plugin.stats command
+    pub fn stats_report(&self) -> String {
+        let total = self.dispatch_stats();
+        let mut lines = vec![format!("{} events, {} delivered, {} skipped, {} errors", total.events, total.delivered, total.skipped, total.errors)];
+        for plugin in self.get_plugins() {
+            let plugin_id = plugin.id();
+            let stats = self.plugin_dispatch_stats(&plugin_id).cloned().unwrap_or_default();
+            let state = if self.is_enabled(&plugin_id) { "" } else { ", disabled" };
+            lines.push(format!(
+                "{}: {} delivered, {} skipped, {} errors, {} subscriptions{}",
+                plugin_id, stats.delivered, stats.skipped, stats.errors, self.get_subscriptions(&plugin_id).len(), state,
+            ));
         }
+        lines.join("\n")
     }
 }
\ No newline at end of file
@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// A mark a plugin puts in the gutter next to a line of a file, e.g. a diagnostic or a changed line
+#[derive(Clone, Debug)]
+pub struct Sign {
+    pub owner: String, // plugin id
+    pub path: PathBuf,
+    pub line: usize, // 0-based
+    pub text: String, // up to two cells, cut to fit
+    pub group: String, // theme group it is drawn with, e.g. "error" or "diff.added"
+    pub priority: i32, // the highest one wins when several plugins mark the same line
+}
+
+struct Signs {
+    signs: Vec<Sign>,
+    generation: u64, // bumped on every change, lets editors know when to redraw
+}
+
+static SIGNS: Mutex<Signs> = Mutex::new(Signs {
+    signs: Vec::new(),
+    generation: 0,
+});
+
+// Adds a sign or replaces the one the plugin already has on that line
+pub fn set_sign(sign: Sign) {
+    let Ok(mut signs) = SIGNS.lock() else {
+        return;
+    };
+
+    signs.signs.retain(|s| s.owner != sign.owner || s.path != sign.path || s.line != sign.line);
+    signs.signs.push(sign);
+    signs.generation += 1;
+}
+
+pub fn remove_sign(owner: &str, path: &Path, line: usize) {
+    if let Ok(mut signs) = SIGNS.lock() {
+        signs.signs.retain(|s| s.owner != owner || s.path != path || s.line != line);
+        signs.generation += 1;
+    }
+}
+
+// All of a plugin's signs in one file, or in every file without a path
+pub fn clear_signs(owner: &str, path: Option<&Path>) {
+    if let Ok(mut signs) = SIGNS.lock() {
+        signs.signs.retain(|s| s.owner != owner || path.is_some_and(|p| s.path != p));
+        signs.generation += 1;
+    }
+}
+
+pub fn remove_signs_by_owner(owner: &str) {
+    clear_signs(owner, None);
+}
+
+// The sign shown on each marked line of a file, by line
+pub fn signs_for(path: &Path) -> Vec<Sign> {
+    let Ok(signs) = SIGNS.lock() else {
+        return Vec::new();
+    };
+
+    let mut shown: Vec<Sign> = Vec::new();
+    for sign in signs.signs.iter().filter(|s| s.path == path) {
+        match shown.iter_mut().find(|s| s.line == sign.line) {
+            Some(existing) if existing.priority < sign.priority => *existing = sign.clone(),
+            Some(_) => {}
+            None => shown.push(sign.clone()),
+        }
+    }
+    shown.sort_by_key(|s| s.line);
+    shown
+}
+
+pub fn generation() -> u64 {
+    SIGNS.lock().map(|s| s.generation).unwrap_or(0)
+}
\ No newline at end of file
@@ -0,0 +1,81 @@
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+impl Align {
+    pub fn from_name(name: &str) -> Option<Align> {
+        match name.to_ascii_lowercase().as_str() {
+            "left" => Some(Align::Left),
+            "right" => Some(Align::Right),
+            _ => None,
+        }
+    }
+}
+
+// An item a plugin shows in the status line, next to the built-in ones
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub owner: String, // plugin id
+    pub name: String,
+    pub text: String,
+    pub align: Align,
+}
+
+struct Segments {
+    segments: Vec<Segment>, // in the order they were first set
+    generation: u64, // bumped on every change, lets the status line know when to redraw
+}
+
+static SEGMENTS: Mutex<Segments> = Mutex::new(Segments {
+    segments: Vec::new(),
+    generation: 0,
+});
+
+// Adds or updates a segment, plugins call this whenever the text should change
+pub fn set_segment(owner: &str, name: &str, text: impl Into<String>, align: Align) {
+    let Ok(mut segments) = SEGMENTS.lock() else {
+        return;
+    };
+
+    let text = text.into();
+    match segments.segments.iter_mut().find(|s| s.owner == owner && s.name == name) {
+        Some(segment) if segment.text == text && segment.align == align => return,
+        Some(segment) => {
+            segment.text = text;
+            segment.align = align;
+        }
+        None => segments.segments.push(Segment {
+            owner: owner.to_string(),
+            name: name.to_string(),
+            text,
+            align,
+        }),
+    }
+    segments.generation += 1;
+}
+
+pub fn remove_segment(owner: &str, name: &str) {
+    if let Ok(mut segments) = SEGMENTS.lock() {
+        segments.segments.retain(|s| s.owner != owner || s.name != name);
+        segments.generation += 1;
+    }
+}
+
+pub fn remove_segments_by_owner(owner: &str) {
+    if let Ok(mut segments) = SEGMENTS.lock() {
+        segments.segments.retain(|s| s.owner != owner);
+        segments.generation += 1;
+    }
+}
+
+pub fn segments() -> Vec<Segment> {
+    SEGMENTS.lock().map(|s| s.segments.clone()).unwrap_or_default()
+}
+
+pub fn generation() -> u64 {
+    SEGMENTS.lock().map(|s| s.generation).unwrap_or(0)
+}
\ No newline at end of file
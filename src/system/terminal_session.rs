@@ -0,0 +1,96 @@
+use crossterm::event::{DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste, EnableFocusChange, EnableMouseCapture, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::execute;
+use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+use std::backtrace::Backtrace;
+use std::io::{stdout, Stdout};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::system::{panic_guard, paths};
+
+// Whether the terminal is currently in the state set up by TerminalSession::enter
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Owns the terminal while mos runs, the user's shell gets its terminal back on drop, early returns and panics
+pub struct TerminalSession {
+    pub terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl TerminalSession {
+    pub fn enter() -> Result<Self, String> {
+        crossterm::terminal::enable_raw_mode().map_err(|e| format!("Failed to enable raw mode: {}", e))?;
+        ACTIVE.store(true, Ordering::SeqCst);
+
+        // Enter the alternate screen and enable mouse capture so only our UI is visible.
+        // Bracketed paste makes pasted text arrive as one event instead of a key per char,
+        // focus changes are reported so buffers can be saved and checked when switching windows.
+        // If anything fails from here on, restore() runs when the error is returned.
+        execute!(stdout(), EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste, EnableFocusChange, PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES,
+            //KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+        )).map_err(|e| {
+            Self::restore();
+            format!("Failed to enter alternate screen: {}", e)
+        })?;
+
+        let terminal = Terminal::new(CrosstermBackend::new(stdout())).map_err(|e| {
+            Self::restore();
+            format!("Failed to initialize terminal: {}", e)
+        })?;
+
+        Ok(Self { terminal })
+    }
+
+    // Best-effort and safe to call more than once, only the first call after enter() does anything
+    pub fn restore() {
+        if !ACTIVE.swap(false, Ordering::SeqCst) {
+            return;
+        }
+
+        crossterm::terminal::disable_raw_mode().ok();
+        execute!(stdout(), PopKeyboardEnhancementFlags, DisableFocusChange, DisableBracketedPaste, DisableMouseCapture, LeaveAlternateScreen, crossterm::cursor::Show).ok();
+    }
+
+    // Restores the terminal before a panic is reported, so the message ends up in the user's shell
+    // instead of on the alternate screen, and writes a crash report with the backtrace.
+    // Has to be installed before panic_guard::install_hook, which only forwards uncaught panics here.
+    pub fn install_panic_hook() {
+        std::panic::set_hook(Box::new(|info| {
+            Self::restore();
+
+            let message = panic_guard::payload_message(info.payload());
+            let location = info.location().map(|l| l.to_string()).unwrap_or_default();
+
+            eprintln!("mos panicked: {} at {}", message, location);
+
+            match Self::write_crash_report(&message, &location) {
+                Ok(path) => eprintln!("A crash report was written to {}", path.display()),
+                Err(e) => eprintln!("Failed to write crash report: {}", e),
+            }
+        }));
+    }
+
+    fn write_crash_report(message: &str, location: &str) -> Result<PathBuf, String> {
+        let dir = paths::data_dir().join("crash");
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let path = dir.join(format!("crash-{}.txt", timestamp));
+
+        let report = format!(
+            "mos {} crashed\n\npanic: {}\nlocation: {}\n\nbacktrace:\n{}\n",
+            env!("CARGO_PKG_VERSION"), message, location, Backtrace::force_capture(),
+        );
+        std::fs::write(&path, report).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        Ok(path)
+    }
+}
+
+impl Drop for TerminalSession {
+    fn drop(&mut self) {
+        Self::restore();
+    }
+}
@@ -0,0 +1,540 @@
+use crate::system::paths;
+use ratatui::style::{Color, Modifier, Style};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+// How many colours the terminal can show, theme colours are brought down to it when drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Indexed, // the xterm 256 colour palette
+    Ansi, // the 16 named colours
+}
+
+// xterm's defaults for the 16 named colours, used to find the closest one
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI[index as usize].1,
+        16..=231 => {
+            let i = index - 16;
+            (CUBE_LEVELS[(i / 36) as usize], CUBE_LEVELS[(i / 6 % 6) as usize], CUBE_LEVELS[(i % 6) as usize])
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+// The closest of the 6x6x6 cube and the grey ramp
+fn rgb_to_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let cube_index = |v: u8| CUBE_LEVELS.iter().enumerate().min_by_key(|(_, l)| (**l as i32 - v as i32).abs()).map(|(i, _)| i as u8).unwrap_or(0);
+    let cube = 16 + 36 * cube_index(rgb.0) + 6 * cube_index(rgb.1) + cube_index(rgb.2);
+
+    let average = ((rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3) as u8;
+    let grey = 232 + (average.saturating_sub(3) / 10).min(23);
+
+    if distance(indexed_rgb(grey), rgb) < distance(indexed_rgb(cube), rgb) { grey } else { cube }
+}
+
+fn rgb_to_ansi(rgb: (u8, u8, u8)) -> Color {
+    ANSI.iter().min_by_key(|(_, c)| distance(*c, rgb)).map(|(color, _)| *color).unwrap_or(Color::Reset)
+}
+
+impl ColorDepth {
+    // COLORTERM is how most terminals announce 24-bit colour, TERM names the 256 colour ones
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default().to_ascii_lowercase();
+        let term = std::env::var("TERM").unwrap_or_default().to_ascii_lowercase();
+
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") || !colorterm.is_empty() {
+            ColorDepth::Indexed
+        } else {
+            ColorDepth::Ansi
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            "256" => Some(ColorDepth::Indexed),
+            "16" => Some(ColorDepth::Ansi),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorDepth::TrueColor => "truecolor",
+            ColorDepth::Indexed => "256",
+            ColorDepth::Ansi => "16",
+        }
+    }
+
+    pub fn adapt_color(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::Indexed, Color::Rgb(r, g, b)) => Color::Indexed(rgb_to_indexed((r, g, b))),
+            (ColorDepth::Ansi, Color::Rgb(r, g, b)) => rgb_to_ansi((r, g, b)),
+            (ColorDepth::Ansi, Color::Indexed(index)) => rgb_to_ansi(indexed_rgb(index)),
+            _ => color,
+        }
+    }
+
+    pub fn adapt(self, style: Style) -> Style {
+        Style {
+            fg: style.fg.map(|c| self.adapt_color(c)),
+            bg: style.bg.map(|c| self.adapt_color(c)),
+            underline_color: style.underline_color.map(|c| self.adapt_color(c)),
+            ..style
+        }
+    }
+}
+
+fn parse_modifier(name: &str) -> Result<Modifier, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "bold" => Ok(Modifier::BOLD),
+        "dim" => Ok(Modifier::DIM),
+        "italic" => Ok(Modifier::ITALIC),
+        "underlined" | "underline" => Ok(Modifier::UNDERLINED),
+        "reversed" => Ok(Modifier::REVERSED),
+        "crossed_out" => Ok(Modifier::CROSSED_OUT),
+        "slow_blink" => Ok(Modifier::SLOW_BLINK),
+        _ => Err(format!("unknown modifier '{}'", name)),
+    }
+}
+
+// "#rrggbb", a name like "light-blue", a palette entry or a 256 colour index
+fn parse_color(value: &Value, palette: &HashMap<String, Color>) -> Result<Color, String> {
+    match value {
+        Value::String(text) => palette.get(text).copied()
+            .map_or_else(|| text.parse::<Color>().map_err(|_| format!("unknown colour '{}'", text)), Ok),
+        Value::Number(n) => n.as_u64().and_then(|n| u8::try_from(n).ok()).map(Color::Indexed)
+            .ok_or_else(|| format!("colour index {} is out of range", n)),
+        _ => Err(format!("expected a colour, got {}", value)),
+    }
+}
+
+// A colour string is the foreground, a table has fg, bg and modifiers
+fn parse_style(value: &Value, palette: &HashMap<String, Color>) -> Result<Style, String> {
+    let Value::Object(fields) = value else {
+        return Ok(Style::default().fg(parse_color(value, palette)?));
+    };
+
+    let mut style = Style::default();
+    for (key, value) in fields {
+        match key.as_str() {
+            "fg" => style = style.fg(parse_color(value, palette)?),
+            "bg" => style = style.bg(parse_color(value, palette)?),
+            "underline" => style = style.underline_color(parse_color(value, palette)?),
+            "modifiers" => {
+                let names = value.as_array().ok_or("modifiers must be a list")?;
+                for name in names {
+                    style = style.add_modifier(parse_modifier(name.as_str().ok_or("modifiers must be strings")?)?);
+                }
+            }
+            _ => return Err(format!("unknown style field '{}'", key)),
+        }
+    }
+    Ok(style)
+}
+
+fn is_style(fields: &Map<String, Value>) -> bool {
+    ["fg", "bg", "underline", "modifiers"].iter().any(|k| fields.contains_key(*k))
+}
+
+// Styles for highlight groups like "keyword" or "ui.gutter", a missing group falls back to its parent
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    pub owner: Option<String>, // the plugin that defined it, removed with it when it is disabled
+    styles: HashMap<String, Style>,
+}
+
+impl Theme {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            owner: None,
+            styles: HashMap::new(),
+        }
+    }
+
+    fn with_styles(name: &str, groups: &[(&str, Style)]) -> Self {
+        let mut theme = Theme::new(name);
+        for (group, style) in groups {
+            theme.set(group, *style);
+        }
+        theme
+    }
+
+    pub fn default_dark() -> Self {
+        Self::with_styles("default", &[
+            ("ui.gutter", Style::default().fg(Color::Gray)),
+            ("ui.gutter.wrap", Style::default().fg(Color::DarkGray)),
+            ("ui.gutter.current", Style::default().fg(Color::White).add_modifier(Modifier::BOLD)),
+            ("ui.gutter.fold", Style::default().fg(Color::DarkGray)),
+            ("ui.fold", Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)),
+            ("ui.cursor", Style::default().fg(Color::White).add_modifier(Modifier::REVERSED)),
+            ("ui.selection", Style::default().bg(Color::DarkGray)),
+            ("ui.search", Style::default().fg(Color::Black).bg(Color::Yellow)),
+            ("ui.search.current", Style::default().fg(Color::Black).bg(Color::LightRed)),
+            ("ui.substitute", Style::default().fg(Color::Red).add_modifier(Modifier::CROSSED_OUT)),
+            ("ui.substitute.current", Style::default().fg(Color::Black).bg(Color::LightRed).add_modifier(Modifier::CROSSED_OUT)),
+            ("ui.statusline", Style::default().fg(Color::White).bg(Color::DarkGray)),
+            ("ui.statusline.mode", Style::default().fg(Color::Black).bg(Color::Gray).add_modifier(Modifier::BOLD)),
+            ("ui.statusline.mode.normal", Style::default().fg(Color::Black).bg(Color::Blue).add_modifier(Modifier::BOLD)),
+            ("ui.statusline.mode.insert", Style::default().fg(Color::Black).bg(Color::Green).add_modifier(Modifier::BOLD)),
+            ("ui.text.dim", Style::default().fg(Color::DarkGray)),
+            ("ui.text.source", Style::default().fg(Color::Cyan)),
+            ("ui.border", Style::default().fg(Color::Gray)),
+            ("ui.header", Style::default().add_modifier(Modifier::REVERSED)),
+            ("error", Style::default().fg(Color::Red)),
+            ("warning", Style::default().fg(Color::Yellow)),
+            ("info", Style::default().fg(Color::Blue)),
+            ("success", Style::default().fg(Color::Green)),
+            ("diff.added", Style::default().fg(Color::Green)),
+            ("diff.changed", Style::default().fg(Color::Yellow)),
+            ("diff.removed", Style::default().fg(Color::Red)),
+            ("log.error", Style::default().fg(Color::Red)),
+            ("log.warn", Style::default().fg(Color::Yellow)),
+            ("log.info", Style::default().fg(Color::Green)),
+            ("log.debug", Style::default().fg(Color::Blue)),
+            ("log.trace", Style::default().fg(Color::DarkGray)),
+            ("keyword", Style::default().fg(Color::Magenta)),
+            ("type", Style::default().fg(Color::Yellow)),
+            ("function", Style::default().fg(Color::Blue)),
+            ("macro", Style::default().fg(Color::LightMagenta)),
+            ("attribute", Style::default().fg(Color::LightYellow)),
+            ("label", Style::default().fg(Color::LightBlue)),
+            ("string", Style::default().fg(Color::Green)),
+            ("string.escape", Style::default().fg(Color::Cyan)),
+            ("number", Style::default().fg(Color::LightRed)),
+            ("constant", Style::default().fg(Color::LightRed)),
+            ("comment", Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)),
+            ("property", Style::default().fg(Color::Cyan)),
+            ("heading", Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD)),
+            ("markup.bold", Style::default().add_modifier(Modifier::BOLD)),
+            ("markup.italic", Style::default().add_modifier(Modifier::ITALIC)),
+            ("markup.code", Style::default().fg(Color::Green)),
+            ("markup.link", Style::default().fg(Color::Cyan).add_modifier(Modifier::UNDERLINED)),
+            ("markup.list", Style::default().fg(Color::Yellow)),
+            ("markup.quote", Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)),
+        ])
+    }
+
+    // Only what differs from the dark one, the rest is inherited
+    pub fn default_light() -> Self {
+        let mut theme = Self::default_dark();
+        theme.name = String::from("light");
+        let overrides = [
+            ("ui.gutter", Style::default().fg(Color::DarkGray)),
+            ("ui.gutter.wrap", Style::default().fg(Color::Gray)),
+            ("ui.gutter.current", Style::default().fg(Color::Black).add_modifier(Modifier::BOLD)),
+            ("ui.cursor", Style::default().fg(Color::Black).add_modifier(Modifier::REVERSED)),
+            ("ui.selection", Style::default().bg(Color::Gray)),
+            ("ui.statusline", Style::default().fg(Color::Black).bg(Color::Gray)),
+            ("ui.text.dim", Style::default().fg(Color::Gray)),
+            ("ui.text.source", Style::default().fg(Color::Blue)),
+            ("type", Style::default().fg(Color::Rgb(0x8a, 0x5a, 0x00))),
+            ("string", Style::default().fg(Color::Rgb(0x2e, 0x7d, 0x32))),
+            ("comment", Style::default().fg(Color::Gray).add_modifier(Modifier::ITALIC)),
+            ("attribute", Style::default().fg(Color::Rgb(0x8a, 0x5a, 0x00))),
+            ("macro", Style::default().fg(Color::Magenta)),
+        ];
+        for (group, style) in overrides {
+            theme.set(group, style);
+        }
+        theme
+    }
+
+    // A theme file or Lua table: groups map to a colour or { fg, bg, modifiers }, nested tables join their keys with dots.
+    // name, inherits (a theme name, "default" unless given, "" for none) and palette (named colours) are reserved
+    pub fn from_value(fallback_name: &str, value: &Value) -> Result<Self, String> {
+        let Value::Object(fields) = value else {
+            return Err(String::from("a theme must be a table"));
+        };
+
+        let name = fields.get("name").and_then(|n| n.as_str()).unwrap_or(fallback_name);
+        let inherits = fields.get("inherits").and_then(|n| n.as_str()).unwrap_or("default");
+        let mut theme = if inherits.is_empty() {
+            Theme::new(name)
+        } else {
+            let mut parent = get(inherits).ok_or_else(|| format!("theme {} inherits unknown theme {}", name, inherits))?;
+            parent.name = name.to_string();
+            parent.owner = None;
+            parent
+        };
+
+        let mut palette = HashMap::new();
+        if let Some(Value::Object(colors)) = fields.get("palette") {
+            for (color_name, color) in colors {
+                palette.insert(color_name.clone(), parse_color(color, &HashMap::new())?);
+            }
+        }
+
+        let groups = fields.iter().filter(|(key, _)| !["name", "inherits", "palette"].contains(&key.as_str()));
+        for (group, value) in groups {
+            theme.set_value(group, value, &palette)?;
+        }
+
+        Ok(theme)
+    }
+
+    fn set_value(&mut self, group: &str, value: &Value, palette: &HashMap<String, Color>) -> Result<(), String> {
+        match value {
+            Value::Object(fields) if !is_style(fields) => {
+                for (key, value) in fields {
+                    self.set_value(&format!("{}.{}", group, key), value, palette)?;
+                }
+                Ok(())
+            }
+            _ => {
+                let style = parse_style(value, palette).map_err(|e| format!("{}: {}", group, e))?;
+                self.set(group, style);
+                Ok(())
+            }
+        }
+    }
+
+    // A .toml or .json file, named after the file unless it sets a name
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let value: Value = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?,
+            Some("json") => serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?,
+            _ => return Err(format!("{} is not a .toml or .json theme", path.display())),
+        };
+
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        Self::from_value(&stem, &value).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn set(&mut self, group: &str, style: Style) {
+        self.styles.insert(group.to_string(), style);
+    }
+
+    // "string.escape" falls back to "string", unknown groups are unstyled
+    pub fn style(&self, group: &str) -> Style {
+        let mut group = group;
+        loop {
+            if let Some(style) = self.styles.get(group) {
+                return *style;
+            }
+            match group.rsplit_once('.') {
+                Some((parent, _)) => group = parent,
+                None => return Style::default(),
+            }
+        }
+    }
+}
+
+struct Themes {
+    themes: Vec<Theme>, // the built-in ones first, then the ones that were loaded or defined
+    current: usize,
+    depth: Option<ColorDepth>, // detected on first use
+    generation: u64, // bumped on every switch, tells mos to redraw
+}
+
+static THEMES: Mutex<Themes> = Mutex::new(Themes {
+    themes: Vec::new(),
+    current: 0,
+    depth: None,
+    generation: 0,
+});
+
+impl Themes {
+    fn remove_owner(&mut self, owner: &str) {
+        let current = self.themes[self.current].name.clone();
+        let builtins = [Theme::default_dark(), Theme::default_light()];
+        self.themes = std::mem::take(&mut self.themes).into_iter()
+            .filter_map(|theme| match theme.owner.as_deref() {
+                Some(defined_by) if defined_by == owner => builtins.iter().find(|b| b.name == theme.name).cloned(),
+                _ => Some(theme),
+            })
+            .collect();
+        self.current = self.themes.iter().position(|t| t.name == current).unwrap_or(0);
+        self.generation += 1;
+    }
+}
+
+fn with_themes<T>(f: impl FnOnce(&mut Themes) -> T) -> Option<T> {
+    let mut themes = THEMES.lock().ok()?;
+    if themes.themes.is_empty() {
+        themes.themes = vec![Theme::default_dark(), Theme::default_light()];
+    }
+    Some(f(&mut themes))
+}
+
+// Where theme files are looked up by name
+pub fn themes_dir() -> std::path::PathBuf {
+    paths::config_dir().join("themes")
+}
+
+// The style of a group in the current theme, with colours the terminal can show
+pub fn style(group: &str) -> Style {
+    with_themes(|themes| {
+        let depth = *themes.depth.get_or_insert_with(ColorDepth::detect);
+        depth.adapt(themes.themes[themes.current].style(group))
+    }).unwrap_or_default()
+}
+
+pub fn get(name: &str) -> Option<Theme> {
+    with_themes(|themes| themes.themes.iter().find(|t| t.name == name).cloned()).flatten()
+}
+
+// Adds a theme or replaces the one with the same name, which redraws if it is the current one
+pub fn define(theme: Theme) {
+    with_themes(|themes| {
+        match themes.themes.iter().position(|t| t.name == theme.name) {
+            Some(index) => {
+                themes.themes[index] = theme;
+                if index == themes.current {
+                    themes.generation += 1;
+                }
+            }
+            None => themes.themes.push(theme),
+        }
+    });
+}
+
+// Drops the themes a plugin defined, a built-in one it replaced comes back. The current theme falls back to default
+pub fn remove_themes_by_owner(owner: &str) {
+    with_themes(|themes| themes.remove_owner(owner));
+}
+
+// Switches to a theme that was defined, or one from the themes directory
+pub fn set_current(name: &str) -> Result<(), String> {
+    if get(name).is_none() {
+        let file = ["toml", "json"].iter()
+            .map(|ext| themes_dir().join(format!("{}.{}", name, ext)))
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("No theme named {}", name))?;
+        let mut theme = Theme::load(&file)?;
+        theme.name = name.to_string();
+        define(theme);
+    }
+
+    with_themes(|themes| {
+        if let Some(index) = themes.themes.iter().position(|t| t.name == name) {
+            themes.current = index;
+            themes.generation += 1;
+        }
+    });
+    Ok(())
+}
+
+pub fn current_name() -> String {
+    with_themes(|themes| themes.themes[themes.current].name.clone()).unwrap_or_default()
+}
+
+// Defined themes and the files in the themes directory, sorted
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = with_themes(|themes| themes.themes.iter().map(|t| t.name.clone()).collect()).unwrap_or_default();
+
+    if let Ok(entries) = std::fs::read_dir(themes_dir()) {
+        for path in entries.flatten().map(|e| e.path()) {
+            if matches!(path.extension().and_then(|e| e.to_str()), Some("toml" | "json"))
+                && let Some(stem) = path.file_stem() {
+                names.push(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub fn color_depth() -> ColorDepth {
+    with_themes(|themes| *themes.depth.get_or_insert_with(ColorDepth::detect)).unwrap_or(ColorDepth::Ansi)
+}
+
+pub fn set_color_depth(depth: ColorDepth) {
+    with_themes(|themes| {
+        themes.depth = Some(depth);
+        themes.generation += 1;
+    });
+}
+
+pub fn generation() -> u64 {
+    with_themes(|themes| themes.generation).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owned(name: &str, owner: &str) -> Theme {
+        let mut theme = Theme::new(name);
+        theme.owner = Some(owner.to_string());
+        theme
+    }
+
+    fn themes(defined: Vec<Theme>, current: &str) -> Themes {
+        let mut themes = vec![Theme::default_dark(), Theme::default_light()];
+        themes.extend(defined);
+        let current = themes.iter().position(|t| t.name == current).unwrap();
+        Themes { themes, current, depth: None, generation: 0 }
+    }
+
+    fn names(themes: &Themes) -> Vec<(String, Option<String>)> {
+        themes.themes.iter().map(|t| (t.name.clone(), t.owner.clone())).collect()
+    }
+
+    #[test]
+    fn removes_the_themes_of_a_plugin() {
+        let mut themes = themes(vec![owned("dusk", "a"), owned("dawn", "b")], "dawn");
+        themes.remove_owner("a");
+        assert_eq!(names(&themes)[2..], [(String::from("dawn"), Some(String::from("b")))]);
+        assert_eq!(themes.themes[themes.current].name, "dawn");
+        assert_eq!(themes.generation, 1);
+    }
+
+    #[test]
+    fn falls_back_to_default_when_the_current_theme_goes() {
+        let mut themes = themes(vec![owned("dusk", "a")], "dusk");
+        themes.remove_owner("a");
+        assert_eq!(themes.themes.len(), 2);
+        assert_eq!(themes.themes[themes.current].name, "default");
+    }
+
+    #[test]
+    fn restores_a_builtin_theme_a_plugin_replaced() {
+        let mut themes = themes(vec![], "light");
+        themes.themes[1] = owned("light", "a");
+        themes.remove_owner("a");
+        assert_eq!(names(&themes), vec![(String::from("default"), None), (String::from("light"), None)]);
+        assert_eq!(themes.themes[themes.current].name, "light");
+        assert_eq!(themes.themes[1].style("ui.selection"), Theme::default_light().style("ui.selection"));
+    }
+}
\ No newline at end of file
@@ -1,7 +1,11 @@
-use crate::app::MosId;
-use crate::panel::panel::Panel;
+use crate::app::{MosId, CORE_ID};
+use crate::panel::panel::{Panel, PanelFault};
+use crate::system::panic_guard;
+use crate::system::logger;
 use ratatui::layout::{Constraint, Direction, Rect};
+use ratatui::widgets::Clear;
 use ratatui::Frame;
+use std::rc::Rc;
 
 pub enum Axis {
     Horizontal,
@@ -67,40 +71,122 @@ impl Layout {
         }
     }
 
+    // Every panel, also the ones in tabs that aren't shown
+    pub fn panels_mut(&mut self) -> Vec<&mut (dyn Panel + 'static)> {
+        match self {
+            Layout::Split { children, .. } => children.iter_mut().flat_map(|c| c.panels_mut()).collect(),
+            Layout::Tabs { tabs, .. } => tabs.iter_mut().map(|p| p.as_mut()).collect(),
+        }
+    }
+
+    // Only the visible panels matter for redrawing
+    pub fn is_dirty(&self) -> bool {
+        match self {
+            Layout::Split { children, .. } => children.iter().any(|c| c.is_dirty()),
+            Layout::Tabs { .. } => self.get_active_panel().is_some_and(|p| p.is_dirty()),
+        }
+    }
+
+    pub fn clear_dirty(&mut self) {
+        match self {
+            Layout::Split { children, .. } => children.iter_mut().for_each(|c| c.clear_dirty()),
+            Layout::Tabs { .. } => {
+                if let Some(panel) = self.get_active_panel_mut() {
+                    panel.clear_dirty();
+                }
+            }
+        }
+    }
+
+    pub fn close_panels_by_kind(&mut self, kinds: &[String]) {
+        match self {
+            Layout::Split { children, .. } => {
+                for child in children {
+                    child.close_panels_by_kind(kinds);
+                }
+            }
+            Layout::Tabs { tabs, active } => {
+                tabs.retain(|p| !kinds.iter().any(|k| k == p.kind()));
 
+                if !tabs.iter().any(|p| Some(p.id()) == *active) {
+                    *active = tabs.first().map(|p| p.id());
+                }
+            }
+        }
+    }
 
-    pub fn render(&self, frame: &mut Frame, area: Rect) {
+    pub fn close_panel(&mut self, panel_id: MosId) {
+        match self {
+            Layout::Split { children, .. } => {
+                for child in children {
+                    child.close_panel(panel_id);
+                }
+            }
+            Layout::Tabs { tabs, active } => {
+                tabs.retain(|p| p.id() != panel_id);
+
+                if *active == Some(panel_id) {
+                    *active = tabs.first().map(|p| p.id());
+                }
+            }
+        }
+    }
+
+    fn render_panel(panel: &dyn Panel, frame: &mut Frame, area: Rect, faults: &mut Vec<PanelFault>) {
+        if let Err(report) = panic_guard::guard(|| panel.render(frame, area)) {
+            faults.push(PanelFault {
+                panel_id: panel.id(),
+                kind: panel.kind().to_string(),
+                report,
+            });
+        }
+    }
+
+    fn split(axis: &Axis, count: usize, area: Rect) -> Rc<[Rect]> {
+        let constraints = vec![Constraint::Percentage(100 / count as u16); count];
+        let direction = match axis {
+            Axis::Horizontal => Direction::Horizontal,
+            Axis::Vertical => Direction::Vertical,
+        };
+        ratatui::layout::Layout::default()
+            .direction(direction)
+            .constraints(constraints)
+            .split(area)
+    }
+
+    fn shown_tab(tabs: &[Box<dyn Panel>], active: Option<MosId>) -> Option<&dyn Panel> {
+        tabs.iter().find(|panel| Some(panel.id()) == active).or(tabs.first()).map(|panel| panel.as_ref())
+    }
+
+    // Where each panel on screen was drawn, the same split as render
+    pub fn panel_areas(&self, area: Rect) -> Vec<(MosId, Rect)> {
+        match self {
+            Layout::Split { axis, children } => {
+                let chunks = Self::split(axis, children.len(), area);
+                children.iter().zip(chunks.iter()).flat_map(|(child, chunk)| child.panel_areas(*chunk)).collect()
+            }
+            Layout::Tabs { tabs, active } => {
+                Self::shown_tab(tabs, *active).map(|panel| vec![(panel.id(), area)]).unwrap_or_default()
+            }
+        }
+    }
+
+    // Panels that panic while rendering are collected in faults instead of taking down mos
+    pub fn render(&self, frame: &mut Frame, area: Rect, faults: &mut Vec<PanelFault>) {
         match self {
             Layout::Split { axis, children } => {
-                let constraints = vec![Constraint::Percentage(100 / children.len() as u16); children.len()];
-                let chunks = match axis {
-                    Axis::Horizontal => ratatui::layout::Layout::default()
-                        .direction(Direction::Horizontal)
-                        .constraints(constraints)
-                        .split(area),
-                    Axis::Vertical => ratatui::layout::Layout::default()
-                        .direction(Direction::Vertical)
-                        .constraints(constraints)
-                        .split(area),
-                };
+                let chunks = Self::split(axis, children.len(), area);
                 for (child, chunk) in children.iter().zip(chunks.iter()) {
-                    child.render(frame, *chunk);
+                    child.render(frame, *chunk, faults);
                 }
             }
             Layout::Tabs { tabs, active } => {
                 //println!("Rendering Tabs layout with {} tabs, active tab id: {:?}", tabs.len(), active);
-                if let Some(active_id) = active.as_ref() {
-                    if let Some(active_panel) = tabs.iter().find(|panel| panel.id() == *active_id) {
-                        active_panel.render(frame, area);
-                        return;
-                    }
-                }
-
-                if let Some(first) = tabs.first() {
-                    first.render(frame, area);
+                if let Some(panel) = Self::shown_tab(tabs, *active) {
+                    Self::render_panel(panel, frame, area, faults);
                 } else {
                     // No tabs to render, maybe render a placeholder or do nothing
-                    println!("No tabs to render in Tabs layout");
+                    logger::debug(CORE_ID, "No tabs to render in Tabs layout");
                 }
             }
         }
@@ -123,7 +209,80 @@ pub enum Offset {
     Relative(f32, f32, f32, f32), // left, top, right, bottom as percentage of parent size
 }
 
+impl Anchor {
+    pub fn offset(&self) -> &Offset {
+        match self {
+            Anchor::Top(o) | Anchor::Bottom(o) | Anchor::Left(o) | Anchor::Right(o)
+            | Anchor::TopLeft(o) | Anchor::TopRight(o) | Anchor::BottomLeft(o) | Anchor::BottomRight(o) => o,
+        }
+    }
+
+    // Places a box of the given size at the anchor, moved inwards by the offset.
+    // Without a size the panel fills the parent minus the offsets.
+    pub fn area(&self, parent: Rect, size: Option<(u16, u16)>) -> Rect {
+        let (left, top, right, bottom) = self.offset().resolve(parent);
+        let (px, py, pw, ph) = (parent.x as i32, parent.y as i32, parent.width as i32, parent.height as i32);
+
+        let (width, height) = match size {
+            Some((w, h)) => (w as i32, h as i32),
+            None => (pw - left - right, ph - top - bottom),
+        };
+        let (width, height) = (width.clamp(0, pw), height.clamp(0, ph));
+
+        let center_x = px + (pw - width) / 2;
+        let center_y = py + (ph - height) / 2;
+        let (start_x, end_x) = (px + left, px + pw - right - width);
+        let (start_y, end_y) = (py + top, py + ph - bottom - height);
+
+        let (x, y) = match self {
+            Anchor::Top(_) => (center_x, start_y),
+            Anchor::Bottom(_) => (center_x, end_y),
+            Anchor::Left(_) => (start_x, center_y),
+            Anchor::Right(_) => (end_x, center_y),
+            Anchor::TopLeft(_) => (start_x, start_y),
+            Anchor::TopRight(_) => (end_x, start_y),
+            Anchor::BottomLeft(_) => (start_x, end_y),
+            Anchor::BottomRight(_) => (end_x, end_y),
+        };
+
+        let x = x.clamp(px, px + pw - width);
+        let y = y.clamp(py, py + ph - height);
+        Rect::new(x as u16, y as u16, width as u16, height as u16)
+    }
+}
+
+impl Offset {
+    // The offsets in cells for the given parent area
+    fn resolve(&self, parent: Rect) -> (i32, i32, i32, i32) {
+        match *self {
+            Offset::Absolute(left, top, right, bottom) => (left, top, right, bottom),
+            Offset::Relative(left, top, right, bottom) => {
+                let w = parent.width as f32 / 100.0;
+                let h = parent.height as f32 / 100.0;
+                ((left * w) as i32, (top * h) as i32, (right * w) as i32, (bottom * h) as i32)
+            }
+        }
+    }
+}
+
 pub struct FloatingPanel {
     pub panel: Box<dyn Panel>,
     pub anchor: Anchor,
-}
\ No newline at end of file
+}
+
+impl FloatingPanel {
+    pub fn area(&self, parent: Rect, size: Option<(u16, u16)>) -> Rect {
+        self.anchor.area(parent, size)
+    }
+
+    // Clears what is below the panel before drawing it on top
+    pub fn render(&self, frame: &mut Frame, parent: Rect, size: Option<(u16, u16)>, faults: &mut Vec<PanelFault>) {
+        let area = self.area(parent, size);
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        frame.render_widget(Clear, area);
+        Layout::render_panel(self.panel.as_ref(), frame, area, faults);
+    }
+}
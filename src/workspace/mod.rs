@@ -1,2 +1,3 @@
 pub mod workspace;
-pub mod layout;
\ No newline at end of file
+pub mod layout;
+pub mod status_line;
\ No newline at end of file
@@ -0,0 +1,96 @@
+use crate::panel::panel::PanelStatus;
+use crate::system::status::{self, Align};
+use crate::system::theme;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use unicode_width::UnicodeWidthStr;
+use std::cell::Cell;
+
+const SEPARATOR: &str = "  ";
+
+// The bar below the layout, built-in segments for the active panel plus the ones plugins set through system::status
+pub struct StatusLine {
+    seen_generation: Cell<u64>, // status::generation() at the last render
+}
+
+impl StatusLine {
+    pub fn new() -> Self {
+        Self {
+            seen_generation: Cell::new(0),
+        }
+    }
+
+    // Changes to the active panel already redraw, this only tracks the plugin segments
+    pub fn is_dirty(&self) -> bool {
+        status::generation() != self.seen_generation.get()
+    }
+
+    fn left_spans(panel: &PanelStatus, plugin_segments: &[String]) -> Vec<Span<'static>> {
+        let mut spans = Vec::new();
+
+        if let Some(mode) = &panel.mode {
+            spans.push(Span::styled(format!(" {} ", mode.to_uppercase()), theme::style(&format!("ui.statusline.mode.{}", mode))));
+            spans.push(Span::raw(" "));
+        }
+
+        if let Some(file) = &panel.file {
+            let mut text = file.clone();
+            if panel.modified {
+                text.push_str(" [+]");
+            }
+            if panel.read_only {
+                text.push_str(" [RO]");
+            }
+            spans.push(Span::raw(text));
+        }
+
+        for text in plugin_segments {
+            spans.push(Span::raw(SEPARATOR));
+            spans.push(Span::raw(text.clone()));
+        }
+
+        spans
+    }
+
+    fn right_items(panel: &PanelStatus, plugin_segments: Vec<String>) -> Vec<String> {
+        let mut items = plugin_segments;
+
+        if panel.cursors > 1 {
+            items.push(format!("{} cursors", panel.cursors));
+        }
+        if let Some((line, column)) = panel.position {
+            items.push(format!("{}:{}", line + 1, column + 1));
+        }
+        items.extend(panel.encoding.clone());
+        items.extend(panel.line_ending.clone());
+
+        items
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, panel: &PanelStatus) {
+        self.seen_generation.set(status::generation());
+
+        let segments = status::segments();
+        let plugin_texts = |align: Align| -> Vec<String> {
+            segments.iter()
+                .filter(|s| s.align == align && !s.text.is_empty())
+                .map(|s| s.text.clone())
+                .collect()
+        };
+
+        let left = Line::from(Self::left_spans(panel, &plugin_texts(Align::Left)));
+        let right = format!("{} ", Self::right_items(panel, plugin_texts(Align::Right)).join(SEPARATOR));
+
+        // The right side is kept whole, the left side gets cut off when there isn't enough room
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(0), Constraint::Length(right.width() as u16)])
+            .split(area);
+
+        let style = theme::style("ui.statusline");
+        frame.render_widget(Paragraph::new(left).style(style), chunks[0]);
+        frame.render_widget(Paragraph::new(right).style(style), chunks[1]);
+    }
+}
\ No newline at end of file
@@ -1,11 +1,23 @@
-use crate::app::MosId;
-use crate::panel::panel::Panel;
+use crate::app::{MosId, CORE_ID};
+use crate::event::event::{EditorEvent, Event};
+use crate::panel::panel::{Panel, PanelFault, PanelStatus};
+use crate::system::event_bus;
+use crate::system::panic_guard;
+use crate::system::logger;
 use crate::workspace::layout::{FloatingPanel, Layout};
+use crate::workspace::status_line::StatusLine;
+use crossterm::event::{MouseEvent, MouseEventKind};
+use ratatui::layout::{Constraint, Direction, Rect};
 use ratatui::Frame;
+use std::cell::Cell;
 
 pub struct Workspace {
     floating_panel: Option<FloatingPanel>,
     layout: Layout,
+    status_line: StatusLine,
+    dirty: bool, // the layout itself changed, e.g. a panel was added or closed
+    area: Cell<Rect>, // what the layout had at the last render, without the status line
+    mouse_capture: Option<MosId>, // the panel a button went down in, it gets the drag and the release too
 }
 
 impl Workspace {
@@ -16,23 +28,76 @@ impl Workspace {
                 active: None,
             },
             floating_panel: None,
+            status_line: StatusLine::new(),
+            dirty: true,
+            area: Cell::new(Rect::default()),
+            mouse_capture: None,
         }
     }
 
     pub fn add_panel(&mut self, panel: Box<dyn Panel>) {
+        self.dirty = true;
         match &mut self.layout {
             Layout::Tabs { tabs, active } => {
                 let panel_id = panel.id();
+                let title = panel.title().to_string();
                 tabs.push(panel);
                 *active = Some(panel_id); // Set the newly added panel as active
+                event_bus::emit(Event::Editor(EditorEvent::PanelFocused { panel: panel_id, title }));
             }
             _ => {
-                eprintln!("Currently only Tabs layout is supported for adding panels");
+                logger::warn(CORE_ID, "Currently only Tabs layout is supported for adding panels");
             }
         }
     }
 
+    // Close the live panels of the given kinds, e.g. when the plugin providing them is disabled
+    pub fn close_panels_by_kind(&mut self, kinds: &[String]) {
+        self.dirty = true;
+        self.layout.close_panels_by_kind(kinds);
+
+        if let Some(floating) = &self.floating_panel
+            && kinds.iter().any(|k| k == floating.panel.kind()) {
+            self.floating_panel = None;
+        }
+    }
+
+    pub fn close_panel(&mut self, panel_id: MosId) {
+        self.dirty = true;
+        self.layout.close_panel(panel_id);
+
+        if self.floating_panel.as_ref().is_some_and(|f| f.panel.id() == panel_id) {
+            self.floating_panel = None;
+        }
+    }
+
+    // Focus the next (delta 1) or previous (delta -1) tab, wrapping around
+    pub fn cycle_panels(&mut self, delta: isize) {
+        let next = match &self.layout {
+            Layout::Tabs { tabs, active } if !tabs.is_empty() => {
+                let current = tabs.iter().position(|p| Some(p.id()) == *active).unwrap_or(0);
+                let index = (current as isize + delta).rem_euclid(tabs.len() as isize) as usize;
+                Some(tabs[index].id())
+            }
+            _ => None,
+        };
+
+        if let Some(panel_id) = next {
+            self.focus_panel(panel_id);
+        }
+    }
+
+    pub fn focus_panel(&mut self, panel_id: MosId) {
+        if let Layout::Tabs { tabs, active } = &mut self.layout
+            && let Some(panel) = tabs.iter().find(|p| p.id() == panel_id) {
+            *active = Some(panel_id);
+            self.dirty = true;
+            event_bus::emit(Event::Editor(EditorEvent::PanelFocused { panel: panel_id, title: panel.title().to_string() }));
+        }
+    }
+
     pub fn set_floating(&mut self, floating_panel: Option<FloatingPanel>) {
+        self.dirty = true;
         self.floating_panel = floating_panel;
     }
 
@@ -44,14 +109,92 @@ impl Workspace {
         self.layout.get_active_panel_mut()
     }
 
+    // The panels in the layout and the floating one, e.g. to tell them all the terminal was resized
+    pub fn panels_mut(&mut self) -> Vec<&mut (dyn Panel + 'static)> {
+        let mut panels = self.layout.panels_mut();
+        if let Some(floating) = self.floating_panel.as_mut() {
+            panels.push(floating.panel.as_mut());
+        }
+        panels
+    }
+
+    // The panel a mouse event is for and the event moved into its own coordinates. Only the floating panel
+    // gets the mouse while it has the focus, a drag outside the panel it started in still goes to that panel
+    pub fn mouse_target(&mut self, mouse: MouseEvent, floating: bool) -> Option<(&mut (dyn Panel + 'static), MouseEvent)> {
+        let areas = if floating {
+            let floating = self.floating_panel.as_ref()?;
+            vec![(floating.panel.id(), floating.area(self.area.get(), None))]
+        } else {
+            self.layout.panel_areas(self.area.get())
+        };
+        let under = areas.iter()
+            .find(|(_, area)| area.intersects(Rect::new(mouse.column, mouse.row, 1, 1)))
+            .map(|(id, _)| *id);
+
+        let target = match mouse.kind {
+            MouseEventKind::Down(_) => {
+                self.mouse_capture = under;
+                under
+            }
+            MouseEventKind::Drag(_) => self.mouse_capture.or(under),
+            MouseEventKind::Up(_) => self.mouse_capture.take().or(under),
+            _ => under,
+        }?;
+        let (_, area) = areas.into_iter().find(|(id, _)| *id == target)?;
+        let local = MouseEvent {
+            column: mouse.column.saturating_sub(area.x),
+            row: mouse.row.saturating_sub(area.y),
+            ..mouse
+        };
+
+        let panel = self.panels_mut().into_iter().find(|panel| panel.id() == target)?;
+        Some((panel, local))
+    }
+
     pub fn get_floating(&mut self) -> &mut Option<FloatingPanel> {
         &mut self.floating_panel
     }
 
-    pub fn render(&self, frame: &mut Frame) {
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+            || self.layout.is_dirty()
+            || self.status_line.is_dirty()
+            || self.floating_panel.as_ref().is_some_and(|f| f.panel.is_dirty())
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+        self.layout.clear_dirty();
+        if let Some(floating) = self.floating_panel.as_mut() {
+            floating.panel.clear_dirty();
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame) -> Vec<PanelFault> {
         // chunks?
 
-        let area = frame.size();
-        self.layout.render(frame, area);
+        let mut faults = Vec::new();
+        let chunks = ratatui::layout::Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)])
+            .split(frame.size());
+        let area = chunks[0];
+        self.area.set(area);
+        self.layout.render(frame, area, &mut faults);
+
+        let panel_status = match self.get_active_panel() {
+            Some(panel) => panic_guard::guard(|| panel.status()).unwrap_or_else(|report| {
+                faults.push(PanelFault { panel_id: panel.id(), kind: panel.kind().to_string(), report });
+                PanelStatus::default()
+            }),
+            None => PanelStatus::default(),
+        };
+        self.status_line.render(frame, chunks[1], &panel_status);
+
+        if let Some(floating) = &self.floating_panel {
+            floating.render(frame, area, None, &mut faults);
+        }
+
+        faults
     }
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+mos.subscribe("command", { command = "save" }, function(event)
+end)
+
+return {
+    name = "Test",
+    version = "0.1.0",
+    description = "Plugin used for testing the lua api",
+    dependencies = { mos_builtin_editor = "0.1" },
+}